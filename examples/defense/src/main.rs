@@ -2,7 +2,7 @@
 
 use ucqcf_ciem::CIEM;
 use ucqcf_core::capability::CryptographicCapability;
-use ucqcf_core::profile::{Domain, SecurityProfile};
+use ucqcf_core::preset::ProfileRegistry;
 // Import the mock hardware components AND THEIR TRAITS.
 use ucqcf_mock_hw::clock::{ClockSource, MockAtomicClock, MockQuantumClock};
 use ucqcf_mock_hw::rng::{MockQRNG, MockTRNG, RngSource};
@@ -29,21 +29,23 @@ fn main() {
     let clock_source = Box::new(MockAtomicClock);
     let ciem = CIEM::new(aggregator, clock_source).unwrap();
 
-    // 3. HUMAN/AI/APP VIEW: Define security intent via a profile.
-    let profile = SecurityProfile {
-        domain: Domain::Defense,
-        quantum_resistant: true,
-        require_atomic_time: true,
-    };
+    // 3. HUMAN/AI/APP VIEW: Define security intent via a profile, loaded from configuration.
+    let mut presets = ProfileRegistry::builtin();
+    presets.load_file(concat!(env!("CARGO_MANIFEST_DIR"), "/profiles.toml")).unwrap();
+    let profile = presets.get("mission-planning").unwrap().clone();
     println!("REQUEST: Encrypt capability with profile: {:?}\n", profile);
 
-    // 4. CAPABILITY REQUEST: Request an encryption capability.
+    // 4. KEY GENERATION: Generate a key in the CIEM for the AEAD the profile calls for.
+    let key = ciem.generate_key(ciem.check_profile(&profile).unwrap(), &profile).unwrap();
+    println!("CIEM: Generated key {}.\n", key.id());
+
+    // 5. CAPABILITY REQUEST: Request an encryption capability under that key.
     println!("CIEM: Authorizing request...");
     // The capability does not need to be mutable due to the interior mutability pattern.
-    let encrypt_capability = ciem.request_encrypt_capability(&profile).unwrap();
-    println!("CIEM: Request authorized. Capability granted.\n");
+    let encrypt_capability = ciem.request_encrypt_capability(&key, &profile).unwrap();
+    println!("CIEM: Request authorized. Capability granted ({}).\n", encrypt_capability.algorithm());
 
-    // 5. USE CAPABILITY: Execute the cryptographic operation.
+    // 6. USE CAPABILITY: Execute the cryptographic operation.
     let plaintext = b"Top secret mission objectives.";
     println!(
         "CAPABILITY: Executing encryption for: \"{}\"",
@@ -55,8 +57,8 @@ fn main() {
     println!("CAPABILITY: Execution complete.");
     println!("RESULT: Ciphertext with nonce -> {:?}\n", ciphertext_with_nonce);
 
-    // 6. DECRYPTION: Request a decryption capability and verify the result.
-    let decrypt_capability = ciem.request_decrypt_capability(&profile).unwrap();
+    // 7. DECRYPTION: Request a decryption capability and verify the result.
+    let decrypt_capability = ciem.request_decrypt_capability(&key, &profile).unwrap();
     let decrypted_plaintext = decrypt_capability.execute(&ciphertext_with_nonce).unwrap();
     println!("DECRYPTION: Decrypted plaintext: \"{}\"", std::str::from_utf8(&decrypted_plaintext).unwrap());
     assert_eq!(plaintext, decrypted_plaintext.as_slice());
@@ -64,15 +66,17 @@ fn main() {
 
     // --- DEMONSTRATE SECURITY GUARANTEES ---
 
-    // 7. TAMPER EVENT
+    // 8. TAMPER EVENT
     println!("--- Simulating Tamper Event ---");
-    // This second CIEM uses a different entropy aggregator configuration.
+    // This second CIEM uses a different entropy aggregator configuration, still
+    // with the two sources classified data requires.
     let tampered_key_material = [99; 32];
     let tampered_hmac_key = ring::hmac::Key::new(ring::hmac::HMAC_SHA256, &tampered_key_material);
-    let tampered_aggregator = ucqcf_ciem::entropy::EntropyAggregator::new(Box::new(MockTRNG {}), vec![], tampered_hmac_key);
+    let tampered_aggregator = ucqcf_ciem::entropy::EntropyAggregator::new(Box::new(MockTRNG {}), vec![Box::new(MockQRNG {})], tampered_hmac_key);
     let tampered_clock: Box<dyn ClockSource> = Box::new(MockQuantumClock {});
     let tampered_ciem = CIEM::new(tampered_aggregator, tampered_clock).unwrap();
-    let second_capability = tampered_ciem.request_encrypt_capability(&profile).unwrap();
+    let tampered_key = tampered_ciem.generate_key(tampered_ciem.check_profile(&profile).unwrap(), &profile).unwrap();
+    let second_capability = tampered_ciem.request_encrypt_capability(&tampered_key, &profile).unwrap();
 
     // Inject a tamper event (e.g., from a physical sensor).
     println!("TAMPER: Fault detected! Injecting tamper signal into CIEM.");
@@ -0,0 +1,447 @@
+// policy_modules/ucqcf_policy/src/combinators.rs
+
+//! Combinators that compose several `PolicyInterface`s into one, so a site-wide
+//! baseline can be stacked with per-domain and per-tenant layers.
+//!
+//! Layers are always evaluated in order, and an error from any layer fails the
+//! whole evaluation. Whenever several layers permit a request, their
+//! obligations are merged to the most restrictive value of each, and their
+//! reasons are joined with `"; "` in layer order.
+//!
+//! `explain` reports a step for the combinator itself, then a step for each
+//! layer consulted followed by that layer's own trace, one level deeper.
+//!
+//! `AllOf` requires every layer to permit, so a scoped layer that does not
+//! apply denies. To stack a baseline with scoped layers that only speak for
+//! their own domain or tenant, use `DenyOverrides`:
+//!
+//! ```ignore
+//! let policy = DenyOverrides::new(vec![
+//!     Box::new(RulePolicy::from_file("site.rules")?),
+//!     Box::new(Scoped::domain(Domain::Defense, Box::new(RulePolicy::from_file("defense.rules")?))),
+//!     Box::new(Scoped::environment("tenant", "acme", Box::new(RulePolicy::from_file("acme.rules")?))),
+//! ]);
+//! let engine = CoreEngine::new(Box::new(policy));
+//! ```
+
+use ucqcf_core::CryptoError;
+use ucqcf_core::interfaces::{
+    Obligations, PolicyContext, PolicyDecision, PolicyEvaluation, PolicyInterface, TraceStep,
+};
+use ucqcf_core::profile::Domain;
+
+/// A boxed policy layer.
+pub type Layer<'a> = Box<dyn PolicyInterface + 'a>;
+
+fn explain_all(
+    layers: &[Layer<'_>],
+    context: &PolicyContext,
+) -> Result<Vec<PolicyEvaluation>, CryptoError> {
+    layers.iter().map(|layer| layer.explain(context)).collect()
+}
+
+/// Builds the trace of a combinator from the evaluations of the layers it consulted.
+fn compose(
+    source: &str,
+    decision: PolicyDecision,
+    layers: Vec<PolicyEvaluation>,
+) -> PolicyEvaluation {
+    let mut trace = vec![TraceStep::from_decision(source, &decision)];
+    for (index, layer) in layers.into_iter().enumerate() {
+        let mut step = TraceStep::from_decision(&format!("layer {}", index), &layer.decision);
+        step.depth = 1;
+        trace.push(step);
+        trace.extend(layer.trace.into_iter().map(|mut step| {
+            step.depth += 2;
+            step
+        }));
+    }
+    PolicyEvaluation { decision, trace }
+}
+
+fn decisions(layers: &[PolicyEvaluation]) -> Vec<PolicyDecision> {
+    layers.iter().map(|layer| layer.decision.clone()).collect()
+}
+
+/// Merges the decisions of permitting layers into one permit. Conflicting
+/// obligations turn the result into a denial.
+fn merge_permits<'d>(permits: impl IntoIterator<Item = &'d PolicyDecision>) -> PolicyDecision {
+    let mut obligations = Obligations::default();
+    let mut reasons = Vec::new();
+    for permit in permits {
+        match obligations.merge(&permit.obligations) {
+            Some(merged) => obligations = merged,
+            None => return PolicyDecision::deny("policy layers require conflicting AAD"),
+        }
+        reasons.extend(permit.reason.clone());
+    }
+    let mut decision = PolicyDecision::permit().with_obligations(obligations);
+    if !reasons.is_empty() {
+        decision.reason = Some(reasons.join("; "));
+    }
+    decision
+}
+
+fn is_permit(decision: &PolicyDecision) -> bool {
+    decision.applicable && decision.allow
+}
+
+fn is_deny(decision: &PolicyDecision) -> bool {
+    decision.applicable && !decision.allow
+}
+
+/// Permits only if every layer permits. A layer that denies or is not
+/// applicable denies the request.
+pub struct AllOf<'a> {
+    layers: Vec<Layer<'a>>,
+}
+
+impl<'a> AllOf<'a> {
+    pub fn new(layers: Vec<Layer<'a>>) -> Self {
+        Self { layers }
+    }
+}
+
+impl PolicyInterface for AllOf<'_> {
+    fn evaluate(&self, context: &PolicyContext) -> Result<PolicyDecision, CryptoError> {
+        self.explain(context).map(|evaluation| evaluation.decision)
+    }
+
+    fn explain(&self, context: &PolicyContext) -> Result<PolicyEvaluation, CryptoError> {
+        let layers = explain_all(&self.layers, context)?;
+        let decisions = decisions(&layers);
+        let decision = if decisions.is_empty() {
+            PolicyDecision::not_applicable("no policy layers")
+        } else if let Some(refusal) = decisions.iter().find(|d| !is_permit(d)) {
+            let reason = refusal
+                .reason
+                .as_deref()
+                .unwrap_or("a policy layer refused");
+            PolicyDecision::deny(reason)
+        } else {
+            merge_permits(&decisions)
+        };
+        Ok(compose("AllOf", decision, layers))
+    }
+}
+
+/// Permits if any layer permits, even if others deny (permit-overrides).
+/// Denies if no layer permits but some layer denies; otherwise not applicable.
+pub struct AnyOf<'a> {
+    layers: Vec<Layer<'a>>,
+}
+
+impl<'a> AnyOf<'a> {
+    pub fn new(layers: Vec<Layer<'a>>) -> Self {
+        Self { layers }
+    }
+}
+
+impl PolicyInterface for AnyOf<'_> {
+    fn evaluate(&self, context: &PolicyContext) -> Result<PolicyDecision, CryptoError> {
+        self.explain(context).map(|evaluation| evaluation.decision)
+    }
+
+    fn explain(&self, context: &PolicyContext) -> Result<PolicyEvaluation, CryptoError> {
+        let layers = explain_all(&self.layers, context)?;
+        let decisions = decisions(&layers);
+        let decision = if decisions.iter().any(is_permit) {
+            merge_permits(decisions.iter().filter(|d| is_permit(d)))
+        } else {
+            decisions
+                .into_iter()
+                .find(is_deny)
+                .unwrap_or_else(|| PolicyDecision::not_applicable("no policy layer applies"))
+        };
+        Ok(compose("AnyOf", decision, layers))
+    }
+}
+
+/// The first layer with an applicable decision decides; later layers are not consulted.
+pub struct FirstApplicable<'a> {
+    layers: Vec<Layer<'a>>,
+}
+
+impl<'a> FirstApplicable<'a> {
+    pub fn new(layers: Vec<Layer<'a>>) -> Self {
+        Self { layers }
+    }
+}
+
+impl PolicyInterface for FirstApplicable<'_> {
+    fn evaluate(&self, context: &PolicyContext) -> Result<PolicyDecision, CryptoError> {
+        self.explain(context).map(|evaluation| evaluation.decision)
+    }
+
+    fn explain(&self, context: &PolicyContext) -> Result<PolicyEvaluation, CryptoError> {
+        let mut consulted = Vec::new();
+        for layer in &self.layers {
+            let evaluation = layer.explain(context)?;
+            let applicable = evaluation.decision.applicable;
+            consulted.push(evaluation);
+            if applicable {
+                let decision = consulted[consulted.len() - 1].decision.clone();
+                return Ok(compose("FirstApplicable", decision, consulted));
+            }
+        }
+        let decision = PolicyDecision::not_applicable("no policy layer applies");
+        Ok(compose("FirstApplicable", decision, consulted))
+    }
+}
+
+/// Denies if any layer denies; otherwise permits if any layer permits;
+/// otherwise not applicable.
+pub struct DenyOverrides<'a> {
+    layers: Vec<Layer<'a>>,
+}
+
+impl<'a> DenyOverrides<'a> {
+    pub fn new(layers: Vec<Layer<'a>>) -> Self {
+        Self { layers }
+    }
+}
+
+impl PolicyInterface for DenyOverrides<'_> {
+    fn evaluate(&self, context: &PolicyContext) -> Result<PolicyDecision, CryptoError> {
+        self.explain(context).map(|evaluation| evaluation.decision)
+    }
+
+    fn explain(&self, context: &PolicyContext) -> Result<PolicyEvaluation, CryptoError> {
+        let layers = explain_all(&self.layers, context)?;
+        let decisions = decisions(&layers);
+        let decision = if let Some(deny) = decisions.iter().find(|d| is_deny(d)) {
+            deny.clone()
+        } else if decisions.iter().any(is_permit) {
+            merge_permits(decisions.iter().filter(|d| is_permit(d)))
+        } else {
+            PolicyDecision::not_applicable("no policy layer applies")
+        };
+        Ok(compose("DenyOverrides", decision, layers))
+    }
+}
+
+/// Applies a policy only to requests matching a predicate; other requests get
+/// a not-applicable decision. Useful for per-domain and per-tenant layers.
+pub struct Scoped<'a> {
+    scope: Box<dyn Fn(&PolicyContext) -> bool + 'a>,
+    policy: Layer<'a>,
+}
+
+impl<'a> Scoped<'a> {
+    /// Scopes `policy` to requests for which `scope` returns `true`.
+    pub fn new(scope: impl Fn(&PolicyContext) -> bool + 'a, policy: Layer<'a>) -> Self {
+        Self {
+            scope: Box::new(scope),
+            policy,
+        }
+    }
+
+    /// Scopes `policy` to requests made under a profile for `domain`.
+    pub fn domain(domain: Domain, policy: Layer<'a>) -> Self {
+        Self::new(move |context| context.profile.domain == domain, policy)
+    }
+
+    /// Scopes `policy` to requests where environment attribute `name` equals `value`,
+    /// e.g. a `tenant` attribute set by the hosting service.
+    pub fn environment(name: &str, value: &str, policy: Layer<'a>) -> Self {
+        let (name, value) = (name.to_string(), value.to_string());
+        Self::new(
+            move |context| context.environment.get(&name) == Some(&value),
+            policy,
+        )
+    }
+}
+
+impl PolicyInterface for Scoped<'_> {
+    fn evaluate(&self, context: &PolicyContext) -> Result<PolicyDecision, CryptoError> {
+        if (self.scope)(context) {
+            self.policy.evaluate(context)
+        } else {
+            Ok(PolicyDecision::not_applicable(
+                "request is outside the policy's scope",
+            ))
+        }
+    }
+
+    fn explain(&self, context: &PolicyContext) -> Result<PolicyEvaluation, CryptoError> {
+        if (self.scope)(context) {
+            let evaluation = self.policy.explain(context)?;
+            Ok(compose(
+                "Scoped",
+                evaluation.decision.clone(),
+                vec![evaluation],
+            ))
+        } else {
+            let decision = PolicyDecision::not_applicable("request is outside the policy's scope");
+            Ok(compose("Scoped", decision, Vec::new()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RulePolicy;
+    use std::collections::BTreeMap;
+    use ucqcf_core::interfaces::AuditLevel;
+    use ucqcf_core::profile::SecurityProfile;
+    use ucqcf_core::request::Operation;
+
+    fn context(domain: Domain) -> PolicyContext {
+        PolicyContext {
+            principal: "alice".to_string(),
+            operation: Operation::Encrypt,
+            algorithm: None,
+            key_attributes: BTreeMap::new(),
+            profile: SecurityProfile::new(domain),
+            epoch: 0,
+            environment: BTreeMap::new(),
+        }
+    }
+
+    fn rules(source: &str) -> Layer<'static> {
+        Box::new(RulePolicy::parse(source).unwrap())
+    }
+
+    fn permit() -> Layer<'static> {
+        rules("permit \"all\" with reason = \"allowed\";")
+    }
+
+    fn deny() -> Layer<'static> {
+        rules("deny \"none\" with reason = \"refused\";")
+    }
+
+    fn not_applicable() -> Layer<'static> {
+        rules("permit \"operators\" when principal == bob;")
+    }
+
+    /// A layer that fails to evaluate, to show it was never consulted.
+    struct Failing;
+
+    impl PolicyInterface for Failing {
+        fn evaluate(&self, _context: &PolicyContext) -> Result<PolicyDecision, CryptoError> {
+            Err(CryptoError::InvalidState)
+        }
+    }
+
+    #[test]
+    fn all_of_refuses_unless_every_layer_permits() {
+        let context = context(Domain::Defense);
+        let policy = AllOf::new(vec![permit(), permit()]);
+        assert!(policy.evaluate(&context).unwrap().allow);
+
+        let policy = AllOf::new(vec![permit(), not_applicable()]);
+        let decision = policy.evaluate(&context).unwrap();
+        assert!(!decision.allow);
+        assert!(decision.applicable);
+
+        let policy = AllOf::new(vec![permit(), deny()]);
+        let decision = policy.evaluate(&context).unwrap();
+        assert_eq!(
+            (decision.allow, decision.reason.as_deref()),
+            (false, Some("refused"))
+        );
+    }
+
+    #[test]
+    fn any_of_lets_a_permit_override_denials() {
+        let context = context(Domain::Defense);
+        let policy = AnyOf::new(vec![deny(), not_applicable(), permit()]);
+        assert!(policy.evaluate(&context).unwrap().allow);
+
+        let policy = AnyOf::new(vec![not_applicable(), deny()]);
+        let decision = policy.evaluate(&context).unwrap();
+        assert!(!decision.allow);
+        assert!(decision.applicable);
+
+        let policy = AnyOf::new(vec![not_applicable()]);
+        assert!(!policy.evaluate(&context).unwrap().applicable);
+    }
+
+    #[test]
+    fn deny_overrides_lets_a_denial_win() {
+        let context = context(Domain::Defense);
+        let policy = DenyOverrides::new(vec![permit(), deny(), permit()]);
+        let decision = policy.evaluate(&context).unwrap();
+        assert_eq!(
+            (decision.allow, decision.reason.as_deref()),
+            (false, Some("refused"))
+        );
+
+        // Layers that do not apply are ignored.
+        let policy = DenyOverrides::new(vec![not_applicable(), permit()]);
+        assert!(policy.evaluate(&context).unwrap().allow);
+        let policy = DenyOverrides::new(vec![not_applicable()]);
+        assert!(!policy.evaluate(&context).unwrap().applicable);
+    }
+
+    #[test]
+    fn first_applicable_stops_at_the_first_decisive_layer() {
+        let context = context(Domain::Defense);
+        let policy = FirstApplicable::new(vec![not_applicable(), deny(), Box::new(Failing)]);
+        let evaluation = policy.explain(&context).unwrap();
+        assert_eq!(evaluation.decision.reason.as_deref(), Some("refused"));
+        let layers: Vec<&str> = evaluation
+            .trace
+            .iter()
+            .filter(|step| step.depth == 1)
+            .map(|step| step.source.as_str())
+            .collect();
+        assert_eq!(layers, ["layer 0", "layer 1"]);
+
+        let policy = FirstApplicable::new(vec![not_applicable(), Box::new(Failing)]);
+        assert_eq!(policy.evaluate(&context), Err(CryptoError::InvalidState));
+    }
+
+    #[test]
+    fn scoped_policies_do_not_apply_outside_their_scope() {
+        let policy = Scoped::domain(Domain::Telecom, permit());
+        assert!(policy.evaluate(&context(Domain::Telecom)).unwrap().allow);
+        let evaluation = policy.explain(&context(Domain::Defense)).unwrap();
+        assert!(!evaluation.decision.applicable);
+        assert_eq!(evaluation.trace.len(), 1);
+
+        let policy = Scoped::environment("tenant", "acme", permit());
+        let mut context = context(Domain::Defense);
+        assert!(!policy.evaluate(&context).unwrap().applicable);
+        context
+            .environment
+            .insert("tenant".to_string(), "acme".to_string());
+        assert!(policy.evaluate(&context).unwrap().allow);
+    }
+
+    #[test]
+    fn obligations_merge_to_the_strictest_in_any_order() {
+        let context = context(Domain::Defense);
+        let strict = "permit \"a\" with max_uses = 5, expires_in = 500, reason = a;";
+        let audited = "permit \"b\" with max_uses = 10, expires_in = 100, audit = detailed, required_aad = x;";
+        let expected = Obligations {
+            max_uses: Some(5),
+            expires_in: Some(100),
+            audit_level: AuditLevel::Detailed,
+            required_aad: Some(b"x".to_vec()),
+            key_max_invocations: None,
+            key_max_bytes: None,
+        };
+        let orders = [[strict, audited], [audited, strict]];
+        for [first, second] in orders {
+            let policies: [Layer<'_>; 3] = [
+                Box::new(AllOf::new(vec![rules(first), rules(second)])),
+                Box::new(AnyOf::new(vec![rules(first), rules(second)])),
+                Box::new(DenyOverrides::new(vec![rules(first), rules(second)])),
+            ];
+            for policy in policies {
+                let decision = policy.evaluate(&context).unwrap();
+                assert!(decision.allow);
+                assert_eq!(decision.obligations, expected);
+            }
+        }
+
+        // Obligations that cannot both be met deny.
+        let other_aad = rules("permit \"c\" with required_aad = y;");
+        let policy = AllOf::new(vec![rules(audited), other_aad]);
+        let decision = policy.evaluate(&context).unwrap();
+        assert!(!decision.allow);
+        assert!(decision.applicable);
+    }
+}
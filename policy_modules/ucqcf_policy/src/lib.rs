@@ -0,0 +1,360 @@
+// policy_modules/ucqcf_policy/src/lib.rs
+
+//! A declarative policy module implementing `PolicyInterface`, and
+//! combinators for layering several policies (see `combinators`).
+//!
+//! Rules are loaded from a text file, so authorization can change without
+//! recompiling. Each rule ends with `;`, and `#` starts a comment:
+//!
+//! ```text
+//! permit "defense-operators"
+//!     when principal in [alice, bob]
+//!     and operation in [encrypt, decrypt]
+//!     and domain == defense
+//!     and key.provider == ciem
+//!     with max_uses = 10, expires_in = 500, audit = detailed,
+//!          reason = "mission operators";
+//!
+//! deny "lockdown" when env.threat_level == high with reason = "site in lockdown";
+//! ```
+//!
+//! Conditions compare an attribute with `==`, `!=`, `in [..]`, or, for
+//! numeric attributes, `<`, `<=`, `>`, `>=`. The attributes are `principal`,
+//! `operation`, `algorithm`, `domain`, `quantum_resistant`,
+//! `require_atomic_time`, `security_strength` (in bits), `compliance`
+//! (`fips140-3`, `cnsa2` or `bsi-tr-02102`), `classification`, `epoch`
+//! (the engine's secure-clock time in seconds since the UNIX epoch),
+//! `key.<name>` for key attributes and
+//! `env.<name>` for environment attributes. A condition on an attribute the
+//! request does not carry, or a numeric comparison on a value that is not a
+//! number, does not hold in permit rules and always holds in deny rules, so
+//! leaving an attribute out or garbling it never evades a denial.
+//!
+//! Permit rules may attach `max_uses`, `expires_in`, `key_max_invocations`,
+//! `key_max_bytes` (all at least 1), `audit` (`standard` or `detailed`),
+//! `required_aad` and `reason`; deny rules only a `reason`. The key limits
+//! are passed to the key manager of the capability's key.
+//!
+//! Evaluation uses deny-overrides semantics: any matching deny rule denies the
+//! request; otherwise any matching permit rule allows it, with the obligations
+//! of all matching permit rules merged to the most restrictive; otherwise the
+//! policy is not applicable, which the engine treats as a denial.
+//!
+//! `explain` reports one trace step per rule, listing the attributes the rule
+//! consulted before it matched or failed to.
+
+pub mod combinators;
+mod parser;
+mod rules;
+
+pub use crate::combinators::{AllOf, AnyOf, DenyOverrides, FirstApplicable, Scoped};
+pub use crate::parser::{ParseError, parse_rules};
+pub use crate::rules::{Comparison, Condition, Effect, Rule};
+
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use ucqcf_core::CryptoError;
+use ucqcf_core::interfaces::{
+    Obligations, PolicyContext, PolicyDecision, PolicyEvaluation, PolicyInterface, TraceStep,
+};
+
+/// Errors that can occur while loading a rule file.
+#[derive(Debug, Error)]
+pub enum PolicyLoadError {
+    #[error("Could not read policy file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid policy: {0}")]
+    Parse(#[from] ParseError),
+    #[error("The policy was not loaded from a file.")]
+    NoSource,
+}
+
+/// A `PolicyInterface` that evaluates declarative rules with deny-overrides semantics.
+pub struct RulePolicy {
+    rules: RefCell<Vec<Rule>>,
+    source: Option<PathBuf>,
+}
+
+impl RulePolicy {
+    /// Creates a policy from rules written in the text format.
+    pub fn parse(source: &str) -> Result<Self, PolicyLoadError> {
+        Ok(Self {
+            rules: RefCell::new(parse_rules(source)?),
+            source: None,
+        })
+    }
+
+    /// Loads a policy from a rule file.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, PolicyLoadError> {
+        let path = path.as_ref();
+        let rules = parse_rules(&std::fs::read_to_string(path)?)?;
+        Ok(Self {
+            rules: RefCell::new(rules),
+            source: Some(path.to_path_buf()),
+        })
+    }
+
+    /// Re-reads the rule file the policy was loaded from. If the file cannot be
+    /// read or parsed, the current rules stay in force and the error is returned.
+    pub fn reload(&self) -> Result<(), PolicyLoadError> {
+        let path = self.source.as_ref().ok_or(PolicyLoadError::NoSource)?;
+        let rules = parse_rules(&std::fs::read_to_string(path)?)?;
+        *self.rules.borrow_mut() = rules;
+        Ok(())
+    }
+
+    /// Returns a copy of the rules currently in force.
+    pub fn rules(&self) -> Vec<Rule> {
+        self.rules.borrow().clone()
+    }
+}
+
+impl PolicyInterface for RulePolicy {
+    fn evaluate(&self, context: &PolicyContext) -> Result<PolicyDecision, CryptoError> {
+        self.explain(context).map(|evaluation| evaluation.decision)
+    }
+
+    fn explain(&self, context: &PolicyContext) -> Result<PolicyEvaluation, CryptoError> {
+        let rules = self.rules.borrow();
+        let mut trace = Vec::with_capacity(rules.len());
+        let mut matching = Vec::new();
+        for rule in rules.iter() {
+            let (matched, consulted) = rule.trace(context);
+            trace.push(TraceStep {
+                depth: 0,
+                source: format!("rule \"{}\"", rule.name),
+                applicable: matched,
+                allow: rule.effect == Effect::Permit,
+                consulted,
+                obligations: rule.obligations.clone(),
+                reason: rule.reason.clone(),
+            });
+            if matched {
+                matching.push(rule);
+            }
+        }
+        Ok(PolicyEvaluation {
+            decision: decide(&matching),
+            trace,
+        })
+    }
+}
+
+/// Combines the rules that matched a request into a decision.
+fn decide(matching: &[&Rule]) -> PolicyDecision {
+    if let Some(deny) = matching.iter().find(|r| r.effect == Effect::Deny) {
+        let reason = deny
+            .reason
+            .clone()
+            .unwrap_or_else(|| format!("denied by rule \"{}\"", deny.name));
+        return PolicyDecision::deny(&reason);
+    }
+
+    let permits: Vec<&&Rule> = matching
+        .iter()
+        .filter(|r| r.effect == Effect::Permit)
+        .collect();
+    let Some(first) = permits.first() else {
+        return PolicyDecision::not_applicable("no rule applies to the request");
+    };
+
+    let mut obligations = Obligations::default();
+    for permit in &permits {
+        match obligations.merge(&permit.obligations) {
+            Some(merged) => obligations = merged,
+            None => {
+                return PolicyDecision::deny(&format!(
+                    "rule \"{}\" requires conflicting AAD",
+                    permit.name
+                ));
+            }
+        }
+    }
+
+    let mut decision = PolicyDecision::permit().with_obligations(obligations);
+    decision.reason = Some(
+        first
+            .reason
+            .clone()
+            .unwrap_or_else(|| format!("permitted by rule \"{}\"", first.name)),
+    );
+    decision
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use ucqcf_core::interfaces::AuditLevel;
+    use ucqcf_core::profile::{Domain, SecurityProfile};
+    use ucqcf_core::request::Operation;
+
+    fn context(principal: &str, operation: Operation, domain: Domain) -> PolicyContext {
+        let mut profile = SecurityProfile::new(domain);
+        profile.quantum_resistant = true;
+        PolicyContext {
+            principal: principal.to_string(),
+            operation,
+            algorithm: None,
+            key_attributes: BTreeMap::new(),
+            profile,
+            epoch: 0,
+            environment: BTreeMap::from([("threat_level".to_string(), "low".to_string())]),
+        }
+    }
+
+    fn example() -> RulePolicy {
+        RulePolicy::parse(include_str!("../policies/example.rules")).unwrap()
+    }
+
+    #[test]
+    fn permits_with_obligations() {
+        let decision = example()
+            .evaluate(&context("alice", Operation::Encrypt, Domain::Defense))
+            .unwrap();
+        assert!(decision.allow);
+        assert_eq!(decision.obligations.max_uses, Some(100));
+        assert_eq!(decision.obligations.audit_level, AuditLevel::Detailed);
+        assert_eq!(decision.reason.as_deref(), Some("mission operators"));
+    }
+
+    #[test]
+    fn is_not_applicable_when_no_rule_matches() {
+        let decision = example()
+            .evaluate(&context("mallory", Operation::Encrypt, Domain::Defense))
+            .unwrap();
+        assert!(!decision.allow);
+        assert!(!decision.applicable);
+    }
+
+    #[test]
+    fn deny_overrides_permit() {
+        let mut context = context("alice", Operation::Encrypt, Domain::Defense);
+        context
+            .environment
+            .insert("threat_level".to_string(), "high".to_string());
+        let decision = example().evaluate(&context).unwrap();
+        assert!(!decision.allow);
+        assert!(decision.applicable);
+        assert_eq!(decision.reason.as_deref(), Some("site is in lockdown"));
+    }
+
+    #[test]
+    fn merges_the_obligations_of_every_matching_permit() {
+        let policy = RulePolicy::parse(
+            "permit \"a\" when principal == alice with max_uses = 5, expires_in = 100,\n\
+                 key_max_invocations = 1000;\n\
+             permit \"b\" when operation == encrypt with max_uses = 10, audit = detailed,\n\
+                 key_max_invocations = 500, key_max_bytes = 4096;",
+        )
+        .unwrap();
+        let decision = policy
+            .evaluate(&context("alice", Operation::Encrypt, Domain::Defense))
+            .unwrap();
+        assert!(decision.allow);
+        assert_eq!(
+            decision.obligations,
+            Obligations {
+                max_uses: Some(5),
+                expires_in: Some(100),
+                audit_level: AuditLevel::Detailed,
+                required_aad: None,
+                key_max_invocations: Some(500),
+                key_max_bytes: Some(4096),
+            }
+        );
+        assert_eq!(decision.reason.as_deref(), Some("permitted by rule \"a\""));
+    }
+
+    #[test]
+    fn denies_conflicting_aad() {
+        let policy = RulePolicy::parse(
+            "permit \"a\" with required_aad = x;\npermit \"b\" with required_aad = y;",
+        )
+        .unwrap();
+        let decision = policy
+            .evaluate(&context("alice", Operation::Encrypt, Domain::Defense))
+            .unwrap();
+        assert!(!decision.allow);
+        assert_eq!(
+            decision.reason.as_deref(),
+            Some("rule \"b\" requires conflicting AAD")
+        );
+    }
+
+    #[test]
+    fn missing_attributes_never_evade_a_denial() {
+        let policy = RulePolicy::parse(
+            "permit \"all\";\n\
+             deny \"uncleared\" when key.label.clearance != secret;\n\
+             permit \"labelled\" when key.label.project != apollo;",
+        )
+        .unwrap();
+        let mut context = context("alice", Operation::Encrypt, Domain::Defense);
+        assert!(!policy.evaluate(&context).unwrap().allow);
+
+        context
+            .key_attributes
+            .insert("label.clearance".to_string(), "secret".to_string());
+        let evaluation = policy.explain(&context).unwrap();
+        assert!(evaluation.decision.allow);
+        // The permit rule's `!=` does not hold on the missing project label.
+        assert!(!evaluation.trace[2].applicable);
+        assert_eq!(
+            evaluation.trace[2].consulted,
+            [("key.label.project".to_string(), None)]
+        );
+    }
+
+    #[test]
+    fn missing_or_garbled_attributes_satisfy_every_deny_comparison() {
+        let mut context = context("alice", Operation::Encrypt, Domain::Defense);
+        let missing = &[None][..];
+        // Numeric comparisons also treat a non-number as unknown.
+        let garbled = &[None, Some("high")][..];
+        for (condition, levels) in [
+            ("env.level == 3", missing),
+            ("env.level != 3", missing),
+            ("env.level in [1, 2]", missing),
+            ("env.level < 3", garbled),
+            ("env.level <= 3", garbled),
+            ("env.level > 3", garbled),
+            ("env.level >= 3", garbled),
+        ] {
+            let permit = RulePolicy::parse(&format!("permit \"p\" when {condition};")).unwrap();
+            let deny = RulePolicy::parse(&format!("permit \"all\";\ndeny \"d\" when {condition};"))
+                .unwrap();
+            for level in levels {
+                match level {
+                    Some(level) => context
+                        .environment
+                        .insert("level".to_string(), level.to_string()),
+                    None => context.environment.remove("level"),
+                };
+                assert!(
+                    !permit.evaluate(&context).unwrap().allow,
+                    "{condition} {level:?}"
+                );
+                assert!(
+                    !deny.evaluate(&context).unwrap().allow,
+                    "{condition} {level:?}"
+                );
+            }
+        }
+
+        // Unknown threat levels lock the example site down.
+        context.environment.clear();
+        assert!(!example().evaluate(&context).unwrap().allow);
+    }
+
+    #[test]
+    fn compares_numbers() {
+        let policy = RulePolicy::parse("permit \"early\" when epoch < 100;").unwrap();
+        let mut context = context("alice", Operation::Sign, Domain::Telecom);
+        context.epoch = 99;
+        assert!(policy.evaluate(&context).unwrap().allow);
+        context.epoch = 100;
+        assert!(!policy.evaluate(&context).unwrap().allow);
+    }
+}
@@ -0,0 +1,443 @@
+// policy_modules/ucqcf_policy/src/parser.rs
+
+//! Parses the textual rule format into `Rule`s. See the crate documentation for the grammar.
+
+use crate::rules::{Comparison, Condition, Effect, Rule, is_known_attribute};
+use thiserror::Error;
+use ucqcf_core::interfaces::{AuditLevel, Obligations};
+
+/// An error in a rule file, with the line it was found on.
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("line {line}: {message}")]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Number(String),
+    Symbol(&'static str),
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::Ident(s) => format!("`{}`", s),
+            Token::Str(s) => format!("\"{}\"", s),
+            Token::Number(n) => n.clone(),
+            Token::Symbol(s) => format!("`{}`", s),
+        }
+    }
+}
+
+const SYMBOLS: [&str; 11] = ["==", "!=", "<=", ">=", "<", ">", "=", "[", "]", ",", ";"];
+
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, ParseError> {
+    let mut tokens = Vec::new();
+    for (index, line) in source.lines().enumerate() {
+        let line_no = index + 1;
+        let mut rest = line;
+        loop {
+            rest = rest.trim_start();
+            if rest.is_empty() || rest.starts_with('#') {
+                break;
+            }
+            if let Some(body) = rest.strip_prefix('"') {
+                let end = body.find('"').ok_or_else(|| ParseError {
+                    line: line_no,
+                    message: "unterminated string".to_string(),
+                })?;
+                tokens.push((Token::Str(body[..end].to_string()), line_no));
+                rest = &body[end + 1..];
+            } else if let Some(symbol) = SYMBOLS.iter().find(|s| rest.starts_with(**s)) {
+                tokens.push((Token::Symbol(symbol), line_no));
+                rest = &rest[symbol.len()..];
+            } else {
+                let end = rest
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-')))
+                    .unwrap_or(rest.len());
+                if end == 0 {
+                    return Err(ParseError {
+                        line: line_no,
+                        message: format!("unexpected character `{}`", rest.chars().next().unwrap()),
+                    });
+                }
+                let word = &rest[..end];
+                let token = if word.chars().all(|c| c.is_ascii_digit()) {
+                    Token::Number(word.to_string())
+                } else {
+                    Token::Ident(word.to_string())
+                };
+                tokens.push((token, line_no));
+                rest = &rest[end..];
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|(t, _)| t)
+    }
+
+    fn line(&self) -> usize {
+        self.tokens
+            .get(self.position)
+            .or(self.tokens.last())
+            .map_or(1, |(_, line)| *line)
+    }
+
+    fn error(&self, message: String) -> ParseError {
+        ParseError {
+            line: self.line(),
+            message,
+        }
+    }
+
+    fn next(&mut self, expected: &str) -> Result<Token, ParseError> {
+        let token = self
+            .peek()
+            .cloned()
+            .ok_or_else(|| self.error(format!("expected {}, found end of file", expected)))?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn unexpected<T>(&mut self, token: &Token, expected: &str) -> Result<T, ParseError> {
+        self.position -= 1;
+        Err(self.error(format!("expected {}, found {}", expected, token.describe())))
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        if self.peek() == Some(&Token::Ident(keyword.to_string())) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn eat_symbol(&mut self, symbol: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Symbol(s)) if *s == symbol) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_symbol(&mut self, symbol: &str) -> Result<(), ParseError> {
+        if self.eat_symbol(symbol) {
+            Ok(())
+        } else {
+            let token = self.next(&format!("`{}`", symbol))?;
+            self.unexpected(&token, &format!("`{}`", symbol))
+        }
+    }
+
+    /// A value is a bare word, a quoted string or a number.
+    fn value(&mut self) -> Result<String, ParseError> {
+        match self.next("a value")? {
+            Token::Ident(v) | Token::Str(v) | Token::Number(v) => Ok(v),
+            token => self.unexpected(&token, "a value"),
+        }
+    }
+
+    fn number(&mut self) -> Result<u64, ParseError> {
+        match self.next("a number")? {
+            Token::Number(n) => n
+                .parse()
+                .map_err(|_| self.error(format!("number `{}` is out of range", n))),
+            token => self.unexpected(&token, "a number"),
+        }
+    }
+
+    fn rule(&mut self) -> Result<Rule, ParseError> {
+        let effect = match self.next("`permit` or `deny`")? {
+            Token::Ident(word) if word == "permit" => Effect::Permit,
+            Token::Ident(word) if word == "deny" => Effect::Deny,
+            token => return self.unexpected(&token, "`permit` or `deny`"),
+        };
+        let name = match self.next("a quoted rule name")? {
+            Token::Str(name) => name,
+            token => return self.unexpected(&token, "a quoted rule name"),
+        };
+
+        let mut conditions = Vec::new();
+        if self.eat_keyword("when") {
+            loop {
+                conditions.push(self.condition()?);
+                if !self.eat_keyword("and") {
+                    break;
+                }
+            }
+        }
+
+        let mut obligations = Obligations::default();
+        let mut reason = None;
+        if self.eat_keyword("with") {
+            loop {
+                self.obligation(effect, &mut obligations, &mut reason)?;
+                if !self.eat_symbol(",") {
+                    break;
+                }
+            }
+        }
+        self.expect_symbol(";")?;
+
+        Ok(Rule {
+            name,
+            effect,
+            conditions,
+            obligations,
+            reason,
+        })
+    }
+
+    fn condition(&mut self) -> Result<Condition, ParseError> {
+        let attribute = match self.next("an attribute")? {
+            Token::Ident(attribute) if is_known_attribute(&attribute) => attribute,
+            Token::Ident(attribute) => {
+                self.position -= 1;
+                return Err(self.error(format!("unknown attribute `{}`", attribute)));
+            }
+            token => return self.unexpected(&token, "an attribute"),
+        };
+
+        let comparison = match self.next("a comparison")? {
+            Token::Symbol("==") => Comparison::Eq,
+            Token::Symbol("!=") => Comparison::Ne,
+            Token::Symbol("<") => Comparison::Lt,
+            Token::Symbol("<=") => Comparison::Le,
+            Token::Symbol(">") => Comparison::Gt,
+            Token::Symbol(">=") => Comparison::Ge,
+            Token::Ident(word) if word == "in" => Comparison::In,
+            token => return self.unexpected(&token, "a comparison"),
+        };
+
+        let values = if comparison == Comparison::In {
+            self.expect_symbol("[")?;
+            let mut values = vec![self.value()?];
+            while self.eat_symbol(",") {
+                values.push(self.value()?);
+            }
+            self.expect_symbol("]")?;
+            values
+        } else if matches!(comparison, Comparison::Eq | Comparison::Ne) {
+            vec![self.value()?]
+        } else {
+            vec![self.number()?.to_string()]
+        };
+
+        Ok(Condition {
+            attribute,
+            comparison,
+            values,
+        })
+    }
+
+    fn obligation(
+        &mut self,
+        effect: Effect,
+        obligations: &mut Obligations,
+        reason: &mut Option<String>,
+    ) -> Result<(), ParseError> {
+        let name = match self.next("an obligation")? {
+            Token::Ident(name) => name,
+            token => return self.unexpected(&token, "an obligation"),
+        };
+        if effect == Effect::Deny && name != "reason" {
+            self.position -= 1;
+            return Err(self.error("deny rules only accept a `reason`".to_string()));
+        }
+        self.expect_symbol("=")?;
+        match name.as_str() {
+            "max_uses" => {
+                let uses = self.number()?;
+                if uses == 0 {
+                    return Err(self.error("max_uses must be at least 1".to_string()));
+                }
+                obligations.max_uses = Some(
+                    u32::try_from(uses)
+                        .map_err(|_| self.error(format!("max_uses `{}` is out of range", uses)))?,
+                );
+            }
+            "expires_in" => {
+                let lifetime = self.number()?;
+                if lifetime == 0 {
+                    return Err(self.error("expires_in must be at least 1".to_string()));
+                }
+                obligations.expires_in = Some(lifetime);
+            }
+            "key_max_invocations" | "key_max_bytes" => {
+                let limit = self.number()?;
+                if limit == 0 {
+                    return Err(self.error(format!("{} must be at least 1", name)));
+                }
+                if name == "key_max_invocations" {
+                    obligations.key_max_invocations = Some(limit);
+                } else {
+                    obligations.key_max_bytes = Some(limit);
+                }
+            }
+            "audit" => {
+                obligations.audit_level = match self.value()?.as_str() {
+                    "standard" => AuditLevel::Standard,
+                    "detailed" => AuditLevel::Detailed,
+                    other => return Err(self.error(format!("unknown audit level `{}`", other))),
+                }
+            }
+            "required_aad" => obligations.required_aad = Some(self.value()?.into_bytes()),
+            "reason" => *reason = Some(self.value()?),
+            other => return Err(self.error(format!("unknown obligation `{}`", other))),
+        }
+        Ok(())
+    }
+}
+
+/// Parses a complete rule file.
+pub fn parse_rules(source: &str) -> Result<Vec<Rule>, ParseError> {
+    let mut parser = Parser {
+        tokens: tokenize(source)?,
+        position: 0,
+    };
+    let mut rules = Vec::new();
+    while parser.peek().is_some() {
+        let line = parser.line();
+        let rule = parser.rule()?;
+        if rules.iter().any(|r: &Rule| r.name == rule.name) {
+            return Err(ParseError {
+                line,
+                message: format!("duplicate rule name \"{}\"", rule.name),
+            });
+        }
+        rules.push(rule);
+    }
+    Ok(rules)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(source: &str) -> ParseError {
+        parse_rules(source).unwrap_err()
+    }
+
+    #[test]
+    fn parses_the_example_policy() {
+        let rules = parse_rules(include_str!("../policies/example.rules")).unwrap();
+        let names: Vec<&str> = rules.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "lockdown",
+                "no-blockchain-decrypt",
+                "defense-operators",
+                "telecom-signing"
+            ]
+        );
+
+        let operators = &rules[2];
+        assert_eq!(operators.effect, Effect::Permit);
+        assert_eq!(
+            operators.conditions[0],
+            Condition {
+                attribute: "principal".to_string(),
+                comparison: Comparison::In,
+                values: vec!["alice".to_string(), "bob".to_string()],
+            }
+        );
+        assert_eq!(
+            operators.obligations,
+            Obligations {
+                max_uses: Some(100),
+                expires_in: Some(500),
+                audit_level: AuditLevel::Detailed,
+                required_aad: None,
+                key_max_invocations: None,
+                key_max_bytes: None,
+            }
+        );
+        assert_eq!(operators.reason.as_deref(), Some("mission operators"));
+        assert_eq!(rules[3].conditions[2].comparison, Comparison::Lt);
+    }
+
+    #[test]
+    fn parses_rules_without_conditions_or_obligations() {
+        let rules = parse_rules("permit \"anyone\"; # trailing comment\ndeny \"no one\";").unwrap();
+        assert_eq!(rules.len(), 2);
+        assert!(rules.iter().all(|r| r.conditions.is_empty()));
+        assert_eq!(rules[1].effect, Effect::Deny);
+    }
+
+    #[test]
+    fn reports_errors_with_their_line() {
+        assert_eq!(
+            error("permit \"a\"\n  when colour == red;"),
+            ParseError {
+                line: 2,
+                message: "unknown attribute `colour`".to_string(),
+            }
+        );
+        assert_eq!(error("permit \"a\";\npermit \"a\";").line, 2);
+        assert_eq!(
+            error("permit \"a\" when epoch < soon;").message,
+            "expected a number, found `soon`"
+        );
+        assert_eq!(
+            error("permit \"a\" when key. == x;").message,
+            "unknown attribute `key.`"
+        );
+        assert_eq!(
+            error("permit \"a\" when principal == alice").message,
+            "expected `;`, found end of file"
+        );
+        assert_eq!(error("permit \"a;").message, "unterminated string");
+        assert_eq!(
+            error("allow \"a\";").message,
+            "expected `permit` or `deny`, found `allow`"
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_obligations() {
+        assert_eq!(
+            error("deny \"a\" with max_uses = 1;").message,
+            "deny rules only accept a `reason`"
+        );
+        assert_eq!(
+            error("permit \"a\" with max_uses = 0;").message,
+            "max_uses must be at least 1"
+        );
+        assert_eq!(
+            error("permit \"a\" with max_uses = 4294967296;").message,
+            "max_uses `4294967296` is out of range"
+        );
+        assert_eq!(
+            error("permit \"a\" with expires_in = 0;").message,
+            "expires_in must be at least 1"
+        );
+        assert_eq!(
+            error("permit \"a\" with key_max_bytes = 0;").message,
+            "key_max_bytes must be at least 1"
+        );
+        assert_eq!(
+            error("permit \"a\" with audit = verbose;").message,
+            "unknown audit level `verbose`"
+        );
+        assert_eq!(
+            error("permit \"a\" with colour = red;").message,
+            "unknown obligation `colour`"
+        );
+    }
+}
@@ -0,0 +1,155 @@
+// policy_modules/ucqcf_policy/src/rules.rs
+
+//! The rule model and its evaluation against a `PolicyContext`.
+
+use ucqcf_core::interfaces::{Obligations, PolicyContext};
+use ucqcf_core::profile::Domain;
+
+/// Whether a matching rule allows or forbids the request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Effect {
+    Permit,
+    Deny,
+}
+
+/// How a condition compares an attribute with its operand(s).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    In,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// A single `attribute <comparison> operand` test.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Condition {
+    pub attribute: String,
+    pub comparison: Comparison,
+    /// The operands; exactly one unless the comparison is `In`.
+    pub values: Vec<String>,
+}
+
+impl Condition {
+    /// Returns `true` if the condition holds in a rule with `effect`. An
+    /// attribute the context does not carry, or that is not a number where
+    /// the comparison needs one, fails every condition of a permit rule and
+    /// satisfies every condition of a deny rule: a request must not escape a
+    /// deny rule by leaving the attribute out or garbling it.
+    pub fn matches(&self, context: &PolicyContext, effect: Effect) -> bool {
+        self.holds(attribute(context, &self.attribute), effect)
+    }
+
+    fn holds(&self, actual: Option<String>, effect: Effect) -> bool {
+        let deny = effect == Effect::Deny;
+        let Some(actual) = actual else {
+            return deny;
+        };
+        let expected = &self.values[0];
+        let compare = |op: fn(u64, u64) -> bool| match (actual.parse(), expected.parse()) {
+            (Ok(actual), Ok(expected)) => op(actual, expected),
+            _ => deny,
+        };
+        match self.comparison {
+            Comparison::Eq => &actual == expected,
+            Comparison::Ne => &actual != expected,
+            Comparison::In => self.values.contains(&actual),
+            Comparison::Lt => compare(|a, b| a < b),
+            Comparison::Le => compare(|a, b| a <= b),
+            Comparison::Gt => compare(|a, b| a > b),
+            Comparison::Ge => compare(|a, b| a >= b),
+        }
+    }
+}
+
+/// A named rule: if every condition holds, the rule applies with its effect.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rule {
+    pub name: String,
+    pub effect: Effect,
+    pub conditions: Vec<Condition>,
+    /// Obligations attached when a permit rule applies.
+    pub obligations: Obligations,
+    pub reason: Option<String>,
+}
+
+impl Rule {
+    /// Returns `true` if every condition of the rule holds.
+    pub fn matches(&self, context: &PolicyContext) -> bool {
+        self.conditions
+            .iter()
+            .all(|c| c.matches(context, self.effect))
+    }
+
+    /// Like `matches`, but also returns the attributes consulted, with the
+    /// values seen, up to and including the first condition that fails.
+    pub(crate) fn trace(&self, context: &PolicyContext) -> (bool, Vec<(String, Option<String>)>) {
+        let mut consulted = Vec::new();
+        for condition in &self.conditions {
+            let actual = attribute(context, &condition.attribute);
+            consulted.push((condition.attribute.clone(), actual.clone()));
+            if !condition.holds(actual, self.effect) {
+                return (false, consulted);
+            }
+        }
+        (true, consulted)
+    }
+}
+
+/// The attributes a condition may test, besides the `key.*` and `env.*` families.
+pub(crate) const ATTRIBUTES: [&str; 10] = [
+    "principal",
+    "operation",
+    "algorithm",
+    "domain",
+    "quantum_resistant",
+    "require_atomic_time",
+    "security_strength",
+    "compliance",
+    "classification",
+    "epoch",
+];
+
+/// Returns `true` if `name` is an attribute a condition may test.
+pub(crate) fn is_known_attribute(name: &str) -> bool {
+    ATTRIBUTES.contains(&name)
+        || name.strip_prefix("key.").is_some_and(|n| !n.is_empty())
+        || name.strip_prefix("env.").is_some_and(|n| !n.is_empty())
+}
+
+/// Resolves an attribute of the context to its textual value.
+fn attribute(context: &PolicyContext, name: &str) -> Option<String> {
+    match name {
+        "principal" => Some(context.principal.clone()),
+        "operation" => Some(context.operation.as_str().to_string()),
+        "algorithm" => context.algorithm.map(|a| a.name().to_string()),
+        "domain" => Some(domain_name(context.profile.domain).to_string()),
+        "quantum_resistant" => Some(context.profile.quantum_resistant.to_string()),
+        "require_atomic_time" => Some(context.profile.require_atomic_time.to_string()),
+        "security_strength" => Some(context.profile.security_strength.bits().to_string()),
+        "compliance" => context.profile.compliance.map(|c| c.as_str().to_string()),
+        "classification" => Some(context.profile.classification.as_str().to_string()),
+        "epoch" => Some(context.epoch.to_string()),
+        _ => {
+            if let Some(key) = name.strip_prefix("key.") {
+                context.key_attributes.get(key).cloned()
+            } else if let Some(env) = name.strip_prefix("env.") {
+                context.environment.get(env).cloned()
+            } else {
+                None
+            }
+        }
+    }
+}
+
+fn domain_name(domain: Domain) -> &'static str {
+    match domain {
+        Domain::Defense => "defense",
+        Domain::Telecom => "telecom",
+        Domain::Blockchain => "blockchain",
+        Domain::EndToEnd => "end_to_end",
+    }
+}
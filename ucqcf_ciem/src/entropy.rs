@@ -33,7 +33,7 @@ impl<'a> EntropySource<'a> {
 
 
 /// Errors that can occur during entropy aggregation.
-#[derive(Debug, Error, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Error, PartialEq, Eq)]
 pub enum EntropyError {
     #[error("Health check failed: Repetition count test detected a stuck source.")]
     RepetitionCheckFailed,
@@ -49,17 +49,47 @@ pub struct EntropyAggregator<'a> {
     primary: Box<dyn RngSource + 'a>,
     auxiliary: Vec<Box<dyn RngSource + 'a>>,
     hmac_key: hmac::Key,
+    /// The cutoff of the adaptive proportion test for the primary source.
+    proportion_cutoff: usize,
+    /// The outcome of the health checks on the latest sample of the primary
+    /// source, or `None` before the first sample.
+    health: Cell<Option<Result<(), EntropyError>>>,
 }
 
 use ring::hmac;
+use std::cell::Cell;
 
 // --- Health Check Constants ---
+/// The number of bytes sampled from the primary source per draw.
+const PRIMARY_SAMPLE_LEN: usize = 1024;
 /// The cutoff for the repetition count test (i.e., how many times a value can repeat).
 const REPETITION_COUNT_CUTOFF: usize = 5; // A reasonable value for a mock test.
 /// The window size for the adaptive proportion test.
 const ADAPTIVE_PROPORTION_WINDOW_SIZE: usize = 512;
-/// The cutoff for the adaptive proportion test (max occurrences of one value in the window).
-const ADAPTIVE_PROPORTION_CUTOFF: usize = 10; // A reasonable value for a mock test.
+/// The min-entropy claimed for each byte of the primary source, in bits.
+const CLAIMED_MIN_ENTROPY: f64 = 8.0;
+/// The probability that a healthy source fails a health check, 2^-20.
+const FALSE_POSITIVE_RATE: f64 = 1.0 / (1u64 << 20) as f64;
+
+/// The cutoff of the adaptive proportion test (SP 800-90B section 4.4.2):
+/// `1 + CRITBINOM(window, 2^-min_entropy, 1 - alpha)`, the number of times the
+/// first value of a window may not recur in it. A source that yields
+/// `min_entropy` bits per sample fails the test with probability at most
+/// `alpha`.
+pub(crate) fn adaptive_proportion_cutoff(window: usize, min_entropy: f64, alpha: f64) -> usize {
+    let p = 2f64.powf(-min_entropy);
+    let n = window as f64;
+    // Walks the binomial distribution up from zero occurrences.
+    let mut probability = (1.0 - p).powf(n);
+    let mut cumulative = probability;
+    let mut occurrences = 0;
+    while cumulative < 1.0 - alpha && occurrences < window {
+        probability *= (n - occurrences as f64) / (occurrences as f64 + 1.0) * p / (1.0 - p);
+        occurrences += 1;
+        cumulative += probability;
+    }
+    1 + occurrences
+}
 
 
 impl<'a> EntropyAggregator<'a> {
@@ -69,7 +99,17 @@ impl<'a> EntropyAggregator<'a> {
         auxiliary: Vec<Box<dyn RngSource + 'a>>,
         hmac_key: hmac::Key,
     ) -> Self {
-        Self { primary, auxiliary, hmac_key }
+        let proportion_cutoff = adaptive_proportion_cutoff(
+            ADAPTIVE_PROPORTION_WINDOW_SIZE,
+            CLAIMED_MIN_ENTROPY,
+            FALSE_POSITIVE_RATE,
+        );
+        Self { primary, auxiliary, hmac_key, proportion_cutoff, health: Cell::new(None) }
+    }
+
+    /// Returns the number of independent sources, primary included.
+    pub fn source_count(&self) -> usize {
+        1 + self.auxiliary.len()
     }
 
     /// Gathers, health-checks, and conditions entropy from all sources.
@@ -77,9 +117,11 @@ impl<'a> EntropyAggregator<'a> {
         let mut raw_entropy = Vec::new();
 
         // 1. Gather entropy from the primary source.
-        let mut primary_data = vec![0u8; 1024]; // Collect enough data for tests.
+        let mut primary_data = vec![0u8; PRIMARY_SAMPLE_LEN]; // Collect enough data for tests.
         self.primary.read(&mut primary_data);
-        self.run_health_checks(&primary_data)?;
+        let health = self.run_health_checks(&primary_data);
+        self.health.set(Some(health));
+        health?;
         raw_entropy.extend_from_slice(&primary_data);
 
         // 2. Gather entropy from auxiliary sources (no health checks required by NIST).
@@ -93,6 +135,20 @@ impl<'a> EntropyAggregator<'a> {
         Ok(self.condition_entropy(&raw_entropy))
     }
 
+    /// Returns the outcome of the continuous health checks on the latest
+    /// sample drawn from the primary source, without drawing entropy. Before
+    /// the first draw, runs them once on a fresh sample, as a start-up test.
+    pub fn health(&self) -> Result<(), EntropyError> {
+        if let Some(health) = self.health.get() {
+            return health;
+        }
+        let mut sample = vec![0u8; PRIMARY_SAMPLE_LEN];
+        self.primary.read(&mut sample);
+        let health = self.run_health_checks(&sample);
+        self.health.set(Some(health));
+        health
+    }
+
     /// Runs all required health checks on a sample of entropy.
     fn run_health_checks(&self, data: &[u8]) -> Result<(), EntropyError> {
         self.repetition_count_test(data)?;
@@ -123,11 +179,11 @@ impl<'a> EntropyAggregator<'a> {
         if data.len() < ADAPTIVE_PROPORTION_WINDOW_SIZE {
             return Ok(()); // Not enough data to test.
         }
-        let mut counts = std::collections::HashMap::new();
-        for &byte in data.iter().take(ADAPTIVE_PROPORTION_WINDOW_SIZE) {
-            *counts.entry(byte).or_insert(0) += 1;
-        }
-        if counts.values().any(|&c| c > ADAPTIVE_PROPORTION_CUTOFF) {
+        // Counts how often the window's first value recurs in it, as the standard prescribes;
+        // testing every value would multiply the false positive rate by 256.
+        let window = &data[..ADAPTIVE_PROPORTION_WINDOW_SIZE];
+        let count = window.iter().filter(|&&byte| byte == window[0]).count();
+        if count >= self.proportion_cutoff {
             return Err(EntropyError::ProportionCheckFailed);
         }
         Ok(())
@@ -1,52 +1,146 @@
 // ucqcf/ucqcf_ciem/src/fsm_generated.rs
 
-//! This FSM is a mechanical mapping from the PlusCal specification CIEM_FSM.tla.
-//! It is considered an authoritative, compiled artifact, not a hand-written interpretation.
+//! The FSM started as a mechanical mapping from the PlusCal specification
+//! CIEM_FSM.tla, but has since been extended by hand and no specification is
+//! kept in step with it: this hand-written code is authoritative.
+//!
+//! The states follow the key states of NIST SP 800-57 Part 1 (section 7):
+//! pre-activation, active (split into `Bound` and `Active` below), suspended,
+//! deactivated, compromised and destroyed (`Zeroized`), plus `Expired` and
+//! `Revoked`.
+//!
+//! A key may have several capabilities outstanding at once: `outstanding`
+//! counts them. AUTHORIZE moves a `Bound` key to `Active` or adds another
+//! capability to an `Active` one; RELEASE gives one back, and the key returns
+//! to `Bound` when the last is released. Invariant: a `Bound` key has no
+//! capability outstanding and an `Active` key at least one. Capabilities stay
+//! outstanding when the key leaves `Active`, and the key's state decides what
+//! they may still do (see `KeyUsage`).
+//!
+//! Events that depend on time carry the current epoch. A key may have a
+//! cryptoperiod, counted in epochs from its activation: the first AUTHORIZE
+//! or USE at or after its end moves a `Bound` or `Active` key to `Expired`.
+//!
+//! An `Active` key also expires when it reaches one of its usage limits: the
+//! number of USE and STREAM events, the number of STREAM events that protect
+//! data, and optionally the total bytes reported by USE, STREAM and DATA
+//! events. The event that reaches a limit succeeds and expires the key; an
+//! event that would exceed one fails, counts nothing and expires the key too.
+//!
+//! `Revoked` is terminal: a revoked key can only be zeroized, or marked as
+//! compromised without leaving `Revoked`, so nothing can use it again.
 
 /// Represents errors that can occur during FSM state transitions.
 #[derive(Debug, PartialEq, Eq)]
 pub enum FsmError {
     /// An event occurred in a state where it is not allowed.
     InvalidTransition,
-    /// The key has exceeded its maximum usage count.
+    /// The key has exceeded its maximum usage count or byte volume.
     UsageExceeded,
+    /// The key's cryptoperiod has ended, so it no longer protects data.
+    CryptoperiodEnded,
 }
 
 /// Represents the lifecycle of a cryptographic key inside the CIEM.
-/// These states correspond directly to the states in the PlusCal model.
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum KeyState {
     Empty,
     Created,
+    /// Bound, but not yet authorized for use.
+    PreActivation,
+    /// Ready for use, with no capability outstanding.
     Bound,
+    /// At least one capability is outstanding.
     Active,
+    /// Temporarily unusable, e.g. while a possible compromise is investigated.
+    Suspended,
+    /// No longer used to protect data, but still to process protected data.
+    Deactivated,
+    /// Known or suspected to be compromised. Only processes protected data.
+    Compromised,
+    /// Deactivated because the key reached its usage limit or the end of
+    /// its cryptoperiod.
     Expired,
+    /// Withdrawn from use.
     Revoked,
     Zeroized,
 }
 
-/// The Finite State Machine that governs key lifecycles, derived from PlusCal.
+/// How a capability uses its key (SP 800-57 section 5.3.4).
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum KeyUsage {
+    /// Applying protection, e.g. encrypting. Only `Bound` and `Active` keys.
+    Protect,
+    /// Processing protected data, e.g. decrypting. Also `Deactivated`,
+    /// `Compromised` and `Expired` keys, so existing data stays readable.
+    Process,
+}
+
+/// The Finite State Machine that governs key lifecycles.
 #[derive(Debug)]
 pub struct CiemFsm {
     state: KeyState,
-    usage: u32,
-    #[allow(dead_code)]
-    epoch: u64, // epoch is present in the model but not used in this phase's logic.
+    usage: u64,
+    bytes: u64,
+    streams: u64,
+    max_usage: u64,
+    max_bytes: Option<u64>,
+    max_streams: u64,
+    outstanding: u32,
+    /// Whether the key is known or suspected to be compromised.
+    compromised: bool,
+    /// The latest epoch an event carried.
+    epoch: u64,
+    activated_at: Option<u64>,
+    cryptoperiod: Option<u64>,
 }
 
 impl CiemFsm {
-    /// The maximum number of times a key can be used, from the TLA+ CONSTANT.
-    pub const MAX_USAGE: u32 = 1000; // Example value
+    /// The maximum number of times a key can be used, unless set by
+    /// `with_limits`.
+    pub const MAX_USAGE: u64 = 1000; // Example value
 
     /// Creates a new FSM in the `Empty` state.
     pub fn new() -> Self {
         Self {
             state: KeyState::Empty,
             usage: 0,
+            bytes: 0,
+            streams: 0,
+            max_usage: Self::MAX_USAGE,
+            max_bytes: None,
+            max_streams: u64::MAX,
+            outstanding: 0,
+            compromised: false,
             epoch: 0,
+            activated_at: None,
+            cryptoperiod: None,
         }
     }
 
+    /// Sets the key's cryptoperiod, in epochs from activation. `None` means
+    /// the key never expires by time.
+    pub fn with_cryptoperiod(mut self, cryptoperiod: Option<u64>) -> Self {
+        self.cryptoperiod = cryptoperiod;
+        self
+    }
+
+    /// Sets the key's usage limits: the most USE and STREAM events, the most
+    /// bytes reported, if limited, and the most STREAM events that protect
+    /// data.
+    pub fn with_limits(mut self, max_usage: u64, max_bytes: Option<u64>, max_streams: u64) -> Self {
+        self.set_limits(max_usage, max_bytes, max_streams);
+        self
+    }
+
+    /// Changes the key's usage limits. A key already at or past them expires
+    /// on its next USE, STREAM or DATA event.
+    pub fn set_limits(&mut self, max_usage: u64, max_bytes: Option<u64>, max_streams: u64) {
+        self.max_usage = max_usage;
+        self.max_bytes = max_bytes;
+        self.max_streams = max_streams;
+    }
+
     /// Dispatches the 'GENERATE' event.
     /// Allowed only in the `Empty` state.
     pub fn on_generate(&mut self) -> Result<(), FsmError> {
@@ -59,51 +153,223 @@ impl CiemFsm {
         }
     }
 
-    /// Dispatches the 'BIND' event.
+    /// Dispatches the 'BIND' event, moving to `PreActivation`.
     /// Allowed only in the `Created` state.
     pub fn on_bind(&mut self) -> Result<(), FsmError> {
         match self.state {
             KeyState::Created => {
-                self.state = KeyState::Bound;
+                self.state = KeyState::PreActivation;
                 Ok(())
             }
             _ => Err(FsmError::InvalidTransition),
         }
     }
 
-    /// Dispatches the 'AUTHORIZE' event.
-    /// Allowed only in the `Bound` state.
-    pub fn on_authorize(&mut self) -> Result<(), FsmError> {
+    /// Dispatches the 'ACTIVATE' event at `epoch`, moving to `Bound`. The
+    /// cryptoperiod starts at `epoch`.
+    /// Allowed only in the `PreActivation` state.
+    pub fn on_activate(&mut self, epoch: u64) -> Result<(), FsmError> {
         match self.state {
-            KeyState::Bound => {
+            KeyState::PreActivation => {
+                self.advance(epoch);
+                self.state = KeyState::Bound;
+                self.activated_at = Some(epoch);
+                Ok(())
+            }
+            _ => Err(FsmError::InvalidTransition),
+        }
+    }
+
+    /// Dispatches the 'AUTHORIZE' event, issuing one more capability.
+    /// Allowed in the `Bound` and `Active` states, and for `Process` also in
+    /// the `Deactivated`, `Compromised` and `Expired` states, which it does
+    /// not leave.
+    pub fn on_authorize(&mut self, usage: KeyUsage, epoch: u64) -> Result<(), FsmError> {
+        self.advance(epoch);
+        self.check_expiry(usage)?;
+        match (self.state, usage) {
+            (KeyState::Bound | KeyState::Active, _) => {
                 self.state = KeyState::Active;
+                self.outstanding += 1;
+                Ok(())
+            }
+            (
+                KeyState::Deactivated | KeyState::Compromised | KeyState::Expired,
+                KeyUsage::Process,
+            ) => {
+                self.outstanding += 1;
+                Ok(())
+            }
+            _ => Err(FsmError::InvalidTransition),
+        }
+    }
+
+    /// Dispatches the 'RELEASE' event, giving back one capability.
+    /// Allowed in any state with a capability outstanding. An `Active` key
+    /// returns to `Bound` with the last capability.
+    pub fn on_release(&mut self) -> Result<(), FsmError> {
+        if self.outstanding == 0 {
+            return Err(FsmError::InvalidTransition);
+        }
+        self.outstanding -= 1;
+        if self.state == KeyState::Active && self.outstanding == 0 {
+            self.state = KeyState::Bound;
+        }
+        Ok(())
+    }
+
+    /// Dispatches the 'USE' event at `epoch`, for one operation on `bytes`
+    /// of input. Allowed in the `Active` state, where it counts against the
+    /// usage limits, and for `Process` also in the `Deactivated`,
+    /// `Compromised` and `Expired` states.
+    pub fn on_use(&mut self, usage: KeyUsage, bytes: u64, epoch: u64) -> Result<(), FsmError> {
+        self.invoke(usage, bytes, 0, epoch)
+    }
+
+    /// Dispatches the 'STREAM' event at `epoch`, starting a stream. Allowed
+    /// in the same states as 'USE', and counted like it; a stream that
+    /// protects data also counts against the stream limit, since every such
+    /// stream draws a random nonce prefix. Its segments are reported with
+    /// 'DATA'.
+    pub fn on_stream(&mut self, usage: KeyUsage, epoch: u64) -> Result<(), FsmError> {
+        let streams = u64::from(usage == KeyUsage::Protect);
+        self.invoke(usage, 0, streams, epoch)
+    }
+
+    fn invoke(
+        &mut self,
+        usage: KeyUsage,
+        bytes: u64,
+        streams: u64,
+        epoch: u64,
+    ) -> Result<(), FsmError> {
+        self.advance(epoch);
+        self.check_expiry(usage)?;
+        match (self.state, usage) {
+            (KeyState::Active, _) => self.count(1, bytes, streams),
+            (
+                KeyState::Deactivated | KeyState::Compromised | KeyState::Expired,
+                KeyUsage::Process,
+            ) => Ok(()),
+            _ => Err(FsmError::InvalidTransition),
+        }
+    }
+
+    /// Dispatches the 'DATA' event at `epoch`, reporting `bytes` of stream
+    /// input processed under the key. Allowed in the same states as 'USE';
+    /// only counts, and may exhaust the byte limit, in the `Active` state.
+    pub fn on_data(&mut self, usage: KeyUsage, bytes: u64, epoch: u64) -> Result<(), FsmError> {
+        self.advance(epoch);
+        self.check_expiry(usage)?;
+        match (self.state, usage) {
+            (KeyState::Active, _) => self.count(0, bytes, 0),
+            (
+                KeyState::Deactivated | KeyState::Compromised | KeyState::Expired,
+                KeyUsage::Process,
+            ) => Ok(()),
+            _ => Err(FsmError::InvalidTransition),
+        }
+    }
+
+    /// Counts uses, bytes and streams against the limits, all or none. The
+    /// key expires when it reaches a limit, and when it would exceed one,
+    /// which fails.
+    fn count(&mut self, uses: u64, bytes: u64, streams: u64) -> Result<(), FsmError> {
+        let usage = self.usage.saturating_add(uses);
+        let total = self.bytes.saturating_add(bytes);
+        let stream_count = self.streams.saturating_add(streams);
+        if self.exhausted()
+            || usage > self.max_usage
+            || self.max_bytes.is_some_and(|max| total > max)
+            || stream_count > self.max_streams
+        {
+            self.state = KeyState::Expired;
+            return Err(FsmError::UsageExceeded);
+        }
+        self.usage = usage;
+        self.bytes = total;
+        self.streams = stream_count;
+        if self.exhausted() {
+            self.state = KeyState::Expired;
+        }
+        Ok(())
+    }
+
+    /// Dispatches the 'SUSPEND' event.
+    /// Allowed only in the `Bound` and `Active` states.
+    pub fn on_suspend(&mut self) -> Result<(), FsmError> {
+        match self.state {
+            KeyState::Bound | KeyState::Active => {
+                self.state = KeyState::Suspended;
                 Ok(())
             }
             _ => Err(FsmError::InvalidTransition),
         }
     }
 
-    /// Dispatches the 'USE' event.
-    /// Allowed only in the `Active` state.
-    pub fn on_use(&mut self) -> Result<(), FsmError> {
+    /// Dispatches the 'RESUME' event, returning to `Active` if capabilities
+    /// are outstanding and to `Bound` otherwise.
+    /// Allowed only in the `Suspended` state.
+    pub fn on_resume(&mut self) -> Result<(), FsmError> {
         match self.state {
-            KeyState::Active if self.usage < Self::MAX_USAGE => {
-                self.usage += 1;
+            KeyState::Suspended => {
+                self.state = if self.outstanding > 0 {
+                    KeyState::Active
+                } else {
+                    KeyState::Bound
+                };
                 Ok(())
             }
-            KeyState::Active => {
-                self.state = KeyState::Expired;
-                Err(FsmError::UsageExceeded)
+            _ => Err(FsmError::InvalidTransition),
+        }
+    }
+
+    /// Dispatches the 'DEACTIVATE' event.
+    /// Allowed only in the `Bound`, `Active` and `Suspended` states.
+    pub fn on_deactivate(&mut self) -> Result<(), FsmError> {
+        match self.state {
+            KeyState::Bound | KeyState::Active | KeyState::Suspended => {
+                self.state = KeyState::Deactivated;
+                Ok(())
             }
             _ => Err(FsmError::InvalidTransition),
         }
     }
 
+    /// Dispatches the 'COMPROMISE' event, moving to `Compromised`.
+    /// Allowed in every state a key that has been activated can be in, except
+    /// `Zeroized`. A `Revoked` key is only marked as compromised and stays
+    /// `Revoked`. Keys that were never activated protected nothing; revoke
+    /// or zeroize them instead.
+    pub fn on_compromise(&mut self) -> Result<(), FsmError> {
+        match self.state {
+            KeyState::Empty | KeyState::Created | KeyState::PreActivation | KeyState::Zeroized => {
+                Err(FsmError::InvalidTransition)
+            }
+            KeyState::Revoked => {
+                self.compromised = true;
+                Ok(())
+            }
+            _ => {
+                self.state = KeyState::Compromised;
+                self.compromised = true;
+                Ok(())
+            }
+        }
+    }
+
     /// Dispatches the 'REVOKE' event.
-    /// Allowed only in the `Active` state.
+    /// Allowed only in the `PreActivation`, `Bound`, `Active`, `Suspended`,
+    /// `Deactivated`, `Compromised` and `Expired` states.
     pub fn on_revoke(&mut self) -> Result<(), FsmError> {
         match self.state {
-            KeyState::Active => {
+            KeyState::PreActivation
+            | KeyState::Bound
+            | KeyState::Active
+            | KeyState::Suspended
+            | KeyState::Deactivated
+            | KeyState::Compromised
+            | KeyState::Expired => {
                 self.state = KeyState::Revoked;
                 Ok(())
             }
@@ -111,18 +377,118 @@ impl CiemFsm {
         }
     }
 
-    /// Dispatches the 'ZEROIZE' event. This can be called from any state
-    /// as a result of a tamper event, but in the PlusCal model it follows
-    /// Revoked or Expired.
+    /// Dispatches the 'ZEROIZE' event. This can be called from any state,
+    /// e.g. as a result of a tamper event.
     pub fn on_zeroize(&mut self) {
         self.state = KeyState::Zeroized;
         self.usage = 0;
+        self.bytes = 0;
+        self.streams = 0;
     }
 
     /// Gets the current state of the FSM.
     pub fn state(&self) -> KeyState {
         self.state
     }
+
+    /// Gets the number of times the key has been used.
+    pub fn usage(&self) -> u64 {
+        self.usage
+    }
+
+    /// Gets the number of bytes the key has processed.
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Gets the number of streams that protected data under the key.
+    pub fn streams(&self) -> u64 {
+        self.streams
+    }
+
+    /// Gets the maximum number of streams that may protect data under the key.
+    pub fn max_streams(&self) -> u64 {
+        self.max_streams
+    }
+
+    /// Returns `true` if the key has reached one of its usage limits.
+    pub fn exhausted(&self) -> bool {
+        self.usage >= self.max_usage
+            || self.max_bytes.is_some_and(|max| self.bytes >= max)
+            || self.streams >= self.max_streams
+    }
+
+    /// Gets the maximum number of times the key may be used.
+    pub fn max_usage(&self) -> u64 {
+        self.max_usage
+    }
+
+    /// Gets the maximum number of bytes the key may process, if limited.
+    pub fn max_bytes(&self) -> Option<u64> {
+        self.max_bytes
+    }
+
+    /// Gets the key's cryptoperiod, in epochs from activation.
+    pub fn cryptoperiod(&self) -> Option<u64> {
+        self.cryptoperiod
+    }
+
+    /// Returns `true` if the key is known or suspected to be compromised,
+    /// including a `Revoked` key marked as compromised.
+    pub fn compromised(&self) -> bool {
+        self.compromised
+    }
+
+    /// Gets the number of capabilities outstanding over the key.
+    pub fn outstanding(&self) -> u32 {
+        self.outstanding
+    }
+
+    /// Gets the epoch the key was activated at, if it has been.
+    pub fn activated_at(&self) -> Option<u64> {
+        self.activated_at
+    }
+
+    /// Gets the epoch the key's cryptoperiod ends at, if it has one and the
+    /// key has been activated.
+    pub fn expires_at(&self) -> Option<u64> {
+        Some(self.activated_at?.saturating_add(self.cryptoperiod?))
+    }
+
+    /// Returns `true` if `epoch` lies within the key's cryptoperiod, i.e. the
+    /// key could have protected data at `epoch`.
+    pub fn covers(&self, epoch: u64) -> bool {
+        self.activated_at.is_some_and(|start| start <= epoch)
+            && self.expires_at().is_none_or(|end| epoch < end)
+    }
+
+    /// Records `epoch` and expires a `Bound` or `Active` key whose
+    /// cryptoperiod has ended.
+    fn advance(&mut self, epoch: u64) {
+        self.epoch = self.epoch.max(epoch);
+        if matches!(self.state, KeyState::Bound | KeyState::Active) && self.time_expired() {
+            self.state = KeyState::Expired;
+        }
+    }
+
+    /// Explains why an `Expired` key refuses `Protect`: its cryptoperiod
+    /// ended or it reached a usage limit.
+    fn check_expiry(&self, usage: KeyUsage) -> Result<(), FsmError> {
+        if self.state == KeyState::Expired && usage == KeyUsage::Protect {
+            if self.time_expired() {
+                return Err(FsmError::CryptoperiodEnded);
+            }
+            if self.exhausted() {
+                return Err(FsmError::UsageExceeded);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns `true` if the cryptoperiod has ended by the latest epoch seen.
+    fn time_expired(&self) -> bool {
+        self.expires_at().is_some_and(|end| self.epoch >= end)
+    }
 }
 
 impl Default for CiemFsm {
@@ -130,3 +496,240 @@ impl Default for CiemFsm {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An activated key with a cryptoperiod of 100 epochs from epoch 0.
+    fn bound() -> CiemFsm {
+        let mut fsm = CiemFsm::new().with_cryptoperiod(Some(100));
+        fsm.on_generate().unwrap();
+        fsm.on_bind().unwrap();
+        fsm.on_activate(0).unwrap();
+        fsm
+    }
+
+    /// A key with one capability outstanding.
+    fn active() -> CiemFsm {
+        let mut fsm = bound();
+        fsm.on_authorize(KeyUsage::Protect, 0).unwrap();
+        fsm
+    }
+
+    fn in_state(state: KeyState) -> CiemFsm {
+        let mut fsm = active();
+        match state {
+            KeyState::Active => {}
+            KeyState::Bound => fsm.on_release().unwrap(),
+            KeyState::Suspended => fsm.on_suspend().unwrap(),
+            KeyState::Deactivated => fsm.on_deactivate().unwrap(),
+            KeyState::Compromised => fsm.on_compromise().unwrap(),
+            KeyState::Expired => fsm.advance(100),
+            KeyState::Revoked => fsm.on_revoke().unwrap(),
+            KeyState::Zeroized => fsm.on_zeroize(),
+            KeyState::Empty | KeyState::Created | KeyState::PreActivation => unreachable!(),
+        }
+        assert_eq!(fsm.state(), state);
+        fsm
+    }
+
+    #[test]
+    fn keys_activate_through_their_lifecycle() {
+        let mut fsm = CiemFsm::new();
+        assert_eq!(fsm.on_bind(), Err(FsmError::InvalidTransition));
+        fsm.on_generate().unwrap();
+        assert_eq!(fsm.state(), KeyState::Created);
+        assert_eq!(fsm.on_activate(0), Err(FsmError::InvalidTransition));
+        fsm.on_bind().unwrap();
+        assert_eq!(fsm.state(), KeyState::PreActivation);
+        assert_eq!(
+            fsm.on_authorize(KeyUsage::Protect, 0),
+            Err(FsmError::InvalidTransition)
+        );
+        fsm.on_activate(5).unwrap();
+        assert_eq!(fsm.state(), KeyState::Bound);
+        assert_eq!(fsm.activated_at(), Some(5));
+    }
+
+    #[test]
+    fn capabilities_move_keys_between_bound_and_active() {
+        let mut fsm = bound();
+        fsm.on_authorize(KeyUsage::Protect, 1).unwrap();
+        fsm.on_authorize(KeyUsage::Process, 1).unwrap();
+        assert_eq!((fsm.state(), fsm.outstanding()), (KeyState::Active, 2));
+        fsm.on_release().unwrap();
+        assert_eq!(fsm.state(), KeyState::Active);
+        fsm.on_release().unwrap();
+        assert_eq!((fsm.state(), fsm.outstanding()), (KeyState::Bound, 0));
+        assert_eq!(fsm.on_release(), Err(FsmError::InvalidTransition));
+    }
+
+    #[test]
+    fn suspended_keys_resume_where_they_were() {
+        let mut fsm = in_state(KeyState::Suspended);
+        assert_eq!(
+            fsm.on_use(KeyUsage::Process, 0, 1),
+            Err(FsmError::InvalidTransition)
+        );
+        fsm.on_resume().unwrap();
+        assert_eq!(fsm.state(), KeyState::Active);
+
+        let mut fsm = bound();
+        fsm.on_suspend().unwrap();
+        fsm.on_resume().unwrap();
+        assert_eq!(fsm.state(), KeyState::Bound);
+    }
+
+    #[test]
+    fn compromise_requires_an_activated_key() {
+        let mut fsm = CiemFsm::new();
+        assert_eq!(fsm.on_compromise(), Err(FsmError::InvalidTransition));
+        fsm.on_generate().unwrap();
+        assert_eq!(fsm.on_compromise(), Err(FsmError::InvalidTransition));
+        fsm.on_bind().unwrap();
+        assert_eq!(fsm.on_compromise(), Err(FsmError::InvalidTransition));
+        assert!(!fsm.compromised());
+        assert_eq!(
+            in_state(KeyState::Zeroized).on_compromise(),
+            Err(FsmError::InvalidTransition)
+        );
+
+        for state in [
+            KeyState::Bound,
+            KeyState::Active,
+            KeyState::Suspended,
+            KeyState::Deactivated,
+            KeyState::Expired,
+        ] {
+            let mut fsm = in_state(state);
+            fsm.on_compromise().unwrap();
+            assert_eq!(fsm.state(), KeyState::Compromised, "{state:?}");
+            assert!(fsm.compromised());
+        }
+    }
+
+    #[test]
+    fn compromised_keys_only_process() {
+        let mut fsm = in_state(KeyState::Compromised);
+        assert_eq!(fsm.on_use(KeyUsage::Process, 0, 1), Ok(()));
+        assert_eq!(
+            fsm.on_use(KeyUsage::Protect, 0, 1),
+            Err(FsmError::InvalidTransition)
+        );
+        assert_eq!(
+            fsm.on_authorize(KeyUsage::Protect, 1),
+            Err(FsmError::InvalidTransition)
+        );
+        assert_eq!(fsm.on_resume(), Err(FsmError::InvalidTransition));
+    }
+
+    #[test]
+    fn revocation_is_accepted_from_every_usable_state() {
+        for state in [
+            KeyState::Bound,
+            KeyState::Active,
+            KeyState::Suspended,
+            KeyState::Deactivated,
+            KeyState::Compromised,
+            KeyState::Expired,
+        ] {
+            let mut fsm = in_state(state);
+            fsm.on_revoke().unwrap();
+            assert_eq!(fsm.state(), KeyState::Revoked, "{state:?}");
+        }
+        let mut fsm = CiemFsm::new();
+        fsm.on_generate().unwrap();
+        assert_eq!(fsm.on_revoke(), Err(FsmError::InvalidTransition));
+        fsm.on_bind().unwrap();
+        assert_eq!(fsm.on_revoke(), Ok(()));
+    }
+
+    #[test]
+    fn revoked_is_terminal() {
+        let mut fsm = in_state(KeyState::Revoked);
+        assert_eq!(fsm.on_revoke(), Err(FsmError::InvalidTransition));
+        assert_eq!(fsm.on_suspend(), Err(FsmError::InvalidTransition));
+        assert_eq!(fsm.on_resume(), Err(FsmError::InvalidTransition));
+        assert_eq!(fsm.on_deactivate(), Err(FsmError::InvalidTransition));
+        assert_eq!(fsm.on_activate(1), Err(FsmError::InvalidTransition));
+
+        // Marking a revoked key as compromised does not make it usable again.
+        fsm.on_compromise().unwrap();
+        assert_eq!(fsm.state(), KeyState::Revoked);
+        assert!(fsm.compromised());
+        for usage in [KeyUsage::Protect, KeyUsage::Process] {
+            assert_eq!(fsm.on_authorize(usage, 1), Err(FsmError::InvalidTransition));
+            assert_eq!(fsm.on_use(usage, 0, 1), Err(FsmError::InvalidTransition));
+            assert_eq!(fsm.on_data(usage, 1, 1), Err(FsmError::InvalidTransition));
+        }
+
+        fsm.on_zeroize();
+        assert_eq!(fsm.state(), KeyState::Zeroized);
+    }
+
+    #[test]
+    fn keys_expire_at_the_end_of_their_cryptoperiod() {
+        let mut fsm = active();
+        fsm.on_use(KeyUsage::Protect, 0, 99).unwrap();
+        assert_eq!(
+            fsm.on_use(KeyUsage::Protect, 0, 100),
+            Err(FsmError::CryptoperiodEnded)
+        );
+        assert_eq!(fsm.state(), KeyState::Expired);
+        assert_eq!(fsm.on_use(KeyUsage::Process, 0, 100), Ok(()));
+        assert!(fsm.covers(99) && !fsm.covers(100));
+
+        let mut fsm = active();
+        fsm.on_data(KeyUsage::Protect, 1, 99).unwrap();
+        assert_eq!(
+            fsm.on_data(KeyUsage::Protect, 1, 100),
+            Err(FsmError::CryptoperiodEnded)
+        );
+        assert_eq!(fsm.on_data(KeyUsage::Process, 1, 100), Ok(()));
+    }
+
+    #[test]
+    fn keys_expire_on_the_event_that_reaches_a_limit() {
+        let mut fsm = active();
+        fsm.set_limits(3, Some(10), u64::MAX);
+        fsm.on_use(KeyUsage::Protect, 4, 1).unwrap();
+        fsm.on_data(KeyUsage::Protect, 6, 1).unwrap();
+        assert!(fsm.exhausted());
+        assert_eq!(fsm.state(), KeyState::Expired);
+        assert_eq!(
+            fsm.on_use(KeyUsage::Protect, 0, 1),
+            Err(FsmError::UsageExceeded)
+        );
+        assert_eq!(fsm.on_use(KeyUsage::Process, 1, 1), Ok(()));
+        assert_eq!((fsm.usage(), fsm.bytes()), (1, 10));
+    }
+
+    #[test]
+    fn events_that_would_exceed_a_limit_count_nothing() {
+        let mut fsm = active();
+        fsm.set_limits(3, Some(10), u64::MAX);
+        fsm.on_use(KeyUsage::Protect, 4, 1).unwrap();
+        assert_eq!(
+            fsm.on_use(KeyUsage::Protect, 7, 1),
+            Err(FsmError::UsageExceeded)
+        );
+        assert_eq!(fsm.state(), KeyState::Expired);
+        assert_eq!((fsm.usage(), fsm.bytes()), (1, 4));
+    }
+
+    #[test]
+    fn only_streams_that_protect_data_count_as_streams() {
+        let mut fsm = active();
+        fsm.set_limits(u64::MAX, None, 1);
+        fsm.on_stream(KeyUsage::Process, 1).unwrap();
+        assert_eq!((fsm.usage(), fsm.streams()), (1, 0));
+        fsm.on_stream(KeyUsage::Protect, 1).unwrap();
+        assert_eq!((fsm.usage(), fsm.streams()), (2, 1));
+        assert_eq!(fsm.state(), KeyState::Expired);
+        assert_eq!(
+            fsm.on_stream(KeyUsage::Protect, 1),
+            Err(FsmError::UsageExceeded)
+        );
+    }
+}
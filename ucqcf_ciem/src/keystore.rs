@@ -0,0 +1,110 @@
+// ucqcf_ciem/src/keystore.rs
+
+//! The CIEM's keystore: every key it holds, each with its own lifecycle FSM.
+
+use crate::fsm_generated::{CiemFsm, KeyState};
+use crate::limits::{KeyLimits, LimitKind};
+use std::collections::HashMap;
+use ucqcf_core::CryptoError;
+use ucqcf_core::handles::KeyHandle;
+use ucqcf_core::key::KeyAttributes;
+
+/// A key held by the CIEM.
+pub(crate) struct KeyEntry {
+    pub(crate) fsm: CiemFsm,
+    /// The key material, or `None` once the key has been zeroized.
+    pub(crate) material: Option<[u8; 32]>,
+    pub(crate) attributes: KeyAttributes,
+    /// The limits the FSM enforces, plus the warning threshold and message bound.
+    pub(crate) limits: KeyLimits,
+    /// The limits the usage hook has already been warned about.
+    pub(crate) warned: Vec<LimitKind>,
+    /// The key generated to replace this one when it reached a limit.
+    pub(crate) successor: Option<KeyHandle>,
+}
+
+impl KeyEntry {
+    /// Returns the limits whose warning threshold the key has newly crossed,
+    /// with the usage and the limit, and records them as warned about.
+    pub(crate) fn new_warnings(&mut self) -> Vec<(LimitKind, u64, u64)> {
+        let usage = [
+            (
+                LimitKind::Invocations,
+                self.fsm.usage(),
+                Some(self.fsm.max_usage()),
+            ),
+            (LimitKind::Bytes, self.fsm.bytes(), self.fsm.max_bytes()),
+            (
+                LimitKind::Streams,
+                self.fsm.streams(),
+                Some(self.fsm.max_streams()),
+            ),
+        ];
+        let mut warnings = Vec::new();
+        for (kind, used, limit) in usage {
+            if let Some(limit) = limit
+                && used >= self.limits.warning_threshold(limit)
+                && !self.warned.contains(&kind)
+            {
+                self.warned.push(kind);
+                warnings.push((kind, used, limit));
+            }
+        }
+        warnings
+    }
+
+    /// Wipes the key material and moves the FSM to `Zeroized`.
+    pub(crate) fn zeroize(&mut self) {
+        self.fsm.on_zeroize();
+        self.material = None;
+    }
+
+    /// Returns the limit an exhausted key reached.
+    pub(crate) fn reached_limit(&self) -> LimitKind {
+        if self.fsm.usage() >= self.fsm.max_usage() {
+            LimitKind::Invocations
+        } else if self.fsm.streams() >= self.fsm.max_streams() {
+            LimitKind::Streams
+        } else {
+            LimitKind::Bytes
+        }
+    }
+}
+
+/// The keys held by a CIEM, addressed by `KeyHandle`.
+#[derive(Default)]
+pub(crate) struct KeyStore {
+    entries: HashMap<KeyHandle, KeyEntry>,
+}
+
+impl KeyStore {
+    pub(crate) fn insert(&mut self, handle: KeyHandle, entry: KeyEntry) {
+        self.entries.insert(handle, entry);
+    }
+
+    pub(crate) fn get(&self, handle: &KeyHandle) -> Result<&KeyEntry, CryptoError> {
+        self.entries.get(handle).ok_or(CryptoError::KeyNotFound)
+    }
+
+    pub(crate) fn get_mut(&mut self, handle: &KeyHandle) -> Result<&mut KeyEntry, CryptoError> {
+        self.entries.get_mut(handle).ok_or(CryptoError::KeyNotFound)
+    }
+
+    /// Returns the handles of all keys, including zeroized ones.
+    pub(crate) fn handles(&self) -> impl Iterator<Item = &KeyHandle> {
+        self.entries.keys()
+    }
+
+    /// Zeroizes every key, e.g. on tamper, and returns the keys that were
+    /// not zeroized yet with the state each was in.
+    pub(crate) fn zeroize_all(&mut self) -> Vec<(KeyHandle, KeyState)> {
+        let mut zeroized = Vec::new();
+        for (handle, entry) in &mut self.entries {
+            if entry.fsm.state() != KeyState::Zeroized {
+                zeroized.push((handle.clone(), entry.fsm.state()));
+            }
+            entry.zeroize();
+        }
+        zeroized
+    }
+}
@@ -1,85 +1,566 @@
 // ucqcf_ciem/src/lib.rs
 
-// Declare the new, generated FSM module.
+// The key lifecycle FSM, hand-written despite its module name.
 pub mod fsm_generated;
 
-// These modules remain as they are.
 pub mod entropy;
+mod keystore;
+pub mod limits;
+pub mod stream;
+#[cfg(test)]
+mod tests;
 pub mod time;
 
 use crate::entropy::EntropyAggregator;
+use crate::keystore::{KeyEntry, KeyStore};
+use crate::limits::{KeyLimits, KeyUsageHook};
+use crate::stream::{PREFIX_LEN, StreamDecryptor, StreamEncryptor};
 use std::cell::RefCell;
 // Import the new FSM and its error type.
-use crate::fsm_generated::{CiemFsm, FsmError};
+use crate::fsm_generated::{CiemFsm, FsmError, KeyState, KeyUsage};
 use crate::time::SecureClock;
-use ucqcf_core::CryptoError;
-use ucqcf_core::capability::CryptographicCapability;
-use ucqcf_core::profile::SecurityProfile;
-use ucqcf_mock_hw::clock::{ClockSource, MockClassicalOscillator};
+use ucqcf_core::{CryptoError, ErrorCause, HardwareFault};
+use ucqcf_core::algorithm::AlgorithmId;
+use ucqcf_core::capability::{
+    AeadCapability, CapabilityStream, CryptographicCapability, StreamingCapability,
+};
+use ucqcf_core::handles::KeyHandle;
+use ucqcf_core::interfaces::{KeyManager, ModuleEventSink};
+use ucqcf_core::key::KeyAttributes;
+use ucqcf_core::profile::{DataClassification, SecurityProfile};
+use ucqcf_core::request::Operation;
+use ucqcf_core::suite::CipherSuite;
+use ucqcf_mock_hw::clock::{ClockClass, ClockSource, MockClassicalOscillator};
 use ucqcf_mock_hw::rng::MockTRNG;
 
-/// The CIEM struct, now using the `EntropyAggregator`.
+/// The Cryptographic Information and Entropy Module: owns the keys and their
+/// lifecycles, and issues the capabilities that use them.
 pub struct CIEM<'a> {
-    fsm: RefCell<CiemFsm>,
+    keys: RefCell<KeyStore>,
     entropy: EntropyAggregator<'a>,
     clock: RefCell<SecureClock<'a>>,
     tamper: RefCell<bool>,
-    key: RefCell<Option<[u8; 32]>>,
+    usage_hook: Option<Box<dyn KeyUsageHook + 'a>>,
+    event_sink: Option<Box<dyn ModuleEventSink + 'a>>,
 }
 
 impl<'a> CIEM<'a> {
-    /// Creates a new CIEM instance.
+    /// Creates a new CIEM instance with an empty keystore.
     pub fn new(
         entropy_aggregator: EntropyAggregator<'a>,
         clock_source: Box<dyn ClockSource + 'a>,
     ) -> Result<Self, CryptoError> {
-        let mut fsm = CiemFsm::new();
-        let key = RefCell::new(None);
-
-        // Generate the key when the FSM enters the `Created` state.
-        fsm.on_generate().unwrap();
-        *key.borrow_mut() = Some(entropy_aggregator.get_entropy()?);
-
-        // Bind the key.
-        fsm.on_bind().unwrap();
-
         Ok(Self {
-            fsm: RefCell::new(fsm),
+            keys: RefCell::new(KeyStore::default()),
             entropy: entropy_aggregator,
             clock: RefCell::new(SecureClock::new(clock_source)),
             tamper: RefCell::new(false),
-            key,
+            usage_hook: None,
+            event_sink: None,
         })
     }
 
-    /// The API for requesting a capability, now uses the generated FSM's events.
+    /// Sets the hook told when a key nears or reaches one of its usage limits.
+    pub fn set_usage_hook(&mut self, hook: Box<dyn KeyUsageHook + 'a>) {
+        self.usage_hook = Some(hook);
+    }
+
+    /// Sets the sink told about every key state change and tamper event,
+    /// e.g. `CoreEngine::audit_sink` to audit them.
+    pub fn set_event_sink(&mut self, sink: Box<dyn ModuleEventSink + 'a>) {
+        self.event_sink = Some(sink);
+    }
+
+    /// Tells the event sink that `key` moved from `from` to `to`, if it did.
+    fn report_transition(&self, key: &KeyHandle, from: KeyState, to: KeyState) {
+        if from != to && let Some(sink) = &self.event_sink {
+            sink.fsm_transition(Some(key), &format!("{from:?}"), &format!("{to:?}"));
+        }
+    }
+
+    /// Runs `f` on the entry of `key`, then reports the state change it made.
+    fn with_entry<T>(&self, key: &KeyHandle, f: impl FnOnce(&mut KeyEntry) -> T) -> Result<T, CryptoError> {
+        let (result, from, to) = {
+            let mut keys = self.keys.borrow_mut();
+            let entry = keys.get_mut(key)?;
+            let from = entry.fsm.state();
+            let result = f(entry);
+            (result, from, entry.fsm.state())
+        };
+        self.report_transition(key, from, to);
+        Ok(result)
+    }
+
+    /// Generates a key for `algorithm`, which must be an AEAD the CIEM
+    /// supports and `profile` permits, binds it and activates it. The key
+    /// gets its own FSM, with the profile's `key_cryptoperiod` and usage
+    /// limits (see `limits`); the returned handle carries its attributes.
+    pub fn generate_key(
+        &self,
+        algorithm: AlgorithmId,
+        profile: &SecurityProfile,
+    ) -> Result<KeyHandle, CryptoError> {
+        self.insert_key(algorithm, profile, true)
+    }
+
+    /// Like `generate_key`, but leaves the key in `PreActivation` until
+    /// `activate_key` is called. Its cryptoperiod starts on activation.
+    pub fn generate_inactive_key(
+        &self,
+        algorithm: AlgorithmId,
+        profile: &SecurityProfile,
+    ) -> Result<KeyHandle, CryptoError> {
+        self.insert_key(algorithm, profile, false)
+    }
+
+    fn insert_key(
+        &self,
+        algorithm: AlgorithmId,
+        profile: &SecurityProfile,
+        activate: bool,
+    ) -> Result<KeyHandle, CryptoError> {
+        if *self.tamper.borrow() {
+            return Err(CryptoError::hardware(HardwareFault::Tamper));
+        }
+        if aead_algorithm(algorithm).is_none() {
+            return Err(CryptoError::UnsupportedAlgorithm);
+        }
+        self.check_environment(profile)?;
+        if !profile.permits(algorithm) {
+            return Err(CryptoError::AuthorizationFailed);
+        }
+
+        let limits = KeyLimits::from_profile(profile, algorithm);
+        self.create_key(algorithm, limits, profile.key_cryptoperiod, activate)
+    }
+
+    /// Generates and stores a key, once the request has been checked.
+    fn create_key(
+        &self,
+        algorithm: AlgorithmId,
+        limits: KeyLimits,
+        cryptoperiod: Option<u64>,
+        activate: bool,
+    ) -> Result<KeyHandle, CryptoError> {
+        let mut fsm = CiemFsm::new()
+            .with_cryptoperiod(cryptoperiod)
+            .with_limits(limits.max_invocations, limits.max_bytes, limits.max_streams);
+        let epoch = self.clock.borrow_mut().tick();
+
+        // Generate the key when the FSM enters the `Created` state.
+        fsm.on_generate()?;
+        let material = self.entropy.get_entropy()?;
+
+        // Bind the key.
+        fsm.on_bind()?;
+        if activate {
+            fsm.on_activate(epoch)?;
+        }
+
+        let mut attributes = KeyAttributes::new(algorithm, epoch);
+        attributes.expires_at = fsm.expires_at();
+        attributes.origin_hardware = Some(HARDWARE_NAME.to_string());
+        let handle = KeyHandle::new().with_attributes(attributes.clone());
+        let state = fsm.state();
+        self.keys.borrow_mut().insert(
+            handle.clone(),
+            KeyEntry {
+                fsm,
+                material: Some(material),
+                attributes,
+                limits,
+                warned: Vec::new(),
+                successor: None,
+            },
+        );
+        self.report_transition(&handle, KeyState::Empty, state);
+        Ok(handle)
+    }
+
+    /// Returns the handles of the keys in the keystore, including zeroized ones.
+    pub fn keys(&self) -> Vec<KeyHandle> {
+        self.keys.borrow().handles().cloned().collect()
+    }
+
+    /// Returns the public attributes of `key`.
+    pub fn key_attributes(&self, key: &KeyHandle) -> Result<KeyAttributes, CryptoError> {
+        Ok(self.keys.borrow().get(key)?.attributes.clone())
+    }
+
+    /// Returns the lifecycle state of `key`.
+    pub fn key_state(&self, key: &KeyHandle) -> Result<KeyState, CryptoError> {
+        Ok(self.keys.borrow().get(key)?.fsm.state())
+    }
+
+    /// Returns `true` if `key` was marked as compromised, even if it has been
+    /// revoked since or was revoked at the time.
+    pub fn key_compromised(&self, key: &KeyHandle) -> Result<bool, CryptoError> {
+        Ok(self.keys.borrow().get(key)?.fsm.compromised())
+    }
+
+    /// Returns the number of capabilities outstanding over `key`.
+    pub fn outstanding_capabilities(&self, key: &KeyHandle) -> Result<u32, CryptoError> {
+        Ok(self.keys.borrow().get(key)?.fsm.outstanding())
+    }
+
+    /// Returns the usage limits of `key`.
+    pub fn key_limits(&self, key: &KeyHandle) -> Result<KeyLimits, CryptoError> {
+        Ok(self.keys.borrow().get(key)?.limits)
+    }
+
+    /// Lowers the usage limits of `key` to `limits` wherever they are more
+    /// restrictive, e.g. to apply a policy's bounds. Limits are never raised.
+    pub fn restrict_key_limits(&self, key: &KeyHandle, limits: &KeyLimits) -> Result<(), CryptoError> {
+        let mut keys = self.keys.borrow_mut();
+        let entry = keys.get_mut(key)?;
+        entry.limits = entry.limits.merge(limits);
+        entry.fsm.set_limits(entry.limits.max_invocations, entry.limits.max_bytes, entry.limits.max_streams);
+        Ok(())
+    }
+
+    /// Returns the key generated to replace `key` when it reached a usage
+    /// limit, if it has.
+    pub fn key_successor(&self, key: &KeyHandle) -> Result<Option<KeyHandle>, CryptoError> {
+        Ok(self.keys.borrow().get(key)?.successor.clone())
+    }
+
+    /// Activates a key generated with `generate_inactive_key`, starting its
+    /// cryptoperiod.
+    pub fn activate_key(&self, key: &KeyHandle) -> Result<(), CryptoError> {
+        let epoch = self.clock.borrow_mut().tick();
+        self.with_entry(key, |entry| {
+            entry.fsm.on_activate(epoch)?;
+            entry.attributes.expires_at = entry.fsm.expires_at();
+            Ok(())
+        })?
+    }
+
+    /// Suspends `key`: its capabilities can do nothing until `resume_key`.
+    pub fn suspend_key(&self, key: &KeyHandle) -> Result<(), CryptoError> {
+        self.transition(key, CiemFsm::on_suspend)
+    }
+
+    /// Resumes a suspended key.
+    pub fn resume_key(&self, key: &KeyHandle) -> Result<(), CryptoError> {
+        self.transition(key, CiemFsm::on_resume)
+    }
+
+    /// Deactivates `key`: it no longer encrypts, but still decrypts data it
+    /// protected, through new capabilities as well as outstanding ones.
+    pub fn deactivate_key(&self, key: &KeyHandle) -> Result<(), CryptoError> {
+        self.transition(key, CiemFsm::on_deactivate)
+    }
+
+    /// Marks `key` as known or suspected to be compromised. Like a
+    /// deactivated key, it only decrypts from then on; a revoked key stays
+    /// revoked. Keys that were never activated cannot be compromised.
+    pub fn compromise_key(&self, key: &KeyHandle) -> Result<(), CryptoError> {
+        self.transition(key, CiemFsm::on_compromise)
+    }
+
+    /// Revokes `key`: its capabilities, outstanding or new, can do nothing more.
+    pub fn revoke_key(&self, key: &KeyHandle) -> Result<(), CryptoError> {
+        self.transition(key, CiemFsm::on_revoke)
+    }
+
+    /// Zeroizes `key`, wiping its material: nothing can use it again. The
+    /// CIEM's other keys are left alone.
+    pub fn zeroize_key(&self, key: &KeyHandle) -> Result<(), CryptoError> {
+        self.with_entry(key, KeyEntry::zeroize)
+    }
+
+    /// Dispatches a lifecycle event to the FSM of `key`.
+    fn transition(
+        &self,
+        key: &KeyHandle,
+        event: impl FnOnce(&mut CiemFsm) -> Result<(), FsmError>,
+    ) -> Result<(), CryptoError> {
+        self.with_entry(key, |entry| event(&mut entry.fsm))??;
+        Ok(())
+    }
+
+    /// Issues a capability to encrypt under `key` with the key's AEAD, which
+    /// the profile must permit, moving a `Bound` key to `Active`. Refused if
+    /// the CIEM cannot satisfy the profile (see `check_profile`). Any number
+    /// of capabilities may be outstanding over a key; dropping a capability
+    /// releases it.
     pub fn request_encrypt_capability<'c>(
         &'c self,
-        _profile: &SecurityProfile, // Profile is not used by the new FSM's on_authorize event.
+        key: &KeyHandle,
+        profile: &SecurityProfile,
     ) -> Result<EncryptCapability<'c, 'a>, CryptoError> {
-        // Map the FSM error to the core CryptoError.
-        self.fsm.borrow_mut().on_authorize().map_err(|e| match e {
-            FsmError::InvalidTransition => CryptoError::FsmInvalidTransition,
-            _ => CryptoError::InvalidState, // Should not happen with on_authorize
-        })?;
-        Ok(EncryptCapability { ciem: self })
+        let algorithm = self.authorize(key, profile, Operation::Encrypt)?;
+        Ok(EncryptCapability {
+            ciem: self,
+            key: key.clone(),
+            algorithm,
+            required_aad: None,
+            uses: RefCell::new(0),
+        })
     }
 
     /// A function to simulate a hardware tamper event.
     pub fn inject_tamper(&self) {
         self.tamper.replace(true);
-        // Use the new FSM's zeroize event.
-        self.fsm.borrow_mut().on_zeroize();
-        // Securely wipe the key.
-        *self.key.borrow_mut() = None;
+        if let Some(sink) = &self.event_sink {
+            sink.tamper_event(HARDWARE_NAME);
+        }
+        // Use the new FSM's zeroize event and securely wipe every key.
+        let zeroized = self.keys.borrow_mut().zeroize_all();
+        for (key, from) in zeroized {
+            self.report_transition(&key, from, KeyState::Zeroized);
+        }
     }
 
     pub fn request_decrypt_capability<'c>(
         &'c self,
-        _profile: &SecurityProfile,
+        key: &KeyHandle,
+        profile: &SecurityProfile,
     ) -> Result<DecryptCapability<'c, 'a>, CryptoError> {
-        self.fsm.borrow_mut().on_authorize().map_err(|_| CryptoError::InvalidState)?;
-        Ok(DecryptCapability { ciem: self })
+        let algorithm = self.authorize(key, profile, Operation::Decrypt)?;
+        Ok(DecryptCapability {
+            ciem: self,
+            key: key.clone(),
+            algorithm,
+            required_aad: None,
+            uses: RefCell::new(0),
+        })
+    }
+
+    /// Authorizes `operation` under `key` and returns the key's algorithm.
+    /// The key must allow the operation, the profile its algorithm, and the
+    /// key's state its usage.
+    fn authorize(
+        &self,
+        key: &KeyHandle,
+        profile: &SecurityProfile,
+        operation: Operation,
+    ) -> Result<AlgorithmId, CryptoError> {
+        self.check_environment(profile)?;
+
+        self.with_entry(key, |entry| {
+            let algorithm = entry.attributes.algorithm;
+            if !entry.attributes.allows(operation) || !profile.permits(algorithm) {
+                return Err(CryptoError::AuthorizationFailed);
+            }
+            entry.fsm.on_authorize(key_usage(operation), self.clock.borrow_mut().tick())?;
+            Ok(algorithm)
+        })?
+    }
+
+    /// Gives back a capability over `key`, returning the key to `Bound` when
+    /// it was the last one.
+    fn release(&self, key: &KeyHandle) -> Result<(), CryptoError> {
+        self.transition(key, CiemFsm::on_release)
+    }
+
+    /// Counts one use of `key`, against the key and in the capability's
+    /// `uses`, and returns it, ready for its AEAD, with the current epoch.
+    /// The key's state, cryptoperiod and usage limits must still allow
+    /// `usage`, and a message must fit the AEAD's message bound.
+    fn use_key(
+        &self,
+        key: &KeyHandle,
+        usage: KeyUsage,
+        invocation: Invocation,
+        uses: &RefCell<u32>,
+    ) -> Result<(aead::LessSafeKey, u64), CryptoError> {
+        if *self.tamper.borrow() {
+            return Err(CryptoError::hardware(HardwareFault::Tamper));
+        }
+
+        let limits = self.keys.borrow().get(key)?.limits;
+        if let Invocation::Message(bytes) = invocation
+            && limits.max_message_bytes.is_some_and(|max| bytes as u64 > max)
+        {
+            return Err(CryptoError::MalformedInput);
+        }
+
+        let epoch = self.clock.borrow_mut().tick();
+        self.meter(key, |fsm| match invocation {
+            Invocation::Message(bytes) => fsm.on_use(usage, bytes as u64, epoch),
+            Invocation::Stream => fsm.on_stream(usage, epoch),
+        })?;
+
+        let keys = self.keys.borrow();
+        let entry = keys.get(key)?;
+        let material = entry.material.as_ref().ok_or(CryptoError::KeyNotFound)?;
+
+        let algorithm = aead_algorithm(entry.attributes.algorithm).ok_or(CryptoError::UnsupportedAlgorithm)?;
+        let unbound_key = aead::UnboundKey::new(algorithm, material).map_err(|_| CryptoError::InvalidState)?;
+        *uses.borrow_mut() += 1;
+        Ok((aead::LessSafeKey::new(unbound_key), epoch))
+    }
+
+    /// Counts `bytes` of stream input against `key`, at the current epoch,
+    /// so a stream stops protecting data when the key's cryptoperiod ends.
+    pub(crate) fn meter_data(&self, key: &KeyHandle, usage: KeyUsage, bytes: usize) -> Result<(), CryptoError> {
+        let epoch = self.clock.borrow_mut().tick();
+        self.meter(key, |fsm| fsm.on_data(usage, bytes as u64, epoch))
+    }
+
+    /// Dispatches a usage event to the FSM of `key`, then tells the usage
+    /// hook about limits the key has come near to. If the event reached or
+    /// would have exceeded a limit, expiring the key, generates its successor
+    /// and tells the hook about that too.
+    fn meter(
+        &self,
+        key: &KeyHandle,
+        event: impl FnOnce(&mut CiemFsm) -> Result<(), FsmError>,
+    ) -> Result<(), CryptoError> {
+        let (result, warnings, reached) = self.with_entry(key, |entry| {
+            let was_expired = entry.fsm.state() == KeyState::Expired;
+            let result = event(&mut entry.fsm);
+            let exhausted = match &result {
+                Ok(()) => entry.fsm.exhausted(),
+                Err(error) => *error == FsmError::UsageExceeded,
+            };
+            let reached = (!was_expired && entry.fsm.state() == KeyState::Expired && exhausted)
+                .then(|| entry.reached_limit());
+            (result, entry.new_warnings(), reached)
+        })?;
+
+        if let Some(hook) = &self.usage_hook {
+            for (kind, used, limit) in warnings {
+                hook.near_limit(key, kind, used, limit);
+            }
+        }
+        if let Some(kind) = reached {
+            let successor = self.rotate(key).ok();
+            if let Some(hook) = &self.usage_hook {
+                hook.limit_reached(key, kind, successor.as_ref());
+            }
+        }
+        result?;
+        Ok(())
+    }
+
+    /// Generates the successor of an exhausted key, with the same algorithm,
+    /// limits and cryptoperiod, and records it against the key.
+    fn rotate(&self, key: &KeyHandle) -> Result<KeyHandle, CryptoError> {
+        let (algorithm, limits, cryptoperiod) = {
+            let keys = self.keys.borrow();
+            let entry = keys.get(key)?;
+            (entry.attributes.algorithm, entry.limits, entry.fsm.cryptoperiod())
+        };
+        let successor = self.create_key(algorithm, limits, cryptoperiod, true)?;
+        self.keys.borrow_mut().get_mut(key)?.successor = Some(successor.clone());
+        Ok(successor)
+    }
+
+    /// Checks that data stamped with epoch `stamp` was protected within the
+    /// cryptoperiod of `key`, and not after `now`.
+    pub(crate) fn check_stamp(&self, key: &KeyHandle, stamp: u64, now: u64) -> Result<(), CryptoError> {
+        if stamp > now || !self.keys.borrow().get(key)?.fsm.covers(stamp) {
+            return Err(CryptoError::OutsideCryptoperiod);
+        }
+        Ok(())
+    }
+
+    /// Checks that the CIEM can satisfy `profile` and returns the AEAD to
+    /// generate keys for:
+    /// - the profile must be self-consistent (`InvalidProfile`);
+    /// - `require_atomic_time` needs an atomic or quantum clock source;
+    /// - data classified `Secret` or above needs at least two independent
+    ///   entropy sources, so no single source determines the key stream;
+    /// - the primary entropy source must have passed its health checks on
+    ///   the latest sample drawn from it;
+    /// - the profile's domain must rank an AEAD that the profile permits and
+    ///   the CIEM supports; the most preferred one is returned.
+    ///
+    /// Unmet requirements are reported as `ProfileUnsatisfied`. Capability
+    /// requests make the same checks, except that the key's own AEAD takes
+    /// the place of the suite's.
+    pub fn check_profile(&self, profile: &SecurityProfile) -> Result<AlgorithmId, CryptoError> {
+        self.check_environment(profile)?;
+
+        CipherSuite::candidates(profile, Operation::Encrypt, None)
+            .into_iter()
+            .find(|algorithm| aead_algorithm(*algorithm).is_some())
+            .ok_or_else(|| {
+                CryptoError::ProfileUnsatisfied(
+                    "the profile permits no AEAD the CIEM supports".to_string(),
+                )
+            })
+    }
+
+    /// Checks the profile itself and the CIEM's clock and entropy sources.
+    fn check_environment(&self, profile: &SecurityProfile) -> Result<(), CryptoError> {
+        profile.validate()?;
+
+        let clock = self.clock.borrow();
+        if profile.require_atomic_time && clock.class() < ClockClass::Atomic {
+            return Err(CryptoError::ProfileUnsatisfied(format!(
+                "atomic time is required but the clock source `{}` is {:?}",
+                clock.source_name(),
+                clock.class()
+            )));
+        }
+
+        if profile.classification >= DataClassification::Secret
+            && self.entropy.source_count() < MIN_CLASSIFIED_ENTROPY_SOURCES
+        {
+            return Err(CryptoError::ProfileUnsatisfied(format!(
+                "{} data requires {} independent entropy sources but the CIEM has {}",
+                profile.classification,
+                MIN_CLASSIFIED_ENTROPY_SOURCES,
+                self.entropy.source_count()
+            )));
+        }
+        self.entropy.health()?;
+        Ok(())
+    }
+}
+
+/// The name the CIEM records as the `origin_hardware` of its keys.
+const HARDWARE_NAME: &str = "ciem";
+
+/// The number of independent entropy sources required for classified data.
+const MIN_CLASSIFIED_ENTROPY_SOURCES: usize = 2;
+
+/// The length of the epoch stamp at the start of every ciphertext.
+pub const STAMP_LEN: usize = 8;
+
+/// The associated data actually authenticated: the epoch stamp, then the
+/// caller's associated data.
+pub(crate) fn stamped_aad(stamp: u64, aad: &[u8]) -> Vec<u8> {
+    let mut stamped = stamp.to_be_bytes().to_vec();
+    stamped.extend_from_slice(aad);
+    stamped
+}
+
+/// Refuses associated data other than the data a capability was restricted to.
+fn check_aad(required_aad: &Option<Vec<u8>>, aad: &[u8]) -> Result<(), CryptoError> {
+    match required_aad {
+        Some(required) if required.as_slice() != aad => Err(CryptoError::AuthorizationFailed),
+        _ => Ok(()),
+    }
+}
+
+/// What one use of a key is for: a one-shot message of the given length, or
+/// a stream, whose segments are metered as they are processed.
+#[derive(Clone, Copy)]
+enum Invocation {
+    Message(usize),
+    Stream,
+}
+
+/// Whether `operation` applies protection or processes protected data.
+fn key_usage(operation: Operation) -> KeyUsage {
+    match operation {
+        Operation::Decrypt | Operation::Verify | Operation::Unwrap | Operation::Decapsulate => {
+            KeyUsage::Process
+        }
+        _ => KeyUsage::Protect,
+    }
+}
+
+/// Maps an AEAD to its `ring` implementation, if the CIEM supports it.
+fn aead_algorithm(algorithm: AlgorithmId) -> Option<&'static aead::Algorithm> {
+    match algorithm {
+        AlgorithmId::Aes256Gcm => Some(&aead::AES_256_GCM),
+        AlgorithmId::ChaCha20Poly1305 => Some(&aead::CHACHA20_POLY1305),
+        _ => None,
     }
 }
 
@@ -87,14 +568,47 @@ use ring::{aead, hmac};
 
 impl From<entropy::EntropyError> for CryptoError {
     fn from(err: entropy::EntropyError) -> Self {
+        CryptoError::Hardware {
+            fault: HardwareFault::Entropy,
+            cause: Some(ErrorCause::new(err)),
+        }
+    }
+}
+
+impl From<FsmError> for CryptoError {
+    fn from(err: FsmError) -> Self {
         match err {
-            entropy::EntropyError::RepetitionCheckFailed
-            | entropy::EntropyError::ProportionCheckFailed => CryptoError::EntropyHealthCheckFailed,
-            entropy::EntropyError::NoSources => CryptoError::InvalidState,
+            FsmError::InvalidTransition => CryptoError::FsmInvalidTransition,
+            FsmError::UsageExceeded => CryptoError::FsmUsageExceeded,
+            FsmError::CryptoperiodEnded => CryptoError::OutsideCryptoperiod,
         }
     }
 }
 
+/// Lets a Core Engine generate CIEM keys and pass a policy's key limits on.
+impl KeyManager for CIEM<'_> {
+    fn generate_key(
+        &self,
+        profile: &SecurityProfile,
+        algorithm: AlgorithmId,
+    ) -> Result<KeyHandle, CryptoError> {
+        CIEM::generate_key(self, algorithm, profile)
+    }
+
+    fn delete_key(&self, key_handle: &KeyHandle) -> Result<(), CryptoError> {
+        self.zeroize_key(key_handle)
+    }
+
+    fn restrict_key_usage(
+        &self,
+        key_handle: &KeyHandle,
+        max_invocations: Option<u64>,
+        max_bytes: Option<u64>,
+    ) -> Result<(), CryptoError> {
+        self.restrict_key_limits(key_handle, &KeyLimits::at_most(max_invocations, max_bytes))
+    }
+}
+
 /// Default implementation uses a default `EntropyAggregator`.
 impl<'a> Default for CIEM<'a> {
     fn default() -> Self {
@@ -104,64 +618,169 @@ impl<'a> Default for CIEM<'a> {
     }
 }
 
-/// The capability struct remains the same.
+/// A capability to encrypt under one of the CIEM's keys.
 pub struct EncryptCapability<'c, 'a> {
     pub(crate) ciem: &'c CIEM<'a>,
+    pub(crate) key: KeyHandle,
+    pub(crate) algorithm: AlgorithmId,
+    pub(crate) required_aad: Option<Vec<u8>>,
+    pub(crate) uses: RefCell<u32>,
 }
 
-/// The capability implementation now uses AES-256-GCM.
-impl<'c, 'a> CryptographicCapability for EncryptCapability<'c, 'a> {
-    fn execute(&self, plaintext: &[u8]) -> Result<Vec<u8>, CryptoError> {
-        if *self.ciem.tamper.borrow() {
-            return Err(CryptoError::InvalidState);
-        }
+impl EncryptCapability<'_, '_> {
+    /// The key the capability encrypts under.
+    pub fn key(&self) -> &KeyHandle {
+        &self.key
+    }
+
+    /// The number of times this capability has used its key. A stream
+    /// counts once.
+    pub fn uses(&self) -> u32 {
+        *self.uses.borrow()
+    }
 
-        self.ciem.fsm.borrow_mut().on_use().map_err(|_| CryptoError::InvalidState)?;
+    /// The AEAD the capability encrypts with.
+    pub fn algorithm(&self) -> AlgorithmId {
+        self.algorithm
+    }
 
-        let key_borrow = self.ciem.key.borrow();
-        let key = key_borrow.as_ref().ok_or(CryptoError::InvalidState)?;
+    /// Restricts the capability to associated data `aad`, e.g. to enforce a
+    /// policy's `required_aad` obligation before handing the capability on.
+    /// Any other associated data, including none, is refused with
+    /// `AuthorizationFailed`.
+    pub fn with_required_aad(mut self, aad: &[u8]) -> Self {
+        self.required_aad = Some(aad.to_vec());
+        self
+    }
+}
 
-        let _epoch = self.ciem.clock.borrow_mut().tick();
-        let unbound_key = aead::UnboundKey::new(&aead::AES_256_GCM, key).unwrap();
-        let sealing_key = aead::LessSafeKey::new(unbound_key);
+/// The capability implementation uses the key's AEAD. Ciphertexts are laid out
+/// as `epoch stamp (8, big-endian) | nonce (12) | ciphertext | tag`, and the
+/// stamp is authenticated with the associated data.
+impl<'c, 'a> CryptographicCapability for EncryptCapability<'c, 'a> {
+    fn execute(&self, plaintext: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        self.execute_with_aad(plaintext, &[])
+    }
+}
+
+impl AeadCapability for EncryptCapability<'_, '_> {
+    fn execute_with_aad(&self, plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        check_aad(&self.required_aad, aad)?;
+        // Drawn first, so a failing entropy source costs the key no use.
+        let nonce_bytes = self.ciem.entropy.get_entropy()?;
+        let (sealing_key, epoch) = self.ciem.use_key(&self.key, KeyUsage::Protect, Invocation::Message(plaintext.len()), &self.uses)?;
 
-        let nonce_bytes = self.ciem.entropy.get_entropy().map_err(|_| CryptoError::EntropyHealthCheckFailed)?;
         let nonce = aead::Nonce::try_assume_unique_for_key(&nonce_bytes[..12]).unwrap();
         let nonce_vec = nonce.as_ref().to_vec();
 
         let mut ciphertext = plaintext.to_vec();
-        sealing_key.seal_in_place_append_tag(nonce, aead::Aad::empty(), &mut ciphertext).unwrap();
+        sealing_key.seal_in_place_append_tag(nonce, aead::Aad::from(stamped_aad(epoch, aad)), &mut ciphertext).unwrap();
 
-        let mut result = nonce_vec;
+        let mut result = epoch.to_be_bytes().to_vec();
+        result.extend_from_slice(&nonce_vec);
         result.extend_from_slice(&ciphertext);
 
         Ok(result)
     }
 }
 
+/// Releases the capability, so its key can return to `Bound`.
+impl Drop for EncryptCapability<'_, '_> {
+    fn drop(&mut self) {
+        // A key removed or zeroized meanwhile has nothing left to release.
+        let _ = self.ciem.release(&self.key);
+    }
+}
+
+/// Streams are encrypted in segments (see `stream`), under a random nonce
+/// prefix and stamped with the epoch they were started at. Each stream counts
+/// against the key's stream limit, and each segment against its byte limit.
+impl StreamingCapability for EncryptCapability<'_, '_> {
+    fn init_with_aad(&self, aad: &[u8]) -> Result<Box<dyn CapabilityStream + '_>, CryptoError> {
+        check_aad(&self.required_aad, aad)?;
+        let mut prefix = [0; PREFIX_LEN];
+        prefix.copy_from_slice(&self.ciem.entropy.get_entropy()?[..PREFIX_LEN]);
+        let (sealing_key, epoch) = self.ciem.use_key(&self.key, KeyUsage::Protect, Invocation::Stream, &self.uses)?;
+        Ok(Box::new(StreamEncryptor::new(self.ciem, sealing_key, aad, &self.key, epoch, prefix)))
+    }
+}
+
 pub struct DecryptCapability<'c, 'a> {
     pub(crate) ciem: &'c CIEM<'a>,
+    pub(crate) key: KeyHandle,
+    pub(crate) algorithm: AlgorithmId,
+    pub(crate) required_aad: Option<Vec<u8>>,
+    pub(crate) uses: RefCell<u32>,
 }
 
-impl<'c, 'a> CryptographicCapability for DecryptCapability<'c, 'a> {
-    fn execute(&self, ciphertext_with_nonce: &[u8]) -> Result<Vec<u8>, CryptoError> {
-        if *self.ciem.tamper.borrow() {
-            return Err(CryptoError::InvalidState);
-        }
+impl DecryptCapability<'_, '_> {
+    /// The key the capability decrypts under.
+    pub fn key(&self) -> &KeyHandle {
+        &self.key
+    }
 
-        self.ciem.fsm.borrow_mut().on_use().map_err(|_| CryptoError::InvalidState)?;
+    /// The number of times this capability has used its key. A stream
+    /// counts once.
+    pub fn uses(&self) -> u32 {
+        *self.uses.borrow()
+    }
 
-        let key_borrow = self.ciem.key.borrow();
-        let key = key_borrow.as_ref().ok_or(CryptoError::InvalidState)?;
+    /// The AEAD the capability decrypts with.
+    pub fn algorithm(&self) -> AlgorithmId {
+        self.algorithm
+    }
 
-        let unbound_key = aead::UnboundKey::new(&aead::AES_256_GCM, key).unwrap();
-        let opening_key = aead::LessSafeKey::new(unbound_key);
+    /// Restricts the capability to associated data `aad`, so it only opens
+    /// ciphertexts bound to it (see `EncryptCapability::with_required_aad`).
+    pub fn with_required_aad(mut self, aad: &[u8]) -> Self {
+        self.required_aad = Some(aad.to_vec());
+        self
+    }
+}
 
-        let nonce = aead::Nonce::try_assume_unique_for_key(&ciphertext_with_nonce[..12]).unwrap();
-        let mut ciphertext = ciphertext_with_nonce[12..].to_vec();
+impl<'c, 'a> CryptographicCapability for DecryptCapability<'c, 'a> {
+    fn execute(&self, stamped_ciphertext: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        self.execute_with_aad(stamped_ciphertext, &[])
+    }
+}
+
+/// Fails with `DecryptionFailed` unless the ciphertext was encrypted with `aad`,
+/// and with `OutsideCryptoperiod` unless its stamp lies within the key's
+/// cryptoperiod.
+impl AeadCapability for DecryptCapability<'_, '_> {
+    fn execute_with_aad(&self, stamped_ciphertext: &[u8], aad: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        check_aad(&self.required_aad, aad)?;
+        // Too short to hold a tag, so refused before it costs the key a use.
+        let Some(plaintext_len) = stamped_ciphertext.len().checked_sub(STAMP_LEN + aead::NONCE_LEN + aead::MAX_TAG_LEN) else {
+            return Err(CryptoError::MalformedInput);
+        };
+        let (opening_key, epoch) = self.ciem.use_key(&self.key, KeyUsage::Process, Invocation::Message(plaintext_len), &self.uses)?;
 
-        let plaintext = opening_key.open_in_place(nonce, aead::Aad::empty(), &mut ciphertext).map_err(|_| CryptoError::DecryptionFailed)?;
+        let (stamp, ciphertext_with_nonce) = stamped_ciphertext.split_at(STAMP_LEN);
+        let stamp = u64::from_be_bytes(stamp.try_into().unwrap());
+        let (nonce, ciphertext) = ciphertext_with_nonce.split_at(aead::NONCE_LEN);
+        let nonce = aead::Nonce::try_assume_unique_for_key(nonce).unwrap();
+        let mut ciphertext = ciphertext.to_vec();
+
+        let plaintext = opening_key.open_in_place(nonce, aead::Aad::from(stamped_aad(stamp, aad)), &mut ciphertext).map_err(|_| CryptoError::DecryptionFailed)?;
+        self.ciem.check_stamp(&self.key, stamp, epoch)?;
 
         Ok(plaintext.to_vec())
     }
 }
+
+/// Releases the capability, so its key can return to `Bound`.
+impl Drop for DecryptCapability<'_, '_> {
+    fn drop(&mut self) {
+        // A key removed or zeroized meanwhile has nothing left to release.
+        let _ = self.ciem.release(&self.key);
+    }
+}
+
+impl StreamingCapability for DecryptCapability<'_, '_> {
+    fn init_with_aad(&self, aad: &[u8]) -> Result<Box<dyn CapabilityStream + '_>, CryptoError> {
+        check_aad(&self.required_aad, aad)?;
+        let (opening_key, epoch) = self.ciem.use_key(&self.key, KeyUsage::Process, Invocation::Stream, &self.uses)?;
+        Ok(Box::new(StreamDecryptor::new(self.ciem, opening_key, aad, &self.key, epoch)))
+    }
+}
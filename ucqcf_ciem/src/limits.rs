@@ -0,0 +1,145 @@
+// ucqcf_ciem/src/limits.rs
+
+//! Per-key usage limits, and the hook told when a key nears or reaches them.
+//!
+//! Every key has a limit on the number of operations performed under it and,
+//! optionally, on the total bytes it processes. The defaults are the AEAD's
+//! safety bounds: with random 96-bit nonces, AES-GCM and ChaCha20-Poly1305
+//! keys must not be used more than 2^32 times, which keeps the probability of
+//! a nonce collision below 2^-32 (SP 800-38D section 8.3). Streams draw only
+//! a 56-bit random nonce prefix each, so streams that protect data have a
+//! bound of their own. A profile can lower the invocation and byte limits.
+//! When a key reaches a limit it expires, the CIEM
+//! generates a successor with the same algorithm, limits and cryptoperiod,
+//! and the `KeyUsageHook` is told.
+
+use std::fmt;
+use ucqcf_core::algorithm::AlgorithmId;
+use ucqcf_core::handles::KeyHandle;
+use ucqcf_core::profile::SecurityProfile;
+
+/// The most invocations of a random-nonce AEAD under one key.
+pub const RANDOM_NONCE_MAX_INVOCATIONS: u64 = 1 << 32;
+
+/// The most streams protecting data under one key. Each stream draws a
+/// random 56-bit nonce prefix, whose birthday bound is about 2^28 streams;
+/// 2^12 streams keep the probability of a prefix collision below 2^-32, the
+/// same margin as one-shot nonces.
+pub const RANDOM_PREFIX_MAX_STREAMS: u64 = 1 << 12;
+
+/// The default share of a limit, in percent, at which a warning is emitted.
+pub const DEFAULT_WARNING_PERCENT: u8 = 90;
+
+/// The usage limits of a key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyLimits {
+    /// The most operations the key may perform, streams included.
+    pub max_invocations: u64,
+    /// The most streams that may protect data under the key.
+    pub max_streams: u64,
+    /// The most bytes of input the key may process in total, if limited.
+    pub max_bytes: Option<u64>,
+    /// The longest input a single one-shot operation may take, if the AEAD
+    /// bounds it. Stream segments are `SEGMENT_LEN` bytes at most.
+    pub max_message_bytes: Option<u64>,
+    /// The share of a limit, in percent, at which the hook is warned.
+    pub warning_percent: u8,
+}
+
+impl KeyLimits {
+    /// The safety bounds of `algorithm`. Algorithms the CIEM does not serve
+    /// get the invocation bound alone.
+    pub fn for_algorithm(algorithm: AlgorithmId) -> Self {
+        let max_message_bytes = match algorithm {
+            // 2^39 - 256 bits (SP 800-38D section 5.2.1.1).
+            AlgorithmId::Aes128Gcm | AlgorithmId::Aes256Gcm => Some((1 << 36) - 32),
+            // 2^32 - 1 blocks of 64 bytes, less the block for Poly1305 (RFC 8439).
+            AlgorithmId::ChaCha20Poly1305 => Some((1 << 38) - 64),
+            _ => None,
+        };
+        Self {
+            max_invocations: RANDOM_NONCE_MAX_INVOCATIONS,
+            max_streams: RANDOM_PREFIX_MAX_STREAMS,
+            max_bytes: None,
+            max_message_bytes,
+            warning_percent: DEFAULT_WARNING_PERCENT,
+        }
+    }
+
+    /// The safety bounds of `algorithm`, lowered to the profile's
+    /// `key_max_invocations` and `key_max_bytes`.
+    pub fn from_profile(profile: &SecurityProfile, algorithm: AlgorithmId) -> Self {
+        Self::for_algorithm(algorithm).merge(&Self::at_most(
+            profile.key_max_invocations,
+            profile.key_max_bytes,
+        ))
+    }
+
+    /// Limits of `max_invocations` operations and `max_bytes` bytes of input,
+    /// where given, and nothing else, to lower another set with `merge`.
+    pub fn at_most(max_invocations: Option<u64>, max_bytes: Option<u64>) -> Self {
+        Self {
+            max_invocations: max_invocations.unwrap_or(u64::MAX),
+            max_streams: u64::MAX,
+            max_bytes,
+            max_message_bytes: None,
+            warning_percent: u8::MAX,
+        }
+    }
+
+    /// Combines two sets of limits, keeping the most restrictive value of each.
+    pub fn merge(&self, other: &KeyLimits) -> KeyLimits {
+        fn min(a: Option<u64>, b: Option<u64>) -> Option<u64> {
+            match (a, b) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            }
+        }
+
+        KeyLimits {
+            max_invocations: self.max_invocations.min(other.max_invocations),
+            max_streams: self.max_streams.min(other.max_streams),
+            max_bytes: min(self.max_bytes, other.max_bytes),
+            max_message_bytes: min(self.max_message_bytes, other.max_message_bytes),
+            warning_percent: self.warning_percent.min(other.warning_percent),
+        }
+    }
+
+    /// Returns the threshold at which usage of `limit` is warned about.
+    pub(crate) fn warning_threshold(&self, limit: u64) -> u64 {
+        let threshold = u128::from(limit) * u128::from(self.warning_percent) / 100;
+        u64::try_from(threshold).unwrap_or(u64::MAX)
+    }
+}
+
+/// Which limit of a key is meant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LimitKind {
+    Invocations,
+    Bytes,
+    Streams,
+}
+
+impl fmt::Display for LimitKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LimitKind::Invocations => "invocations",
+            LimitKind::Bytes => "bytes",
+            LimitKind::Streams => "streams",
+        })
+    }
+}
+
+/// Receives usage notifications from the CIEM, e.g. to log them or to hand
+/// out capabilities over the successor of a key. Called after the operation
+/// that triggered them has been accounted, never while the keystore is in use.
+pub trait KeyUsageHook {
+    /// `key` has used `used` of its `limit`, crossing the warning threshold.
+    /// Called at most once per key and limit.
+    fn near_limit(&self, key: &KeyHandle, kind: LimitKind, used: u64, limit: u64);
+
+    /// `key` has reached its `kind` limit and expired. `successor` is the key
+    /// generated to replace it, or `None` if that failed (e.g. an entropy
+    /// source is unhealthy).
+    fn limit_reached(&self, key: &KeyHandle, kind: LimitKind, successor: Option<&KeyHandle>);
+}
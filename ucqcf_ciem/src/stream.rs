@@ -0,0 +1,253 @@
+// ucqcf_ciem/src/stream.rs
+
+//! Chunked AEAD for streaming capabilities, following the STREAM construction
+//! (Hoang, Reyhanitabar, Rogaway and Vizár, 2015).
+//!
+//! An encrypted stream is a header, holding the epoch the stream was started
+//! at (8 bytes, big-endian) and a random nonce prefix, followed by segments.
+//! Each segment holds up to `SEGMENT_LEN` bytes of plaintext, sealed under the
+//! nonce
+//!
+//! ```text
+//! prefix (7) | segment counter (4, big-endian) | last-segment flag (1)
+//! ```
+//!
+//! Every segment but the last is full. The last segment carries the flag, and
+//! is empty only if the whole message is. Reordered, dropped or duplicated
+//! segments fail to authenticate because of the counter, and truncation at a
+//! segment boundary is detected because the new final segment lacks the flag.
+//! The epoch and the stream's associated data authenticate with every
+//! segment, and the decryptor refuses streams started outside the key's
+//! cryptoperiod. Every segment's plaintext counts against the key's byte
+//! limit as it is sealed or opened, and the encryptor refuses to seal a
+//! segment once the key's cryptoperiod has ended.
+
+use crate::fsm_generated::KeyUsage;
+use crate::{CIEM, STAMP_LEN, stamped_aad};
+use ring::aead;
+use ucqcf_core::capability::CapabilityStream;
+use ucqcf_core::handles::KeyHandle;
+use ucqcf_core::{CryptoError, HardwareFault};
+
+/// The plaintext length of every segment but the last.
+pub const SEGMENT_LEN: usize = 64 * 1024;
+
+/// The length of the random nonce prefix at the start of a stream.
+pub const PREFIX_LEN: usize = aead::NONCE_LEN - 5;
+
+/// The per-segment nonces of one stream.
+struct SegmentNonces {
+    prefix: [u8; PREFIX_LEN],
+    counter: u32,
+    exhausted: bool,
+}
+
+impl SegmentNonces {
+    fn new(prefix: [u8; PREFIX_LEN]) -> Self {
+        Self {
+            prefix,
+            counter: 0,
+            exhausted: false,
+        }
+    }
+
+    /// Returns the nonce of the next segment. Fails once the counter has
+    /// wrapped, since reusing a nonce would break the AEAD.
+    fn next(&mut self, last: bool) -> Result<aead::Nonce, CryptoError> {
+        if self.exhausted {
+            return Err(CryptoError::FsmUsageExceeded);
+        }
+        let mut nonce = [0; aead::NONCE_LEN];
+        nonce[..PREFIX_LEN].copy_from_slice(&self.prefix);
+        nonce[PREFIX_LEN..aead::NONCE_LEN - 1].copy_from_slice(&self.counter.to_be_bytes());
+        nonce[aead::NONCE_LEN - 1] = u8::from(last);
+        match self.counter.checked_add(1) {
+            Some(counter) => self.counter = counter,
+            None => self.exhausted = true,
+        }
+        Ok(aead::Nonce::assume_unique_for_key(nonce))
+    }
+}
+
+/// Fails if the CIEM was tampered with since the stream started.
+fn check_tamper(ciem: &CIEM<'_>) -> Result<(), CryptoError> {
+    if *ciem.tamper.borrow() {
+        return Err(CryptoError::hardware(HardwareFault::Tamper));
+    }
+    Ok(())
+}
+
+/// Encrypts a stream, started by `EncryptCapability::init`.
+pub struct StreamEncryptor<'c, 'a> {
+    ciem: &'c CIEM<'a>,
+    key: aead::LessSafeKey,
+    handle: KeyHandle,
+    stamp: u64,
+    /// The stamped associated data.
+    aad: Vec<u8>,
+    nonces: SegmentNonces,
+    header_sent: bool,
+    buffer: Vec<u8>,
+}
+
+impl<'c, 'a> StreamEncryptor<'c, 'a> {
+    pub(crate) fn new(
+        ciem: &'c CIEM<'a>,
+        key: aead::LessSafeKey,
+        aad: &[u8],
+        handle: &KeyHandle,
+        stamp: u64,
+        prefix: [u8; PREFIX_LEN],
+    ) -> Self {
+        Self {
+            ciem,
+            key,
+            handle: handle.clone(),
+            stamp,
+            aad: stamped_aad(stamp, aad),
+            nonces: SegmentNonces::new(prefix),
+            header_sent: false,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Seals `segment`, prepending the stream header if it has not been sent.
+    fn seal(
+        &mut self,
+        mut segment: Vec<u8>,
+        last: bool,
+        output: &mut Vec<u8>,
+    ) -> Result<(), CryptoError> {
+        self.ciem
+            .meter_data(&self.handle, KeyUsage::Protect, segment.len())?;
+        let nonce = self.nonces.next(last)?;
+        self.key
+            .seal_in_place_append_tag(nonce, aead::Aad::from(&self.aad), &mut segment)
+            .map_err(|_| CryptoError::InvalidState)?;
+        if !self.header_sent {
+            output.extend_from_slice(&self.stamp.to_be_bytes());
+            output.extend_from_slice(&self.nonces.prefix);
+            self.header_sent = true;
+        }
+        output.extend_from_slice(&segment);
+        Ok(())
+    }
+}
+
+impl CapabilityStream for StreamEncryptor<'_, '_> {
+    fn update(&mut self, input: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        check_tamper(self.ciem)?;
+        self.buffer.extend_from_slice(input);
+        let mut output = Vec::new();
+        // Keep at least one byte back, so the last segment is never empty
+        // unless the message is.
+        while self.buffer.len() > SEGMENT_LEN {
+            let segment = self.buffer.drain(..SEGMENT_LEN).collect();
+            self.seal(segment, false, &mut output)?;
+        }
+        Ok(output)
+    }
+
+    fn finalize(mut self: Box<Self>) -> Result<Vec<u8>, CryptoError> {
+        check_tamper(self.ciem)?;
+        let mut output = Vec::new();
+        let segment = std::mem::take(&mut self.buffer);
+        self.seal(segment, true, &mut output)?;
+        Ok(output)
+    }
+}
+
+/// Decrypts a stream, started by `DecryptCapability::init`.
+pub struct StreamDecryptor<'c, 'a> {
+    ciem: &'c CIEM<'a>,
+    key: aead::LessSafeKey,
+    handle: KeyHandle,
+    /// The epoch the stream was opened at.
+    now: u64,
+    /// The caller's associated data, stamped once the header has been read.
+    aad: Vec<u8>,
+    nonces: Option<SegmentNonces>,
+    buffer: Vec<u8>,
+    failed: bool,
+}
+
+impl<'c, 'a> StreamDecryptor<'c, 'a> {
+    pub(crate) fn new(
+        ciem: &'c CIEM<'a>,
+        key: aead::LessSafeKey,
+        aad: &[u8],
+        handle: &KeyHandle,
+        now: u64,
+    ) -> Self {
+        Self {
+            ciem,
+            key,
+            handle: handle.clone(),
+            now,
+            aad: aad.to_vec(),
+            nonces: None,
+            buffer: Vec::new(),
+            failed: false,
+        }
+    }
+
+    /// Opens the next `len` buffered bytes as a segment.
+    fn open(&mut self, len: usize, last: bool, output: &mut Vec<u8>) -> Result<(), CryptoError> {
+        let nonces = self.nonces.as_mut().ok_or(CryptoError::MalformedInput)?;
+        let nonce = nonces.next(last)?;
+        let mut segment: Vec<u8> = self.buffer.drain(..len).collect();
+        let plaintext = self
+            .key
+            .open_in_place(nonce, aead::Aad::from(&self.aad), &mut segment)
+            .map_err(|_| CryptoError::DecryptionFailed)?;
+        self.ciem
+            .meter_data(&self.handle, KeyUsage::Process, plaintext.len())?;
+        output.extend_from_slice(plaintext);
+        Ok(())
+    }
+
+    fn process(&mut self, input: &[u8], last: bool) -> Result<Vec<u8>, CryptoError> {
+        self.buffer.extend_from_slice(input);
+        if self.nonces.is_none() && self.buffer.len() >= STAMP_LEN + PREFIX_LEN {
+            let header: Vec<u8> = self.buffer.drain(..STAMP_LEN + PREFIX_LEN).collect();
+            let (stamp, prefix) = header.split_at(STAMP_LEN);
+            let stamp = u64::from_be_bytes(stamp.try_into().unwrap());
+            self.ciem.check_stamp(&self.handle, stamp, self.now)?;
+            self.aad = stamped_aad(stamp, &self.aad);
+            self.nonces = Some(SegmentNonces::new(prefix.try_into().unwrap()));
+        }
+
+        let mut output = Vec::new();
+        let sealed_len = SEGMENT_LEN + aead::MAX_TAG_LEN;
+        // A full segment may still be the last one until more input arrives.
+        while self.buffer.len() > sealed_len {
+            self.open(sealed_len, false, &mut output)?;
+        }
+        if last {
+            if self.buffer.len() < aead::MAX_TAG_LEN {
+                return Err(CryptoError::MalformedInput);
+            }
+            self.open(self.buffer.len(), true, &mut output)?;
+        }
+        Ok(output)
+    }
+}
+
+impl CapabilityStream for StreamDecryptor<'_, '_> {
+    fn update(&mut self, input: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        check_tamper(self.ciem)?;
+        if self.failed {
+            return Err(CryptoError::InvalidState);
+        }
+        self.process(input, false)
+            .inspect_err(|_| self.failed = true)
+    }
+
+    fn finalize(mut self: Box<Self>) -> Result<Vec<u8>, CryptoError> {
+        check_tamper(self.ciem)?;
+        if self.failed {
+            return Err(CryptoError::InvalidState);
+        }
+        self.process(&[], true)
+    }
+}
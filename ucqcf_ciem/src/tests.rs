@@ -0,0 +1,880 @@
+// ucqcf_ciem/src/tests.rs
+
+//! CIEM tests, run on mock hardware with two entropy sources.
+
+use super::*;
+use crate::limits::LimitKind;
+use crate::stream::{PREFIX_LEN, SEGMENT_LEN};
+use std::cell::Cell;
+use std::rc::Rc;
+use ucqcf_core::preset::ProfileRegistry;
+use ucqcf_core::profile::Domain;
+use ucqcf_mock_hw::clock::{MockAtomicClock, NANOS_PER_EPOCH};
+use ucqcf_mock_hw::rng::{MockQRNG, RngSource};
+
+/// An atomic clock the test sets by hand, in epochs.
+#[derive(Clone, Default)]
+struct TestClock(Rc<Cell<u64>>);
+
+impl TestClock {
+    fn at(epoch: u64) -> Self {
+        Self(Rc::new(Cell::new(epoch)))
+    }
+
+    fn set(&self, epoch: u64) {
+        self.0.set(epoch);
+    }
+
+    fn advance(&self, epochs: u64) {
+        self.0.set(self.0.get() + epochs);
+    }
+}
+
+impl ClockSource for TestClock {
+    fn now(&self) -> u64 {
+        self.0.get() * NANOS_PER_EPOCH
+    }
+
+    fn name(&self) -> &'static str {
+        "TestClock"
+    }
+
+    fn class(&self) -> ClockClass {
+        ClockClass::Atomic
+    }
+}
+
+fn ciem() -> CIEM<'static> {
+    ciem_with_clock(Box::new(MockAtomicClock))
+}
+
+fn ciem_with_clock(clock: Box<dyn ClockSource>) -> CIEM<'static> {
+    let aggregator = EntropyAggregator::new(
+        Box::new(MockTRNG),
+        vec![Box::new(MockQRNG)],
+        hmac::Key::new(hmac::HMAC_SHA256, &[7; 32]),
+    );
+    CIEM::new(aggregator, clock).unwrap()
+}
+
+/// A CIEM on `clock`, with an active key whose cryptoperiod is `cryptoperiod`.
+fn ciem_with_key(
+    clock: &TestClock,
+    cryptoperiod: u64,
+) -> (CIEM<'static>, KeyHandle, SecurityProfile) {
+    let ciem = ciem_with_clock(Box::new(clock.clone()));
+    let profile = SecurityProfile {
+        key_cryptoperiod: Some(cryptoperiod),
+        ..SecurityProfile::new(Domain::EndToEnd)
+    };
+    let key = ciem.generate_key(AlgorithmId::Aes256Gcm, &profile).unwrap();
+    (ciem, key, profile)
+}
+
+/// What a `RecordingHook` was told.
+#[derive(Debug, PartialEq)]
+enum Notice {
+    Near(LimitKind, u64, u64),
+    Reached(LimitKind, bool),
+}
+
+/// A usage hook that records its notices, and whether a successor was made.
+#[derive(Clone, Default)]
+struct RecordingHook(Rc<RefCell<Vec<Notice>>>);
+
+impl RecordingHook {
+    fn take(&self) -> Vec<Notice> {
+        self.0.take()
+    }
+}
+
+impl KeyUsageHook for RecordingHook {
+    fn near_limit(&self, _key: &KeyHandle, kind: LimitKind, used: u64, limit: u64) {
+        self.0.borrow_mut().push(Notice::Near(kind, used, limit));
+    }
+
+    fn limit_reached(&self, _key: &KeyHandle, kind: LimitKind, successor: Option<&KeyHandle>) {
+        self.0
+            .borrow_mut()
+            .push(Notice::Reached(kind, successor.is_some()));
+    }
+}
+
+/// A CIEM reporting to a `RecordingHook`, with an active key generated for
+/// `profile`.
+fn ciem_with_hook(profile: &SecurityProfile) -> (CIEM<'static>, KeyHandle, RecordingHook) {
+    let mut ciem = ciem();
+    let hook = RecordingHook::default();
+    ciem.set_usage_hook(Box::new(hook.clone()));
+    let key = ciem.generate_key(AlgorithmId::Aes256Gcm, profile).unwrap();
+    (ciem, key, hook)
+}
+
+/// Encrypts `message` as a stream, fed in uneven chunks.
+fn seal_stream(encrypt: &EncryptCapability<'_, '_>, message: &[u8]) -> Vec<u8> {
+    let mut stream = encrypt.init_with_aad(b"stream").unwrap();
+    let mut sealed = Vec::new();
+    for chunk in message.chunks(7_777) {
+        sealed.extend(stream.update(chunk).unwrap());
+    }
+    sealed.extend(stream.finalize().unwrap());
+    sealed
+}
+
+/// Decrypts a stream fed in one piece, failing on the first error.
+fn open_stream(decrypt: &DecryptCapability<'_, '_>, sealed: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    let mut stream = decrypt.init_with_aad(b"stream")?;
+    let mut message = stream.update(sealed)?;
+    message.extend(stream.finalize()?);
+    Ok(message)
+}
+
+/// Splits a sealed stream into its header and its sealed segments.
+fn split_stream(sealed: &[u8]) -> (&[u8], Vec<&[u8]>) {
+    let (header, segments) = sealed.split_at(STAMP_LEN + PREFIX_LEN);
+    (
+        header,
+        segments.chunks(SEGMENT_LEN + aead::MAX_TAG_LEN).collect(),
+    )
+}
+
+fn preset(name: &str) -> SecurityProfile {
+    ProfileRegistry::builtin().get(name).unwrap().clone()
+}
+
+#[test]
+fn check_profile_falls_back_to_a_supported_aead() {
+    let ciem = ciem();
+    // The 5G suite prefers AES-128-GCM, which the CIEM does not implement.
+    let profile = preset("telecom-5g-core");
+    assert_eq!(
+        CipherSuite::resolve(&profile).aead,
+        Some(AlgorithmId::Aes128Gcm)
+    );
+    assert_eq!(ciem.check_profile(&profile), Ok(AlgorithmId::Aes256Gcm));
+
+    let profile = SecurityProfile::new(Domain::EndToEnd);
+    assert_eq!(
+        ciem.check_profile(&profile),
+        Ok(AlgorithmId::ChaCha20Poly1305)
+    );
+}
+
+#[test]
+fn every_builtin_preset_is_satisfiable() {
+    let ciem = ciem();
+    let presets = ProfileRegistry::builtin();
+    for name in presets.names() {
+        let profile = presets.get(name).unwrap();
+        assert_eq!(profile.validate(), Ok(()), "{name}");
+        let algorithm = ciem
+            .check_profile(profile)
+            .unwrap_or_else(|err| panic!("{name}: {err}"));
+        assert!(ciem.generate_key(algorithm, profile).is_ok(), "{name}");
+    }
+}
+
+/// A source that alternates between two bytes, so it never repeats a value
+/// twice in a row.
+struct AlternatingSource;
+
+impl RngSource for AlternatingSource {
+    fn read(&self, dest: &mut [u8]) {
+        for (i, byte) in dest.iter_mut().enumerate() {
+            *byte = (i % 2) as u8;
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "AlternatingSource"
+    }
+}
+
+#[test]
+fn adaptive_proportion_cutoffs_match_sp_800_90b() {
+    // Table 2 of SP 800-90B, for a window of 512 samples and alpha = 2^-20.
+    let alpha = 2f64.powi(-20);
+    for (min_entropy, cutoff) in [(0.5, 410), (1.0, 311), (2.0, 177), (4.0, 62), (8.0, 13)] {
+        assert_eq!(
+            entropy::adaptive_proportion_cutoff(512, min_entropy, alpha),
+            cutoff,
+            "H = {min_entropy}"
+        );
+    }
+}
+
+/// A source in which every 16th byte, starting with the first, is zero, and
+/// the rest are random: it repeats no value often enough to trip the
+/// repetition count test, but carries far less than 8 bits per byte.
+struct ZeroBiasedSource;
+
+impl RngSource for ZeroBiasedSource {
+    fn read(&self, dest: &mut [u8]) {
+        MockTRNG.read(dest);
+        for byte in dest.iter_mut().step_by(16) {
+            *byte = 0;
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "ZeroBiasedSource"
+    }
+}
+
+#[test]
+fn entropy_health_checks_catch_biased_sources() {
+    let key = || hmac::Key::new(hmac::HMAC_SHA256, &[7; 32]);
+    let biased = EntropyAggregator::new(Box::new(AlternatingSource), Vec::new(), key());
+    assert_eq!(
+        biased.get_entropy(),
+        Err(entropy::EntropyError::ProportionCheckFailed)
+    );
+    let biased = EntropyAggregator::new(Box::new(ZeroBiasedSource), Vec::new(), key());
+    assert_eq!(
+        biased.get_entropy(),
+        Err(entropy::EntropyError::ProportionCheckFailed)
+    );
+
+    // A healthy source passes far more often than the 2^-20 false positive
+    // rate would fail it.
+    let healthy = EntropyAggregator::new(Box::new(MockTRNG), Vec::new(), key());
+    for _ in 0..1_000 {
+        assert!(healthy.get_entropy().is_ok());
+    }
+}
+
+#[test]
+fn keys_return_to_bound_when_their_last_capability_is_released() {
+    let ciem = ciem();
+    let profile = SecurityProfile::new(Domain::EndToEnd);
+    let key = ciem.generate_key(AlgorithmId::Aes256Gcm, &profile).unwrap();
+
+    let first = ciem.request_encrypt_capability(&key, &profile).unwrap();
+    let second = ciem.request_encrypt_capability(&key, &profile).unwrap();
+    let decrypt = ciem.request_decrypt_capability(&key, &profile).unwrap();
+    assert_eq!(ciem.outstanding_capabilities(&key), Ok(3));
+    let sealed = [
+        first.execute(b"first").unwrap(),
+        second.execute(b"second").unwrap(),
+    ];
+    assert_eq!(decrypt.execute(&sealed[0]), Ok(b"first".to_vec()));
+    assert_eq!(decrypt.execute(&sealed[1]), Ok(b"second".to_vec()));
+
+    drop(first);
+    assert_eq!(ciem.key_state(&key), Ok(KeyState::Active));
+    assert_eq!(ciem.outstanding_capabilities(&key), Ok(2));
+    drop(decrypt);
+    assert_eq!(ciem.key_state(&key), Ok(KeyState::Active));
+    assert!(second.execute(b"still usable").is_ok());
+    drop(second);
+    assert_eq!(ciem.key_state(&key), Ok(KeyState::Bound));
+    assert_eq!(ciem.outstanding_capabilities(&key), Ok(0));
+}
+
+/// A CIEM key lifecycle API, such as `CIEM::revoke_key`.
+type LifecycleEvent = fn(&CIEM<'static>, &KeyHandle) -> Result<(), CryptoError>;
+
+#[test]
+fn deactivated_and_compromised_keys_only_decrypt() {
+    let ciem = ciem();
+    let profile = SecurityProfile::new(Domain::EndToEnd);
+    let lifecycle: [LifecycleEvent; 2] = [CIEM::deactivate_key, CIEM::compromise_key];
+    for event in lifecycle {
+        let key = ciem.generate_key(AlgorithmId::Aes256Gcm, &profile).unwrap();
+        let encrypt = ciem.request_encrypt_capability(&key, &profile).unwrap();
+        let sealed = encrypt.execute(b"message").unwrap();
+        event(&ciem, &key).unwrap();
+
+        assert_eq!(
+            encrypt.execute(b"message").err(),
+            Some(CryptoError::FsmInvalidTransition)
+        );
+        assert_eq!(
+            ciem.request_encrypt_capability(&key, &profile).err(),
+            Some(CryptoError::FsmInvalidTransition)
+        );
+        let decrypt = ciem.request_decrypt_capability(&key, &profile).unwrap();
+        assert_eq!(decrypt.execute(&sealed), Ok(b"message".to_vec()));
+    }
+}
+
+#[test]
+fn revoked_and_zeroized_keys_do_nothing() {
+    let ciem = ciem();
+    let profile = SecurityProfile::new(Domain::EndToEnd);
+    let lifecycle: [LifecycleEvent; 2] = [CIEM::revoke_key, CIEM::zeroize_key];
+    for event in lifecycle {
+        let key = ciem.generate_key(AlgorithmId::Aes256Gcm, &profile).unwrap();
+        let encrypt = ciem.request_encrypt_capability(&key, &profile).unwrap();
+        let decrypt = ciem.request_decrypt_capability(&key, &profile).unwrap();
+        let sealed = encrypt.execute(b"message").unwrap();
+        event(&ciem, &key).unwrap();
+
+        assert!(encrypt.execute(b"message").is_err());
+        assert!(decrypt.execute(&sealed).is_err());
+        assert!(ciem.request_encrypt_capability(&key, &profile).is_err());
+        assert!(ciem.request_decrypt_capability(&key, &profile).is_err());
+    }
+}
+
+#[test]
+fn keys_never_activated_cannot_be_compromised() {
+    let ciem = ciem();
+    let profile = SecurityProfile::new(Domain::EndToEnd);
+    let key = ciem
+        .generate_inactive_key(AlgorithmId::Aes256Gcm, &profile)
+        .unwrap();
+    assert_eq!(
+        ciem.compromise_key(&key),
+        Err(CryptoError::FsmInvalidTransition)
+    );
+    assert_eq!(ciem.key_state(&key), Ok(KeyState::PreActivation));
+    assert_eq!(ciem.key_compromised(&key), Ok(false));
+}
+
+/// A random source that counts its reads, and can be made to fail its
+/// health checks by emitting zeros.
+#[derive(Clone, Default)]
+struct FailingSource {
+    reads: Rc<Cell<usize>>,
+    broken: Rc<Cell<bool>>,
+}
+
+impl RngSource for FailingSource {
+    fn read(&self, dest: &mut [u8]) {
+        self.reads.set(self.reads.get() + 1);
+        if self.broken.get() {
+            dest.fill(0);
+        } else {
+            MockTRNG.read(dest);
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "FailingSource"
+    }
+}
+
+#[test]
+fn atomic_time_requires_an_atomic_clock() {
+    let classical = ciem_with_clock(Box::new(MockClassicalOscillator));
+    let profile = SecurityProfile {
+        require_atomic_time: true,
+        ..SecurityProfile::new(Domain::EndToEnd)
+    };
+    assert!(matches!(
+        classical.check_profile(&profile),
+        Err(CryptoError::ProfileUnsatisfied(reason)) if reason.contains("atomic time")
+    ));
+    assert!(matches!(
+        classical.generate_key(AlgorithmId::Aes256Gcm, &profile),
+        Err(CryptoError::ProfileUnsatisfied(_))
+    ));
+
+    let ciem = ciem();
+    let key = ciem.generate_key(AlgorithmId::Aes256Gcm, &profile).unwrap();
+    assert!(ciem.request_encrypt_capability(&key, &profile).is_ok());
+}
+
+#[test]
+fn classified_data_requires_two_entropy_sources() {
+    let aggregator = EntropyAggregator::new(
+        Box::new(MockTRNG),
+        Vec::new(),
+        hmac::Key::new(hmac::HMAC_SHA256, &[7; 32]),
+    );
+    let single = CIEM::new(aggregator, Box::new(MockAtomicClock)).unwrap();
+    let confidential = SecurityProfile {
+        classification: DataClassification::Confidential,
+        ..SecurityProfile::new(Domain::EndToEnd)
+    };
+    let key = single
+        .generate_key(AlgorithmId::Aes256Gcm, &confidential)
+        .unwrap();
+
+    let secret = SecurityProfile {
+        classification: DataClassification::Secret,
+        ..confidential
+    };
+    assert!(matches!(
+        single.check_profile(&secret),
+        Err(CryptoError::ProfileUnsatisfied(reason)) if reason.contains("2 independent entropy sources")
+    ));
+    assert!(matches!(
+        single.request_encrypt_capability(&key, &secret),
+        Err(CryptoError::ProfileUnsatisfied(_))
+    ));
+    assert!(ciem().check_profile(&secret).is_ok());
+}
+
+#[test]
+fn failed_health_checks_refuse_authorization_without_drawing_entropy() {
+    let source = FailingSource::default();
+    let aggregator = EntropyAggregator::new(
+        Box::new(source.clone()),
+        vec![Box::new(MockQRNG)],
+        hmac::Key::new(hmac::HMAC_SHA256, &[7; 32]),
+    );
+    let ciem = CIEM::new(aggregator, Box::new(MockAtomicClock)).unwrap();
+    let profile = SecurityProfile::new(Domain::EndToEnd);
+    let key = ciem.generate_key(AlgorithmId::Aes256Gcm, &profile).unwrap();
+
+    // Authorizing reports on the samples already drawn, and draws none.
+    let reads = source.reads.get();
+    for _ in 0..3 {
+        assert!(ciem.request_encrypt_capability(&key, &profile).is_ok());
+    }
+    assert_eq!(source.reads.get(), reads);
+
+    source.broken.set(true);
+    let encrypt = ciem.request_encrypt_capability(&key, &profile).unwrap();
+    let entropy_failed = |result: Result<(), CryptoError>| {
+        matches!(
+            result,
+            Err(CryptoError::Hardware {
+                fault: HardwareFault::Entropy,
+                ..
+            })
+        )
+    };
+    assert!(entropy_failed(encrypt.execute(b"message").map(|_| ())));
+    assert!(entropy_failed(
+        ciem.request_encrypt_capability(&key, &profile).map(|_| ())
+    ));
+    assert!(entropy_failed(ciem.check_profile(&profile).map(|_| ())));
+
+    source.broken.set(false);
+    assert!(encrypt.execute(b"message").is_ok());
+    assert!(ciem.request_encrypt_capability(&key, &profile).is_ok());
+}
+
+#[test]
+fn sources_failing_from_the_start_generate_no_keys() {
+    let source = FailingSource::default();
+    source.broken.set(true);
+    let aggregator = EntropyAggregator::new(
+        Box::new(source),
+        vec![Box::new(MockQRNG)],
+        hmac::Key::new(hmac::HMAC_SHA256, &[7; 32]),
+    );
+    let ciem = CIEM::new(aggregator, Box::new(MockAtomicClock)).unwrap();
+    assert!(matches!(
+        ciem.generate_key(
+            AlgorithmId::Aes256Gcm,
+            &SecurityProfile::new(Domain::EndToEnd)
+        ),
+        Err(CryptoError::Hardware {
+            fault: HardwareFault::Entropy,
+            ..
+        })
+    ));
+}
+
+#[test]
+fn associated_data_must_match() {
+    let ciem = ciem();
+    let profile = SecurityProfile::new(Domain::EndToEnd);
+    let key = ciem.generate_key(AlgorithmId::Aes256Gcm, &profile).unwrap();
+    let encrypt = ciem.request_encrypt_capability(&key, &profile).unwrap();
+    let decrypt = ciem.request_decrypt_capability(&key, &profile).unwrap();
+    let sealed = encrypt.execute_with_aad(b"message", b"header").unwrap();
+
+    assert_eq!(
+        decrypt.execute_with_aad(&sealed, b"other header"),
+        Err(CryptoError::DecryptionFailed)
+    );
+    assert_eq!(decrypt.execute(&sealed), Err(CryptoError::DecryptionFailed));
+    assert_eq!(
+        decrypt.execute_with_aad(&sealed, b"header"),
+        Ok(b"message".to_vec())
+    );
+}
+
+#[test]
+fn required_aad_is_enforced_before_any_use() {
+    let ciem = ciem();
+    let profile = SecurityProfile::new(Domain::EndToEnd);
+    let key = ciem.generate_key(AlgorithmId::Aes256Gcm, &profile).unwrap();
+    let encrypt = ciem
+        .request_encrypt_capability(&key, &profile)
+        .unwrap()
+        .with_required_aad(b"header");
+    let decrypt = ciem
+        .request_decrypt_capability(&key, &profile)
+        .unwrap()
+        .with_required_aad(b"header");
+
+    assert_eq!(
+        encrypt.execute_with_aad(b"message", b"other header"),
+        Err(CryptoError::AuthorizationFailed)
+    );
+    assert_eq!(
+        encrypt.execute(b"message"),
+        Err(CryptoError::AuthorizationFailed)
+    );
+    assert!(encrypt.init_with_aad(b"other header").is_err());
+    assert_eq!(encrypt.uses(), 0);
+
+    let sealed = encrypt.execute_with_aad(b"message", b"header").unwrap();
+    assert_eq!(
+        decrypt.execute_with_aad(&sealed, b"other header"),
+        Err(CryptoError::AuthorizationFailed)
+    );
+    assert_eq!(decrypt.uses(), 0);
+    assert_eq!(
+        decrypt.execute_with_aad(&sealed, b"header"),
+        Ok(b"message".to_vec())
+    );
+}
+
+#[test]
+fn entropy_failures_use_no_budget() {
+    let source = FailingSource::default();
+    let aggregator = EntropyAggregator::new(
+        Box::new(source.clone()),
+        vec![Box::new(MockQRNG)],
+        hmac::Key::new(hmac::HMAC_SHA256, &[7; 32]),
+    );
+    let ciem = CIEM::new(aggregator, Box::new(MockAtomicClock)).unwrap();
+    let profile = SecurityProfile::new(Domain::EndToEnd);
+    let key = ciem.generate_key(AlgorithmId::Aes256Gcm, &profile).unwrap();
+    let encrypt = ciem.request_encrypt_capability(&key, &profile).unwrap();
+
+    source.broken.set(true);
+    assert!(encrypt.execute(b"message").is_err());
+    assert!(encrypt.init_with_aad(b"stream").is_err());
+    assert_eq!(encrypt.uses(), 0);
+
+    source.broken.set(false);
+    assert!(encrypt.execute(b"message").is_ok());
+    assert_eq!(encrypt.uses(), 1);
+}
+
+#[test]
+fn keys_are_isolated_from_each_other() {
+    let ciem = ciem();
+    let profile = SecurityProfile::new(Domain::EndToEnd);
+    let lifecycle: [LifecycleEvent; 2] = [CIEM::revoke_key, CIEM::zeroize_key];
+    for event in lifecycle {
+        let a = ciem.generate_key(AlgorithmId::Aes256Gcm, &profile).unwrap();
+        let b = ciem.generate_key(AlgorithmId::Aes256Gcm, &profile).unwrap();
+        let decrypt_a = ciem.request_decrypt_capability(&a, &profile).unwrap();
+        let encrypt_b = ciem.request_encrypt_capability(&b, &profile).unwrap();
+        let decrypt_b = ciem.request_decrypt_capability(&b, &profile).unwrap();
+        let sealed = encrypt_b.execute(b"message").unwrap();
+        assert_eq!(
+            decrypt_a.execute(&sealed),
+            Err(CryptoError::DecryptionFailed)
+        );
+
+        event(&ciem, &a).unwrap();
+        assert_eq!(ciem.key_state(&b), Ok(KeyState::Active));
+        assert_eq!(decrypt_b.execute(&sealed), Ok(b"message".to_vec()));
+        let sealed = encrypt_b.execute(b"another message").unwrap();
+        assert_eq!(decrypt_b.execute(&sealed), Ok(b"another message".to_vec()));
+    }
+}
+
+#[test]
+fn secure_clock_epochs_follow_the_source() {
+    let source = TestClock::at(1_000);
+    let mut clock = SecureClock::new(Box::new(source.clone()));
+    assert_eq!(clock.tick(), 1_000);
+    // Reading the clock does not advance it.
+    assert_eq!(clock.tick(), 1_000);
+    source.advance(5);
+    assert_eq!(clock.tick(), 1_005);
+    // A source running backwards does not take the clock with it.
+    source.set(900);
+    assert_eq!(clock.tick(), 1_005);
+}
+
+#[test]
+fn cryptoperiods_are_measured_on_the_clock() {
+    let clock = TestClock::at(1_000);
+    let (ciem, key, profile) = ciem_with_key(&clock, 10);
+    assert_eq!(ciem.key_attributes(&key).unwrap().expires_at, Some(1_010));
+
+    let encrypt = ciem.request_encrypt_capability(&key, &profile).unwrap();
+    // However often the key is used, only time ends its cryptoperiod.
+    for _ in 0..20 {
+        encrypt.execute(b"message").unwrap();
+    }
+    clock.advance(9);
+    let ciphertext = encrypt.execute(b"message").unwrap();
+    clock.advance(1);
+    assert_eq!(
+        encrypt.execute(b"message"),
+        Err(CryptoError::OutsideCryptoperiod)
+    );
+
+    let decrypt = ciem.request_decrypt_capability(&key, &profile).unwrap();
+    assert_eq!(decrypt.execute(&ciphertext), Ok(b"message".to_vec()));
+}
+
+#[test]
+fn streams_stop_sealing_when_the_cryptoperiod_ends() {
+    let clock = TestClock::at(1_000);
+    let (ciem, key, profile) = ciem_with_key(&clock, 10);
+    let encrypt = ciem.request_encrypt_capability(&key, &profile).unwrap();
+    let mut stream = encrypt.init().unwrap();
+    assert!(stream.update(&vec![1; SEGMENT_LEN + 1]).is_ok());
+
+    clock.advance(10);
+    assert_eq!(
+        stream.update(&vec![1; SEGMENT_LEN]).map(|_| ()),
+        Err(CryptoError::OutsideCryptoperiod)
+    );
+    assert_eq!(
+        stream.finalize().map(|_| ()),
+        Err(CryptoError::OutsideCryptoperiod)
+    );
+}
+
+#[test]
+fn keys_expire_on_the_use_that_reaches_their_limit() {
+    let profile = SecurityProfile {
+        key_max_invocations: Some(10),
+        ..SecurityProfile::new(Domain::EndToEnd)
+    };
+    let (ciem, key, hook) = ciem_with_hook(&profile);
+    let encrypt = ciem.request_encrypt_capability(&key, &profile).unwrap();
+    for _ in 0..8 {
+        encrypt.execute(b"message").unwrap();
+    }
+    assert_eq!(hook.take(), []);
+
+    encrypt.execute(b"message").unwrap();
+    assert_eq!(hook.take(), [Notice::Near(LimitKind::Invocations, 9, 10)]);
+
+    // The tenth use is allowed, and retires the key.
+    encrypt.execute(b"message").unwrap();
+    assert_eq!(hook.take(), [Notice::Reached(LimitKind::Invocations, true)]);
+    assert_eq!(ciem.key_state(&key), Ok(KeyState::Expired));
+    let successor = ciem.key_successor(&key).unwrap().unwrap();
+    assert_eq!(ciem.key_state(&successor), Ok(KeyState::Bound));
+
+    assert_eq!(
+        encrypt.execute(b"message"),
+        Err(CryptoError::FsmUsageExceeded)
+    );
+    assert_eq!(hook.take(), []);
+    assert_eq!(ciem.key_successor(&key), Ok(Some(successor)));
+}
+
+#[test]
+fn byte_limits_count_whole_messages_or_nothing() {
+    let profile = SecurityProfile {
+        key_max_bytes: Some(100),
+        ..SecurityProfile::new(Domain::EndToEnd)
+    };
+    let (ciem, key, hook) = ciem_with_hook(&profile);
+    let encrypt = ciem.request_encrypt_capability(&key, &profile).unwrap();
+    encrypt.execute(&[0; 60]).unwrap();
+    encrypt.execute(&[0; 40]).unwrap();
+    assert_eq!(
+        hook.take(),
+        [
+            Notice::Near(LimitKind::Bytes, 100, 100),
+            Notice::Reached(LimitKind::Bytes, true),
+        ]
+    );
+    assert_eq!(ciem.key_state(&key), Ok(KeyState::Expired));
+
+    // A message that would overshoot the limit is refused without counting.
+    let (ciem, key, hook) = ciem_with_hook(&profile);
+    let encrypt = ciem.request_encrypt_capability(&key, &profile).unwrap();
+    encrypt.execute(&[0; 60]).unwrap();
+    assert_eq!(
+        encrypt.execute(&[0; 50]),
+        Err(CryptoError::FsmUsageExceeded)
+    );
+    assert_eq!(hook.take(), [Notice::Reached(LimitKind::Bytes, true)]);
+    let keys = ciem.keys.borrow();
+    let fsm = &keys.get(&key).unwrap().fsm;
+    assert_eq!((fsm.usage(), fsm.bytes()), (1, 60));
+}
+
+#[test]
+fn malformed_ciphertexts_use_no_budget() {
+    let profile = SecurityProfile {
+        key_max_invocations: Some(2),
+        ..SecurityProfile::new(Domain::EndToEnd)
+    };
+    let (ciem, key, hook) = ciem_with_hook(&profile);
+    let decrypt = ciem.request_decrypt_capability(&key, &profile).unwrap();
+    let too_short = [0; STAMP_LEN + aead::NONCE_LEN + aead::MAX_TAG_LEN - 1];
+    for _ in 0..5 {
+        assert_eq!(
+            decrypt.execute(&too_short),
+            Err(CryptoError::MalformedInput)
+        );
+    }
+    assert_eq!(decrypt.uses(), 0);
+
+    let encrypt = ciem.request_encrypt_capability(&key, &profile).unwrap();
+    let ciphertext = encrypt.execute(b"message").unwrap();
+    assert_eq!(decrypt.execute(&ciphertext), Ok(b"message".to_vec()));
+    assert_eq!(
+        hook.take(),
+        [
+            Notice::Near(LimitKind::Invocations, 1, 2),
+            Notice::Reached(LimitKind::Invocations, true),
+        ]
+    );
+}
+
+#[test]
+fn streams_that_protect_data_count_against_the_stream_limit() {
+    let profile = SecurityProfile::new(Domain::EndToEnd);
+    let (ciem, key, hook) = ciem_with_hook(&profile);
+    assert_eq!(
+        ciem.key_limits(&key).unwrap().max_streams,
+        limits::RANDOM_PREFIX_MAX_STREAMS
+    );
+    let limits = KeyLimits {
+        max_streams: 2,
+        ..ciem.key_limits(&key).unwrap()
+    };
+    ciem.restrict_key_limits(&key, &limits).unwrap();
+
+    let decrypt = ciem.request_decrypt_capability(&key, &profile).unwrap();
+    for _ in 0..3 {
+        decrypt.init().unwrap();
+    }
+    let encrypt = ciem.request_encrypt_capability(&key, &profile).unwrap();
+    encrypt.init().unwrap();
+    encrypt.init().unwrap();
+    assert_eq!(
+        hook.take(),
+        [
+            Notice::Near(LimitKind::Streams, 1, 2),
+            Notice::Reached(LimitKind::Streams, true),
+        ]
+    );
+    assert_eq!(
+        encrypt.init().map(|_| ()),
+        Err(CryptoError::FsmUsageExceeded)
+    );
+    // Opening streams is still allowed once the key has expired.
+    assert!(decrypt.init().is_ok());
+}
+
+#[test]
+fn streams_round_trip_around_segment_boundaries() {
+    let clock = TestClock::at(1_000);
+    let (ciem, key, profile) = ciem_with_key(&clock, 100);
+    let encrypt = ciem.request_encrypt_capability(&key, &profile).unwrap();
+    let decrypt = ciem.request_decrypt_capability(&key, &profile).unwrap();
+    for len in [
+        0,
+        1,
+        SEGMENT_LEN - 1,
+        SEGMENT_LEN,
+        SEGMENT_LEN + 1,
+        2 * SEGMENT_LEN,
+    ] {
+        let message: Vec<u8> = (0..len).map(|i| i as u8).collect();
+        let sealed = seal_stream(&encrypt, &message);
+        let segments = len.div_ceil(SEGMENT_LEN).max(1);
+        assert_eq!(
+            sealed.len(),
+            STAMP_LEN + PREFIX_LEN + len + segments * aead::MAX_TAG_LEN,
+            "{len}"
+        );
+        assert_eq!(open_stream(&decrypt, &sealed), Ok(message), "{len}");
+    }
+}
+
+#[test]
+fn streams_reject_truncated_reordered_and_duplicated_segments() {
+    let clock = TestClock::at(1_000);
+    let (ciem, key, profile) = ciem_with_key(&clock, 100);
+    let encrypt = ciem.request_encrypt_capability(&key, &profile).unwrap();
+    let decrypt = ciem.request_decrypt_capability(&key, &profile).unwrap();
+    let sealed = seal_stream(&encrypt, &vec![1; 3 * SEGMENT_LEN + 10]);
+    let (header, segments) = split_stream(&sealed);
+    assert_eq!(segments.len(), 4);
+
+    for (name, order) in [
+        ("truncated", vec![0, 1, 2]),
+        ("reordered", vec![1, 0, 2, 3]),
+        ("duplicated", vec![0, 0, 1, 2, 3]),
+        ("last segment repeated", vec![0, 1, 2, 3, 3]),
+    ] {
+        let mut forged = header.to_vec();
+        for i in order {
+            forged.extend_from_slice(segments[i]);
+        }
+        assert_eq!(
+            open_stream(&decrypt, &forged),
+            Err(CryptoError::DecryptionFailed),
+            "{name}"
+        );
+    }
+
+    // Cut inside the last segment, and down to the header.
+    assert_eq!(
+        open_stream(&decrypt, &sealed[..sealed.len() - 1]),
+        Err(CryptoError::DecryptionFailed)
+    );
+    assert_eq!(
+        open_stream(&decrypt, header),
+        Err(CryptoError::MalformedInput)
+    );
+}
+
+#[test]
+fn streams_reject_tampered_headers() {
+    let clock = TestClock::at(1_000);
+    let (ciem, key, profile) = ciem_with_key(&clock, 100);
+    let encrypt = ciem.request_encrypt_capability(&key, &profile).unwrap();
+    clock.advance(5);
+    let sealed = seal_stream(&encrypt, b"message");
+    let decrypt = ciem.request_decrypt_capability(&key, &profile).unwrap();
+    assert_eq!(open_stream(&decrypt, &sealed), Ok(b"message".to_vec()));
+
+    // A stamp moved within the cryptoperiod, and a changed nonce prefix.
+    for (name, index) in [("stamp", STAMP_LEN - 1), ("prefix", STAMP_LEN)] {
+        let mut forged = sealed.clone();
+        forged[index] ^= 1;
+        assert_eq!(
+            open_stream(&decrypt, &forged),
+            Err(CryptoError::DecryptionFailed),
+            "{name}"
+        );
+    }
+}
+
+#[test]
+fn streams_stamped_outside_the_cryptoperiod_are_refused() {
+    let clock = TestClock::at(1_000);
+    let (ciem, key, profile) = ciem_with_key(&clock, 100);
+    let encrypt = ciem.request_encrypt_capability(&key, &profile).unwrap();
+    let sealed = seal_stream(&encrypt, b"message");
+    let restamped = |stamp: u64| {
+        let mut forged = sealed.clone();
+        forged[..STAMP_LEN].copy_from_slice(&stamp.to_be_bytes());
+        forged
+    };
+
+    clock.advance(50);
+    let decrypt = ciem.request_decrypt_capability(&key, &profile).unwrap();
+    // Before activation, and after the epoch the stream is opened at.
+    for stamp in [999, 1_051] {
+        assert_eq!(
+            open_stream(&decrypt, &restamped(stamp)),
+            Err(CryptoError::OutsideCryptoperiod),
+            "{stamp}"
+        );
+    }
+
+    // After the end of the cryptoperiod.
+    clock.advance(100);
+    let decrypt = ciem.request_decrypt_capability(&key, &profile).unwrap();
+    assert_eq!(
+        open_stream(&decrypt, &restamped(1_100)),
+        Err(CryptoError::OutsideCryptoperiod)
+    );
+    assert_eq!(open_stream(&decrypt, &sealed), Ok(b"message".to_vec()));
+}
@@ -0,0 +1,297 @@
+// ucqcf_core/src/algorithm.rs
+
+//! The algorithm registry and the typed operation model passed to `CryptoProvider`s.
+//!
+//! Every algorithm the framework knows about has an `AlgorithmId`, with a
+//! canonical name (used in requests and policies), an OID, and the set of
+//! operations it can perform.
+
+use crate::CryptoError;
+use crate::profile::SecurityStrength;
+use crate::request::Operation;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Identifies a cryptographic algorithm. Serialized as its canonical name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum AlgorithmId {
+    #[serde(rename = "AES-128-GCM")]
+    Aes128Gcm,
+    #[serde(rename = "AES-256-GCM")]
+    Aes256Gcm,
+    #[serde(rename = "ChaCha20-Poly1305")]
+    ChaCha20Poly1305,
+    #[serde(rename = "AES-256-KW")]
+    Aes256Kw,
+    #[serde(rename = "HMAC-SHA256")]
+    HmacSha256,
+    #[serde(rename = "HMAC-SHA384")]
+    HmacSha384,
+    #[serde(rename = "HKDF-SHA256")]
+    HkdfSha256,
+    #[serde(rename = "HKDF-SHA384")]
+    HkdfSha384,
+    #[serde(rename = "ECDSA-P256-SHA256")]
+    EcdsaP256Sha256,
+    #[serde(rename = "ECDSA-P384-SHA384")]
+    EcdsaP384Sha384,
+    #[serde(rename = "ECDSA-secp256k1-SHA256")]
+    EcdsaSecp256k1Sha256,
+    #[serde(rename = "Ed25519")]
+    Ed25519,
+    #[serde(rename = "X25519")]
+    X25519,
+    #[serde(rename = "ML-KEM-768")]
+    MlKem768,
+    #[serde(rename = "ML-KEM-1024")]
+    MlKem1024,
+    #[serde(rename = "ML-DSA-65")]
+    MlDsa65,
+    #[serde(rename = "ML-DSA-87")]
+    MlDsa87,
+}
+
+impl AlgorithmId {
+    /// Every registered algorithm.
+    pub const ALL: [AlgorithmId; 17] = [
+        AlgorithmId::Aes128Gcm,
+        AlgorithmId::Aes256Gcm,
+        AlgorithmId::ChaCha20Poly1305,
+        AlgorithmId::Aes256Kw,
+        AlgorithmId::HmacSha256,
+        AlgorithmId::HmacSha384,
+        AlgorithmId::HkdfSha256,
+        AlgorithmId::HkdfSha384,
+        AlgorithmId::EcdsaP256Sha256,
+        AlgorithmId::EcdsaP384Sha384,
+        AlgorithmId::EcdsaSecp256k1Sha256,
+        AlgorithmId::Ed25519,
+        AlgorithmId::X25519,
+        AlgorithmId::MlKem768,
+        AlgorithmId::MlKem1024,
+        AlgorithmId::MlDsa65,
+        AlgorithmId::MlDsa87,
+    ];
+
+    /// Returns the canonical name of the algorithm.
+    pub fn name(&self) -> &'static str {
+        match self {
+            AlgorithmId::Aes128Gcm => "AES-128-GCM",
+            AlgorithmId::Aes256Gcm => "AES-256-GCM",
+            AlgorithmId::ChaCha20Poly1305 => "ChaCha20-Poly1305",
+            AlgorithmId::Aes256Kw => "AES-256-KW",
+            AlgorithmId::HmacSha256 => "HMAC-SHA256",
+            AlgorithmId::HmacSha384 => "HMAC-SHA384",
+            AlgorithmId::HkdfSha256 => "HKDF-SHA256",
+            AlgorithmId::HkdfSha384 => "HKDF-SHA384",
+            AlgorithmId::EcdsaP256Sha256 => "ECDSA-P256-SHA256",
+            AlgorithmId::EcdsaP384Sha384 => "ECDSA-P384-SHA384",
+            AlgorithmId::EcdsaSecp256k1Sha256 => "ECDSA-secp256k1-SHA256",
+            AlgorithmId::Ed25519 => "Ed25519",
+            AlgorithmId::X25519 => "X25519",
+            AlgorithmId::MlKem768 => "ML-KEM-768",
+            AlgorithmId::MlKem1024 => "ML-KEM-1024",
+            AlgorithmId::MlDsa65 => "ML-DSA-65",
+            AlgorithmId::MlDsa87 => "ML-DSA-87",
+        }
+    }
+
+    /// Returns the algorithm's object identifier in dotted form. For ECDSA this
+    /// is the OID of the curve, since the signature OIDs do not name one.
+    pub fn oid(&self) -> &'static str {
+        match self {
+            AlgorithmId::Aes128Gcm => "2.16.840.1.101.3.4.1.6",
+            AlgorithmId::Aes256Gcm => "2.16.840.1.101.3.4.1.46",
+            AlgorithmId::ChaCha20Poly1305 => "1.2.840.113549.1.9.16.3.18",
+            AlgorithmId::Aes256Kw => "2.16.840.1.101.3.4.1.45",
+            AlgorithmId::HmacSha256 => "1.2.840.113549.2.9",
+            AlgorithmId::HmacSha384 => "1.2.840.113549.2.10",
+            AlgorithmId::HkdfSha256 => "1.2.840.113549.1.9.16.3.28",
+            AlgorithmId::HkdfSha384 => "1.2.840.113549.1.9.16.3.29",
+            AlgorithmId::EcdsaP256Sha256 => "1.2.840.10045.3.1.7",
+            AlgorithmId::EcdsaP384Sha384 => "1.3.132.0.34",
+            AlgorithmId::EcdsaSecp256k1Sha256 => "1.3.132.0.10",
+            AlgorithmId::Ed25519 => "1.3.101.112",
+            AlgorithmId::X25519 => "1.3.101.110",
+            AlgorithmId::MlKem768 => "2.16.840.1.101.3.4.4.2",
+            AlgorithmId::MlKem1024 => "2.16.840.1.101.3.4.4.3",
+            AlgorithmId::MlDsa65 => "2.16.840.1.101.3.4.3.18",
+            AlgorithmId::MlDsa87 => "2.16.840.1.101.3.4.3.19",
+        }
+    }
+
+    /// Looks an algorithm up by its OID.
+    pub fn from_oid(oid: &str) -> Option<AlgorithmId> {
+        Self::ALL.into_iter().find(|a| a.oid() == oid)
+    }
+
+    /// Returns the operations the algorithm can perform.
+    pub fn operations(&self) -> &'static [Operation] {
+        match self {
+            AlgorithmId::Aes128Gcm | AlgorithmId::Aes256Gcm | AlgorithmId::ChaCha20Poly1305 => {
+                &[Operation::Encrypt, Operation::Decrypt]
+            }
+            AlgorithmId::Aes256Kw => &[Operation::Wrap, Operation::Unwrap],
+            AlgorithmId::HmacSha256 | AlgorithmId::HmacSha384 => {
+                &[Operation::Mac, Operation::Verify]
+            }
+            AlgorithmId::HkdfSha256 | AlgorithmId::HkdfSha384 | AlgorithmId::X25519 => {
+                &[Operation::Derive]
+            }
+            AlgorithmId::EcdsaP256Sha256
+            | AlgorithmId::EcdsaP384Sha384
+            | AlgorithmId::EcdsaSecp256k1Sha256
+            | AlgorithmId::Ed25519
+            | AlgorithmId::MlDsa65
+            | AlgorithmId::MlDsa87 => &[Operation::Sign, Operation::Verify],
+            AlgorithmId::MlKem768 | AlgorithmId::MlKem1024 => {
+                &[Operation::Encapsulate, Operation::Decapsulate]
+            }
+        }
+    }
+
+    /// Returns `true` if the algorithm can perform `operation`.
+    pub fn supports(&self, operation: Operation) -> bool {
+        self.operations().contains(&operation)
+    }
+
+    /// Returns the algorithm's classical security strength (NIST SP 800-57
+    /// Part 1, Table 2 and 3; the NIST PQC categories for ML-KEM and ML-DSA).
+    pub fn security_strength(&self) -> SecurityStrength {
+        match self {
+            AlgorithmId::Aes128Gcm
+            | AlgorithmId::EcdsaP256Sha256
+            | AlgorithmId::EcdsaSecp256k1Sha256
+            | AlgorithmId::Ed25519
+            | AlgorithmId::X25519 => SecurityStrength::Bits128,
+            AlgorithmId::EcdsaP384Sha384 | AlgorithmId::MlKem768 | AlgorithmId::MlDsa65 => {
+                SecurityStrength::Bits192
+            }
+            AlgorithmId::Aes256Gcm
+            | AlgorithmId::ChaCha20Poly1305
+            | AlgorithmId::Aes256Kw
+            | AlgorithmId::HmacSha256
+            | AlgorithmId::HmacSha384
+            | AlgorithmId::HkdfSha256
+            | AlgorithmId::HkdfSha384
+            | AlgorithmId::MlKem1024
+            | AlgorithmId::MlDsa87 => SecurityStrength::Bits256,
+        }
+    }
+
+    /// Returns `true` if the algorithm resists a quantum adversary: the
+    /// post-quantum algorithms, and symmetric algorithms with 256-bit keys.
+    pub fn quantum_resistant(&self) -> bool {
+        !matches!(
+            self,
+            AlgorithmId::Aes128Gcm
+                | AlgorithmId::EcdsaP256Sha256
+                | AlgorithmId::EcdsaP384Sha384
+                | AlgorithmId::EcdsaSecp256k1Sha256
+                | AlgorithmId::Ed25519
+                | AlgorithmId::X25519
+        )
+    }
+}
+
+impl fmt::Display for AlgorithmId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for AlgorithmId {
+    type Err = CryptoError;
+
+    /// Parses a canonical name or a dotted OID.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|a| a.name() == s)
+            .or_else(|| Self::from_oid(s))
+            .ok_or(CryptoError::UnsupportedAlgorithm)
+    }
+}
+
+/// An operation together with its parameters, as executed by a `CryptoProvider`.
+///
+/// `data` passed alongside is the plaintext, ciphertext, message, key material
+/// or peer public key the operation consumes; its meaning for each variant is
+/// documented below.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CryptoOperation<'a> {
+    /// Encrypts `data`, authenticating `aad` with it.
+    Encrypt { aad: &'a [u8] },
+    /// Decrypts `data`, which must authenticate together with `aad`.
+    Decrypt { aad: &'a [u8] },
+    /// Signs the message `data`.
+    Sign,
+    /// Checks `signature` (or MAC tag) over the message `data`. Returns no
+    /// output if it verifies, and fails with `VerificationFailed` otherwise.
+    Verify { signature: &'a [u8] },
+    /// Computes a MAC tag over `data`.
+    Mac,
+    /// Derives key material bound to `info`. For key agreement, `data` is the
+    /// peer's public key; for a KDF it is the salt.
+    Derive { info: &'a [u8] },
+    /// Wraps the key material in `data`.
+    Wrap,
+    /// Unwraps the wrapped key in `data`.
+    Unwrap,
+    /// Encapsulates a fresh shared secret to the key. Returns the encapsulation
+    /// followed by the shared secret, whose length the algorithm fixes.
+    Encapsulate,
+    /// Recovers the shared secret from the encapsulation in `data`.
+    Decapsulate,
+}
+
+impl CryptoOperation<'_> {
+    /// Returns the kind of the operation, without its parameters.
+    pub fn kind(&self) -> Operation {
+        match self {
+            CryptoOperation::Encrypt { .. } => Operation::Encrypt,
+            CryptoOperation::Decrypt { .. } => Operation::Decrypt,
+            CryptoOperation::Sign => Operation::Sign,
+            CryptoOperation::Verify { .. } => Operation::Verify,
+            CryptoOperation::Mac => Operation::Mac,
+            CryptoOperation::Derive { .. } => Operation::Derive,
+            CryptoOperation::Wrap => Operation::Wrap,
+            CryptoOperation::Unwrap => Operation::Unwrap,
+            CryptoOperation::Encapsulate => Operation::Encapsulate,
+            CryptoOperation::Decapsulate => Operation::Decapsulate,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn every_algorithm_round_trips() {
+        for algorithm in AlgorithmId::ALL {
+            assert_eq!(algorithm.name().parse::<AlgorithmId>().unwrap(), algorithm);
+            assert_eq!(algorithm.to_string(), algorithm.name());
+            assert_eq!(AlgorithmId::from_oid(algorithm.oid()), Some(algorithm));
+            assert_eq!(algorithm.oid().parse::<AlgorithmId>().unwrap(), algorithm);
+            let json = serde_json::to_string(&algorithm).unwrap();
+            assert_eq!(json, format!("\"{}\"", algorithm.name()));
+            assert_eq!(
+                serde_json::from_str::<AlgorithmId>(&json).unwrap(),
+                algorithm
+            );
+        }
+        assert!("AES-512-GCM".parse::<AlgorithmId>().is_err());
+        assert_eq!(AlgorithmId::from_oid("1.2.3"), None);
+    }
+
+    #[test]
+    fn names_and_oids_are_unique() {
+        let names: HashSet<_> = AlgorithmId::ALL.iter().map(|a| a.name()).collect();
+        let oids: HashSet<_> = AlgorithmId::ALL.iter().map(|a| a.oid()).collect();
+        assert_eq!(names.len(), AlgorithmId::ALL.len());
+        assert_eq!(oids.len(), AlgorithmId::ALL.len());
+    }
+}
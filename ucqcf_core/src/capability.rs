@@ -7,3 +7,36 @@ use crate::CryptoError;
 pub trait CryptographicCapability {
     fn execute(&self, input: &[u8]) -> Result<Vec<u8>, CryptoError>;
 }
+
+/// A capability whose operation authenticates associated data along with its
+/// input (AEAD): headers, record ids or routing metadata the output is bound
+/// to. `execute` is the same as `execute_with_aad` with empty associated data.
+pub trait AeadCapability: CryptographicCapability {
+    /// Executes the operation on `input`, binding it to `aad`. Decryption
+    /// fails unless `aad` is the data the input was encrypted with.
+    fn execute_with_aad(&self, input: &[u8], aad: &[u8]) -> Result<Vec<u8>, CryptoError>;
+}
+
+/// A capability that can also process its input incrementally, for payloads
+/// too large to hold in memory. Each stream counts as one use of the capability.
+pub trait StreamingCapability {
+    /// Starts a new stream, binding every part of it to `aad`.
+    fn init_with_aad(&self, aad: &[u8]) -> Result<Box<dyn CapabilityStream + '_>, CryptoError>;
+
+    /// Starts a new stream with empty associated data.
+    fn init(&self) -> Result<Box<dyn CapabilityStream + '_>, CryptoError> {
+        self.init_with_aad(&[])
+    }
+}
+
+/// An operation in progress, started by `StreamingCapability::init`.
+///
+/// Output is returned as soon as it is available. For decryption, output
+/// returned by `update` is authentic but may be a prefix of the message: the
+/// message is only known to be complete once `finalize` succeeds.
+pub trait CapabilityStream {
+    /// Feeds the next part of the input and returns the output it completes.
+    fn update(&mut self, input: &[u8]) -> Result<Vec<u8>, CryptoError>;
+    /// Ends the stream and returns the remaining output.
+    fn finalize(self: Box<Self>) -> Result<Vec<u8>, CryptoError>;
+}
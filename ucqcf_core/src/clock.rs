@@ -0,0 +1,52 @@
+// ucqcf_core/src/clock.rs
+
+//! Time sources: the trait every clock implements, and the operating
+//! system's clock as the default source.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The physical class of a clock source, from least to most stable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ClockClass {
+    Classical,
+    Atomic,
+    Quantum,
+}
+
+/// The number of nanoseconds in one epoch, the unit in which the framework
+/// measures lifetimes, expiries and cryptoperiods.
+pub const NANOS_PER_EPOCH: u64 = 1_000_000_000;
+
+/// A trait for any hardware source of time.
+pub trait ClockSource {
+    /// Returns a timestamp from the source, in nanoseconds since the UNIX epoch.
+    fn now(&self) -> u64;
+    /// Returns the source's time in whole epochs (seconds since the UNIX epoch).
+    fn epoch(&self) -> u64 {
+        self.now() / NANOS_PER_EPOCH
+    }
+    /// Returns the name of the source.
+    fn name(&self) -> &'static str;
+    /// Returns the class of the source. Sources that do not declare one are
+    /// assumed to be classical.
+    fn class(&self) -> ClockClass {
+        ClockClass::Classical
+    }
+}
+
+/// The operating system's wall clock. Classical, since nothing vouches for
+/// its stability.
+pub struct SystemClock;
+
+impl ClockSource for SystemClock {
+    fn now(&self) -> u64 {
+        // A clock set before 1970 reads as the UNIX epoch itself.
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_nanos() as u64)
+    }
+
+    fn name(&self) -> &'static str {
+        "SystemClock"
+    }
+}
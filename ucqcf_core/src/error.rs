@@ -0,0 +1,232 @@
+// ucqcf_core/src/error.rs
+
+//! The error type shared by every crate of the framework.
+
+use crate::profile::ProfileError;
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+use thiserror::Error;
+
+/// A fault reported by the hardware a module runs on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Error)]
+pub enum HardwareFault {
+    /// A tamper sensor fired; keys have been zeroized.
+    #[error("A tamper sensor fired and the keys were zeroized.")]
+    Tamper,
+    /// The clock source failed or cannot be trusted.
+    #[error("The clock source failed or cannot be trusted.")]
+    Clock,
+    /// An entropy source is missing or failed a health check.
+    #[error("An entropy source is missing or failed a health check.")]
+    Entropy,
+}
+
+/// The underlying cause of a `CryptoError`, e.g. an error reported by a
+/// vendor library. Causes compare equal when their messages do.
+#[derive(Clone)]
+pub struct ErrorCause(Arc<dyn Error + Send + Sync>);
+
+impl ErrorCause {
+    pub fn new(cause: impl Error + Send + Sync + 'static) -> Self {
+        Self(Arc::new(cause))
+    }
+}
+
+impl fmt::Debug for ErrorCause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.0, f)
+    }
+}
+
+impl fmt::Display for ErrorCause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl Error for ErrorCause {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.0.source()
+    }
+}
+
+impl PartialEq for ErrorCause {
+    fn eq(&self, other: &Self) -> bool {
+        self.to_string() == other.to_string()
+    }
+}
+
+impl Eq for ErrorCause {}
+
+/// Common error type for the framework, used across different crates.
+///
+/// Every variant has a stable numeric code (see `code`) for use across FFI and
+/// wire boundaries; codes are never reused or renumbered. Code 5, once an
+/// entropy health check failure, is now reported as `HardwareFault::Entropy`.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum CryptoError {
+    /// The operation is not valid in the current state of the FSM.
+    #[error("The operation is not valid in the current state.")]
+    InvalidState,
+    /// The requested operation was not authorized by the security policy.
+    #[error("The requested operation was not authorized by the security policy.")]
+    AuthorizationFailed,
+    /// An FSM transition was attempted from an invalid state.
+    #[error("An FSM transition was attempted from an invalid state.")]
+    FsmInvalidTransition,
+    /// A usage limit for a key or resource was exceeded.
+    #[error("A usage limit for a key or resource was exceeded.")]
+    FsmUsageExceeded,
+    /// Decryption failed, likely due to an incorrect key or tampered ciphertext.
+    #[error("Decryption failed, likely due to an incorrect key or tampered ciphertext.")]
+    DecryptionFailed,
+    /// The request could not be interpreted.
+    #[error("The request could not be interpreted.")]
+    MalformedInput,
+    /// The lifetime of the capability, or of a sealed handle, has ended.
+    #[error("The lifetime of the capability or sealed handle has ended.")]
+    CapabilityExpired,
+    /// No registered provider could satisfy the request.
+    #[error("No registered provider could satisfy the request.")]
+    ProviderUnavailable,
+    /// The algorithm is unknown, or cannot perform the requested operation.
+    #[error("The algorithm is unknown, or cannot perform the requested operation.")]
+    UnsupportedAlgorithm,
+    /// A signature or MAC tag did not verify.
+    #[error("A signature or MAC tag did not verify.")]
+    VerificationFailed,
+    /// The module does not implement the requested operation.
+    #[error("The module does not implement the requested operation.")]
+    NotSupported,
+    /// The key is unknown, or has been destroyed.
+    #[error("The key is unknown, or has been destroyed.")]
+    KeyNotFound,
+    /// The security profile is contradictory.
+    #[error("The security profile is contradictory: {0}.")]
+    InvalidProfile(ProfileError),
+    /// The module cannot provide what the security profile requires, e.g. an
+    /// atomic clock.
+    #[error("The module cannot satisfy the security profile: {0}.")]
+    ProfileUnsatisfied(String),
+    /// The key's cryptoperiod has ended, or does not cover the epoch the data
+    /// was protected at.
+    #[error("The operation falls outside the key's cryptoperiod.")]
+    OutsideCryptoperiod,
+    /// A provider failed for a reason of its own, e.g. a vendor library error.
+    #[error("Provider `{provider}` failed.")]
+    Provider {
+        provider: String,
+        #[source]
+        cause: Option<ErrorCause>,
+    },
+    /// The hardware reported a fault.
+    #[error("The hardware reported a fault. {fault}")]
+    Hardware {
+        fault: HardwareFault,
+        #[source]
+        cause: Option<ErrorCause>,
+    },
+}
+
+impl From<ProfileError> for CryptoError {
+    fn from(err: ProfileError) -> Self {
+        CryptoError::InvalidProfile(err)
+    }
+}
+
+impl CryptoError {
+    /// A provider failure caused by `cause`.
+    pub fn provider(provider: &str, cause: impl Error + Send + Sync + 'static) -> Self {
+        CryptoError::Provider {
+            provider: provider.to_string(),
+            cause: Some(ErrorCause::new(cause)),
+        }
+    }
+
+    /// A hardware fault with no further detail.
+    pub fn hardware(fault: HardwareFault) -> Self {
+        CryptoError::Hardware { fault, cause: None }
+    }
+
+    /// Returns the stable numeric code of the error. Hardware faults have a
+    /// code per fault, in the 100 range.
+    pub fn code(&self) -> u16 {
+        match self {
+            CryptoError::InvalidState => 1,
+            CryptoError::AuthorizationFailed => 2,
+            CryptoError::FsmInvalidTransition => 3,
+            CryptoError::FsmUsageExceeded => 4,
+            CryptoError::DecryptionFailed => 6,
+            CryptoError::MalformedInput => 7,
+            CryptoError::CapabilityExpired => 8,
+            CryptoError::ProviderUnavailable => 9,
+            CryptoError::UnsupportedAlgorithm => 10,
+            CryptoError::VerificationFailed => 11,
+            CryptoError::NotSupported => 12,
+            CryptoError::KeyNotFound => 13,
+            CryptoError::Provider { .. } => 14,
+            CryptoError::InvalidProfile(_) => 15,
+            CryptoError::ProfileUnsatisfied(_) => 16,
+            CryptoError::OutsideCryptoperiod => 17,
+            CryptoError::Hardware { fault, .. } => match fault {
+                HardwareFault::Tamper => 100,
+                HardwareFault::Clock => 101,
+                HardwareFault::Entropy => 102,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hardware_faults_read_as_sentences() {
+        assert_eq!(
+            CryptoError::hardware(HardwareFault::Tamper).to_string(),
+            "The hardware reported a fault. A tamper sensor fired and the keys were zeroized."
+        );
+        for fault in [
+            HardwareFault::Tamper,
+            HardwareFault::Clock,
+            HardwareFault::Entropy,
+        ] {
+            let message = fault.to_string();
+            assert!(message.starts_with(char::is_uppercase) && message.ends_with('.'));
+        }
+    }
+
+    #[test]
+    fn codes_are_stable() {
+        let codes = [
+            CryptoError::InvalidState,
+            CryptoError::AuthorizationFailed,
+            CryptoError::FsmInvalidTransition,
+            CryptoError::FsmUsageExceeded,
+            CryptoError::DecryptionFailed,
+            CryptoError::MalformedInput,
+            CryptoError::CapabilityExpired,
+            CryptoError::ProviderUnavailable,
+            CryptoError::UnsupportedAlgorithm,
+            CryptoError::VerificationFailed,
+            CryptoError::NotSupported,
+            CryptoError::KeyNotFound,
+            CryptoError::provider("vendor", fmt::Error),
+            CryptoError::InvalidProfile(ProfileError::ZeroCapabilityLifetime),
+            CryptoError::ProfileUnsatisfied(String::new()),
+            CryptoError::OutsideCryptoperiod,
+            CryptoError::hardware(HardwareFault::Tamper),
+            CryptoError::hardware(HardwareFault::Clock),
+            CryptoError::hardware(HardwareFault::Entropy),
+        ]
+        .map(|err| err.code());
+        assert_eq!(
+            codes,
+            [
+                1, 2, 3, 4, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 100, 101, 102
+            ]
+        );
+    }
+}
@@ -2,15 +2,76 @@
 
 //! Defines the opaque handles that are used to interact with the Core Engine.
 
+use crate::key::KeyAttributes;
+use serde::{Deserialize, Serialize};
+use std::hash::{Hash, Hasher};
 use uuid::Uuid;
 
 /// An opaque handle to a cryptographic key.
 /// This handle does not expose the key material to the application.
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+///
+/// Handles are identified by their id alone: two handles for the same key are
+/// equal whether or not they carry attributes. Attributes are not serialized,
+/// so a handle received from a client never carries attributes it chose itself.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct KeyHandle {
     /// A unique identifier for the key.
     pub(crate) id: Uuid,
-    // Additional metadata about the key could be stored here in a non-sensitive way.
+    /// Non-sensitive attributes of the key, if the issuer attached them.
+    #[serde(skip)]
+    pub(crate) attributes: Option<KeyAttributes>,
+}
+
+impl KeyHandle {
+    /// Mints a handle for a new key, with a fresh random id.
+    pub fn new() -> Self {
+        Self::from_id(Uuid::new_v4())
+    }
+
+    /// Creates a handle for the key with the given id, e.g. one a key manager
+    /// already tracks.
+    pub fn from_id(id: Uuid) -> Self {
+        Self {
+            id,
+            attributes: None,
+        }
+    }
+
+    /// Attaches the key's public attributes to the handle.
+    pub fn with_attributes(mut self, attributes: KeyAttributes) -> Self {
+        self.attributes = Some(attributes);
+        self
+    }
+
+    /// Returns the unique identifier of the key.
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
+
+    /// Returns the key's public attributes, if the issuer attached them.
+    pub fn attributes(&self) -> Option<&KeyAttributes> {
+        self.attributes.as_ref()
+    }
+}
+
+impl Default for KeyHandle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PartialEq for KeyHandle {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Eq for KeyHandle {}
+
+impl Hash for KeyHandle {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
 }
 
 /// An opaque handle to an authorized cryptographic capability.
@@ -20,3 +81,26 @@ pub struct CapabilityHandle {
     /// A unique identifier for the capability.
     pub(crate) id: Uuid,
 }
+
+impl CapabilityHandle {
+    /// Mints a fresh, unguessable capability handle.
+    pub fn new() -> Self {
+        Self { id: Uuid::new_v4() }
+    }
+
+    /// Recreates a handle from its id, e.g. one carried by a sealed handle.
+    pub fn from_id(id: Uuid) -> Self {
+        Self { id }
+    }
+
+    /// Returns the unique identifier of the capability.
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
+}
+
+impl Default for CapabilityHandle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -2,44 +2,299 @@
 
 //! Defines the interfaces (traits) for the plug-and-play modules of the Core Engine.
 
+use crate::CryptoError;
+use crate::algorithm::{AlgorithmId, CryptoOperation};
 use crate::handles::KeyHandle;
+use crate::key::{DestructionProof, KeyFormat, KeyMetadata};
 use crate::profile::SecurityProfile;
-use crate::CryptoError;
+use crate::request::Operation;
+use std::collections::BTreeMap;
 
 /// The context for a policy evaluation.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct PolicyContext {
-    // Details about the request, user, etc.
+    /// The identity of the principal making the request.
+    pub principal: String,
+    /// The operation the requested capability would authorize.
+    pub operation: Operation,
+    /// The requested algorithm: the one the caller named or, for a fresh key,
+    /// the one the profile's cipher suite prefers. `None` if neither applies.
+    pub algorithm: Option<AlgorithmId>,
+    /// Non-sensitive attributes of the targeted key (e.g. `origin`, `provider`).
+    pub key_attributes: BTreeMap<String, String>,
+    /// The security intent the request was made under.
+    pub profile: SecurityProfile,
+    /// The engine's epoch at the time of the request: its secure clock's time,
+    /// in seconds since the UNIX epoch.
+    pub epoch: u64,
+    /// Attributes of the environment the engine runs in (e.g. `site`, `threat_level`).
+    pub environment: BTreeMap<String, String>,
+}
+
+/// How much detail the audit log must record about a capability's use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum AuditLevel {
+    /// Issuance, revocation and the outcome of every use.
+    #[default]
+    Standard,
+    /// Additionally records the size of every input processed.
+    Detailed,
+}
+
+/// Constraints a policy attaches to an allowed request, enforced by the engine
+/// on the capability it issues.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Obligations {
+    /// The maximum number of times the capability may be used.
+    pub max_uses: Option<u32>,
+    /// The maximum lifetime of the capability, in epochs from issuance.
+    pub expires_in: Option<u64>,
+    /// The minimum audit level for the capability.
+    pub audit_level: AuditLevel,
+    /// Associated data the request must carry verbatim.
+    pub required_aad: Option<Vec<u8>>,
+    /// The most operations the capability's key may perform, passed to its
+    /// key manager to lower the key's own limit.
+    pub key_max_invocations: Option<u64>,
+    /// The most bytes of input the capability's key may process in total,
+    /// likewise.
+    pub key_max_bytes: Option<u64>,
+}
+
+impl Obligations {
+    /// Combines two sets of obligations, keeping the most restrictive value of each.
+    /// Returns `None` if they cannot both be met, i.e. they require different AAD.
+    pub fn merge(&self, other: &Obligations) -> Option<Obligations> {
+        fn min<T: Ord + Copy>(a: Option<T>, b: Option<T>) -> Option<T> {
+            match (a, b) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            }
+        }
+
+        let required_aad = match (&self.required_aad, &other.required_aad) {
+            (Some(a), Some(b)) if a != b => return None,
+            (a, b) => a.clone().or_else(|| b.clone()),
+        };
+        Some(Obligations {
+            max_uses: min(self.max_uses, other.max_uses),
+            expires_in: min(self.expires_in, other.expires_in),
+            audit_level: self.audit_level.max(other.audit_level),
+            required_aad,
+            key_max_invocations: min(self.key_max_invocations, other.key_max_invocations),
+            key_max_bytes: min(self.key_max_bytes, other.key_max_bytes),
+        })
+    }
 }
 
 /// The result of a policy evaluation.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct PolicyDecision {
     pub allow: bool,
-    // Additional constraints, lifetime, etc.
+    /// Whether the policy had an opinion on the request. A non-applicable
+    /// decision never allows, but lets policy combinators consult other layers.
+    pub applicable: bool,
+    /// Constraints on the issued capability. Ignored when `allow` is false.
+    pub obligations: Obligations,
+    /// A human-readable explanation of the decision.
+    pub reason: Option<String>,
+}
+
+impl PolicyDecision {
+    /// Allows the request without further constraints.
+    pub fn permit() -> Self {
+        Self {
+            allow: true,
+            applicable: true,
+            obligations: Obligations::default(),
+            reason: None,
+        }
+    }
+
+    /// Denies the request for the given reason.
+    pub fn deny(reason: &str) -> Self {
+        Self {
+            allow: false,
+            applicable: true,
+            obligations: Obligations::default(),
+            reason: Some(reason.to_string()),
+        }
+    }
+
+    /// Declines to decide on the request. The engine treats this as a denial.
+    pub fn not_applicable(reason: &str) -> Self {
+        Self {
+            allow: false,
+            applicable: false,
+            obligations: Obligations::default(),
+            reason: Some(reason.to_string()),
+        }
+    }
+
+    /// Attaches obligations to the decision.
+    pub fn with_obligations(mut self, obligations: Obligations) -> Self {
+        self.obligations = obligations;
+        self
+    }
+}
+
+/// One step of a policy evaluation trace, e.g. a rule that was tested or a
+/// layer of a composed policy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceStep {
+    /// How deeply the step is nested inside composed policies.
+    pub depth: usize,
+    /// What produced the step, e.g. `rule "defense-operators"` or `DenyOverrides`.
+    pub source: String,
+    /// Whether the rule matched or the layer had an opinion.
+    pub applicable: bool,
+    /// The effect of the step when applicable.
+    pub allow: bool,
+    /// The attributes consulted, in order, with the value seen (`None` if absent).
+    pub consulted: Vec<(String, Option<String>)>,
+    /// The obligations the step contributes.
+    pub obligations: Obligations,
+    pub reason: Option<String>,
+}
+
+impl TraceStep {
+    /// Describes a decision as a single step with no further detail.
+    pub fn from_decision(source: &str, decision: &PolicyDecision) -> Self {
+        Self {
+            depth: 0,
+            source: source.to_string(),
+            applicable: decision.applicable,
+            allow: decision.allow,
+            consulted: Vec::new(),
+            obligations: decision.obligations.clone(),
+            reason: decision.reason.clone(),
+        }
+    }
+}
+
+/// A policy decision together with the trace explaining it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PolicyEvaluation {
+    pub decision: PolicyDecision,
+    pub trace: Vec<TraceStep>,
 }
 
 /// An interface for a policy execution module.
 pub trait PolicyInterface {
     /// Evaluates a security policy for a given context.
     fn evaluate(&self, context: &PolicyContext) -> Result<PolicyDecision, CryptoError>;
+
+    /// Evaluates the policy like `evaluate`, and also reports how the decision
+    /// was reached. Must not have side effects beyond those of `evaluate`.
+    /// The default reports the decision as a single opaque step.
+    fn explain(&self, context: &PolicyContext) -> Result<PolicyEvaluation, CryptoError> {
+        let decision = self.evaluate(context)?;
+        Ok(PolicyEvaluation {
+            trace: vec![TraceStep::from_decision("policy", &decision)],
+            decision,
+        })
+    }
 }
 
 /// An interface for a cryptographic provider.
 pub trait CryptoProvider {
-    /// Executes a cryptographic operation using the given key.
+    /// The algorithms the provider implements.
+    fn algorithms(&self) -> &[AlgorithmId];
+
+    /// Executes a cryptographic operation using the given key. Providers must
+    /// reject algorithms they do not implement, and operations the algorithm
+    /// cannot perform, with `UnsupportedAlgorithm`.
     fn execute_crypto(
         &self,
         key_handle: &KeyHandle,
-        operation: &str, // e.g., "encrypt", "decrypt", "sign", "verify"
+        algorithm: AlgorithmId,
+        operation: CryptoOperation<'_>,
         data: &[u8],
     ) -> Result<Vec<u8>, CryptoError>;
+
+    /// Returns `true` if the provider can perform `operation` with `algorithm`.
+    fn supports(&self, algorithm: AlgorithmId, operation: Operation) -> bool {
+        self.algorithms().contains(&algorithm) && algorithm.supports(operation)
+    }
 }
 
 /// An interface for a key management module.
+///
+/// Only `generate_key` and `delete_key` are required. The other lifecycle
+/// operations default to failing with `NotSupported`, so simple modules can
+/// opt out of them.
 pub trait KeyManager {
-    /// Generates a new cryptographic key.
-    fn generate_key(&self, profile: &SecurityProfile) -> Result<KeyHandle, CryptoError>;
+    /// Generates a new cryptographic key for `algorithm`.
+    fn generate_key(
+        &self,
+        profile: &SecurityProfile,
+        algorithm: AlgorithmId,
+    ) -> Result<KeyHandle, CryptoError>;
     /// Deletes a cryptographic key.
     fn delete_key(&self, key_handle: &KeyHandle) -> Result<(), CryptoError>;
+
+    /// Imports existing key material for `algorithm`, encoded as `format`.
+    fn import_key(
+        &self,
+        _profile: &SecurityProfile,
+        _algorithm: AlgorithmId,
+        _format: KeyFormat,
+        _material: &[u8],
+    ) -> Result<KeyHandle, CryptoError> {
+        Err(CryptoError::NotSupported)
+    }
+
+    /// Exports an exportable key wrapped under `wrapping_key`, a wrap-capable
+    /// key held by the same module.
+    fn export_wrapped(
+        &self,
+        _key_handle: &KeyHandle,
+        _wrapping_key: &KeyHandle,
+    ) -> Result<Vec<u8>, CryptoError> {
+        Err(CryptoError::NotSupported)
+    }
+
+    /// Lowers the usage limits of a key to at most `max_invocations`
+    /// operations and `max_bytes` bytes of input, where given. Limits are
+    /// never raised.
+    fn restrict_key_usage(
+        &self,
+        _key_handle: &KeyHandle,
+        _max_invocations: Option<u64>,
+        _max_bytes: Option<u64>,
+    ) -> Result<(), CryptoError> {
+        Err(CryptoError::NotSupported)
+    }
+
+    /// Generates a successor for a key, with the same algorithm and labels.
+    /// The old key is left in place so data protected under it stays readable.
+    fn rotate_key(&self, _key_handle: &KeyHandle) -> Result<KeyHandle, CryptoError> {
+        Err(CryptoError::NotSupported)
+    }
+
+    /// Enumerates the keys held by the module.
+    fn list_keys(&self) -> Result<Vec<KeyMetadata>, CryptoError> {
+        Err(CryptoError::NotSupported)
+    }
+
+    /// Returns the metadata of a key.
+    fn key_metadata(&self, _key_handle: &KeyHandle) -> Result<KeyMetadata, CryptoError> {
+        Err(CryptoError::NotSupported)
+    }
+
+    /// Replaces the labels of a key.
+    fn set_labels(
+        &self,
+        _key_handle: &KeyHandle,
+        _labels: BTreeMap<String, String>,
+    ) -> Result<(), CryptoError> {
+        Err(CryptoError::NotSupported)
+    }
+
+    /// Destroys a key and returns evidence that it was destroyed.
+    fn destroy_key(&self, _key_handle: &KeyHandle) -> Result<DestructionProof, CryptoError> {
+        Err(CryptoError::NotSupported)
+    }
 }
 
 /// An interface for an entropy provider.
@@ -47,3 +302,13 @@ pub trait EntropyProvider {
     /// Gets a block of high-quality entropy.
     fn get_entropy(&self, length: usize) -> Result<Vec<u8>, CryptoError>;
 }
+
+/// An interface for receiving security-relevant events observed inside a
+/// module, such as a hardware module's key state changes and tamper alarms,
+/// e.g. to audit them.
+pub trait ModuleEventSink {
+    /// A key's lifecycle state changed from `from` to `to`.
+    fn fsm_transition(&self, key_handle: Option<&KeyHandle>, from: &str, to: &str);
+    /// The module detected tampering, signalled by `source`.
+    fn tamper_event(&self, source: &str);
+}
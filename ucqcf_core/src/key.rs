@@ -0,0 +1,93 @@
+// ucqcf_core/src/key.rs
+
+//! The key metadata model shared by key management modules and the Core Engine.
+
+use crate::algorithm::AlgorithmId;
+use crate::handles::KeyHandle;
+use crate::request::Operation;
+use std::collections::BTreeMap;
+
+/// The public, non-secret attributes of a key, reachable from its `KeyHandle`.
+/// Epochs are on the clock of the key manager that owns the key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyAttributes {
+    pub algorithm: AlgorithmId,
+    /// The operations the key may be used for. A subset of what the algorithm can do.
+    pub allowed_operations: Vec<Operation>,
+    /// The epoch at which the key was created.
+    pub created_at: u64,
+    /// The epoch after which the key must no longer be used, if any.
+    pub expires_at: Option<u64>,
+    /// The principal or organisation responsible for the key, if recorded.
+    pub owner: Option<String>,
+    /// Free-form labels set by operators (e.g. `mission`, `environment`).
+    pub labels: BTreeMap<String, String>,
+    /// The hardware module holding the key (e.g. `ciem`), or `None` for software keys.
+    pub origin_hardware: Option<String>,
+}
+
+impl KeyAttributes {
+    /// Creates attributes for a software key allowing every operation of `algorithm`.
+    pub fn new(algorithm: AlgorithmId, created_at: u64) -> Self {
+        Self {
+            algorithm,
+            allowed_operations: algorithm.operations().to_vec(),
+            created_at,
+            expires_at: None,
+            owner: None,
+            labels: BTreeMap::new(),
+            origin_hardware: None,
+        }
+    }
+
+    /// Returns `true` if the key may be used for `operation`.
+    pub fn allows(&self, operation: Operation) -> bool {
+        self.allowed_operations.contains(&operation)
+    }
+}
+
+/// The encoding of key material handed to `KeyManager::import_key`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KeyFormat {
+    /// The bare key bytes, e.g. a 32-byte AES-256 key.
+    Raw,
+    /// A DER-encoded PKCS#8 `PrivateKeyInfo` (RFC 5208).
+    Pkcs8,
+    /// A UTF-8 JSON Web Key (RFC 7517).
+    Jwk,
+}
+
+/// How a key came to exist.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KeyOrigin {
+    /// Generated inside the key manager.
+    Generated,
+    /// Imported from outside the key manager.
+    Imported,
+    /// Generated to replace another key (see `KeyMetadata::predecessor`).
+    Rotated,
+}
+
+/// Everything a key manager discloses about a key: its public attributes plus
+/// lifecycle information. Never contains key material.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyMetadata {
+    pub handle: KeyHandle,
+    pub attributes: KeyAttributes,
+    pub origin: KeyOrigin,
+    /// The key this one replaced, if it was created by rotation.
+    pub predecessor: Option<KeyHandle>,
+    /// Whether the key may leave the key manager wrapped under another key.
+    pub exportable: bool,
+}
+
+/// Evidence that a key manager destroyed a key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DestructionProof {
+    pub key: KeyHandle,
+    /// The epoch at which the key was destroyed, on the key manager's clock.
+    pub destroyed_at: u64,
+    /// Module-specific evidence, e.g. a signature by the module's attestation
+    /// key over the key id and `destroyed_at`. Empty if the module cannot attest.
+    pub attestation: Vec<u8>,
+}
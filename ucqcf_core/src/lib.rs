@@ -1,24 +1,16 @@
 // ucqcf_core/src/lib.rs
 
 // Declare the sub-modules
+pub mod algorithm;
 pub mod capability;
+pub mod clock;
+pub mod error;
 pub mod handles;
 pub mod interfaces;
+pub mod key;
+pub mod preset;
 pub mod profile;
+pub mod request;
+pub mod suite;
 
-/// Common error type for the framework, used across different crates.
-#[derive(Debug, PartialEq, Eq)]
-pub enum CryptoError {
-    /// The operation is not valid in the current state of the FSM.
-    InvalidState,
-    /// The requested operation was not authorized by the security policy.
-    AuthorizationFailed,
-    /// An FSM transition was attempted from an invalid state.
-    FsmInvalidTransition,
-    /// A usage limit for a key or resource was exceeded.
-    FsmUsageExceeded,
-    /// An entropy source failed a health check.
-    EntropyHealthCheckFailed,
-    /// Decryption failed, likely due to an incorrect key or tampered ciphertext.
-    DecryptionFailed,
-}
+pub use crate::error::{CryptoError, ErrorCause, HardwareFault};
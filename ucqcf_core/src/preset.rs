@@ -0,0 +1,373 @@
+// ucqcf_core/src/preset.rs
+
+//! Named `SecurityProfile` presets, built in or loaded from configuration.
+//!
+//! A configuration file holds a `profiles` table mapping preset names to
+//! profiles, in TOML:
+//!
+//! ```toml
+//! [profiles.mission-planning]
+//! domain = "defense"
+//! quantum_resistant = true
+//! security_strength = 256
+//! compliance = "cnsa2"
+//! classification = "secret"
+//! ```
+//!
+//! or the equivalent JSON (`{"profiles": {"mission-planning": {...}}}`).
+//! Every preset is validated when it is loaded, so a contradictory profile is
+//! reported at startup rather than when the first request is refused.
+
+use crate::profile::{
+    AlgorithmSuite, ComplianceRegime, DataClassification, Domain, ProfileError, SecurityProfile,
+    SecurityStrength,
+};
+use serde::Deserialize;
+use serde::de::{Deserializer, MapAccess, Visitor};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// An error loading presets.
+#[derive(Debug, Error)]
+pub enum PresetLoadError {
+    #[error("Could not read preset file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid TOML presets: {0}")]
+    Toml(#[from] toml::de::Error),
+    #[error("Invalid JSON presets: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Preset file `{}` is neither .toml nor .json.", .0.display())]
+    UnknownFormat(PathBuf),
+    #[error("Preset `{name}` is invalid: {source}")]
+    Invalid {
+        name: String,
+        #[source]
+        source: ProfileError,
+    },
+    #[error("Preset `{0}` is already defined.")]
+    Duplicate(String),
+}
+
+/// The layout of a preset file. The presets are kept in file order, with
+/// any repeated name, so that a name defined twice can be reported.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PresetFile {
+    #[serde(deserialize_with = "entries")]
+    profiles: Vec<(String, SecurityProfile)>,
+}
+
+fn entries<'de, D>(deserializer: D) -> Result<Vec<(String, SecurityProfile)>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    struct Entries;
+
+    impl<'de> Visitor<'de> for Entries {
+        type Value = Vec<(String, SecurityProfile)>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a table of named profiles")
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+            let mut entries = Vec::new();
+            while let Some(entry) = map.next_entry()? {
+                entries.push(entry);
+            }
+            Ok(entries)
+        }
+    }
+
+    deserializer.deserialize_map(Entries)
+}
+
+/// A set of named, validated profiles.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProfileRegistry {
+    presets: BTreeMap<String, SecurityProfile>,
+}
+
+impl ProfileRegistry {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a registry holding the built-in presets:
+    ///
+    /// - `defense-top-secret`: CNSA 2.0, 256-bit, quantum-resistant, atomic time.
+    /// - `defense-secret`: FIPS 140-3, 192-bit, quantum-resistant, atomic time.
+    /// - `telecom-5g-core`: 128-bit NIST and CFRG algorithms, as used by 5G.
+    /// - `blockchain-ledger`: the ledger algorithms at 128 bits.
+    /// - `end-to-end-messaging`: CFRG algorithms at 128 bits.
+    /// - `end-to-end-post-quantum`: CFRG and post-quantum algorithms at 256
+    ///   bits, for data that must stay confidential for decades.
+    pub fn builtin() -> Self {
+        let presets = [
+            (
+                "defense-top-secret",
+                SecurityProfile {
+                    quantum_resistant: true,
+                    require_atomic_time: true,
+                    security_strength: SecurityStrength::Bits256,
+                    compliance: Some(ComplianceRegime::Cnsa2),
+                    classification: DataClassification::TopSecret,
+                    protection_lifetime_years: Some(25),
+                    ..SecurityProfile::new(Domain::Defense)
+                },
+            ),
+            (
+                "defense-secret",
+                SecurityProfile {
+                    quantum_resistant: true,
+                    require_atomic_time: true,
+                    security_strength: SecurityStrength::Bits192,
+                    compliance: Some(ComplianceRegime::Fips140_3),
+                    classification: DataClassification::Secret,
+                    protection_lifetime_years: Some(25),
+                    ..SecurityProfile::new(Domain::Defense)
+                },
+            ),
+            (
+                "telecom-5g-core",
+                SecurityProfile {
+                    allowed_suites: vec![AlgorithmSuite::Nist, AlgorithmSuite::Cfrg],
+                    classification: DataClassification::Confidential,
+                    ..SecurityProfile::new(Domain::Telecom)
+                },
+            ),
+            (
+                "blockchain-ledger",
+                SecurityProfile {
+                    allowed_suites: vec![AlgorithmSuite::Ledger],
+                    ..SecurityProfile::new(Domain::Blockchain)
+                },
+            ),
+            (
+                "end-to-end-messaging",
+                SecurityProfile {
+                    allowed_suites: vec![AlgorithmSuite::Cfrg],
+                    classification: DataClassification::Confidential,
+                    ..SecurityProfile::new(Domain::EndToEnd)
+                },
+            ),
+            (
+                "end-to-end-post-quantum",
+                SecurityProfile {
+                    quantum_resistant: true,
+                    security_strength: SecurityStrength::Bits256,
+                    allowed_suites: vec![AlgorithmSuite::Cfrg, AlgorithmSuite::PostQuantum],
+                    classification: DataClassification::Confidential,
+                    protection_lifetime_years: Some(30),
+                    ..SecurityProfile::new(Domain::EndToEnd)
+                },
+            ),
+        ];
+        Self {
+            presets: presets
+                .into_iter()
+                .map(|(name, profile)| (name.to_string(), profile))
+                .collect(),
+        }
+    }
+
+    /// Adds a preset after validating it. Names must be unique.
+    pub fn insert(&mut self, name: &str, profile: SecurityProfile) -> Result<(), PresetLoadError> {
+        self.check(name, &profile)?;
+        self.presets.insert(name.to_string(), profile);
+        Ok(())
+    }
+
+    /// Returns the preset called `name`.
+    pub fn get(&self, name: &str) -> Option<&SecurityProfile> {
+        self.presets.get(name)
+    }
+
+    /// Returns the names of all presets, in order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.presets.keys().map(String::as_str)
+    }
+
+    /// Loads the presets of a TOML document. If any preset is invalid or
+    /// already defined, none is added.
+    pub fn load_toml(&mut self, source: &str) -> Result<(), PresetLoadError> {
+        let file: PresetFile = toml::from_str(source)?;
+        self.load(file)
+    }
+
+    /// Loads the presets of a JSON document. If any preset is invalid or
+    /// already defined, none is added.
+    pub fn load_json(&mut self, source: &str) -> Result<(), PresetLoadError> {
+        let file: PresetFile = serde_json::from_str(source)?;
+        self.load(file)
+    }
+
+    /// Loads a `.toml` or `.json` preset file.
+    pub fn load_file(&mut self, path: impl AsRef<Path>) -> Result<(), PresetLoadError> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => self.load_toml(&source),
+            Some("json") => self.load_json(&source),
+            _ => Err(PresetLoadError::UnknownFormat(path.to_path_buf())),
+        }
+    }
+
+    fn load(&mut self, file: PresetFile) -> Result<(), PresetLoadError> {
+        let mut names = BTreeSet::new();
+        for (name, profile) in &file.profiles {
+            if !names.insert(name) {
+                return Err(PresetLoadError::Duplicate(name.clone()));
+            }
+            self.check(name, profile)?;
+        }
+        self.presets.extend(file.profiles);
+        Ok(())
+    }
+
+    fn check(&self, name: &str, profile: &SecurityProfile) -> Result<(), PresetLoadError> {
+        if self.presets.contains_key(name) {
+            return Err(PresetLoadError::Duplicate(name.to_string()));
+        }
+        profile
+            .validate()
+            .map_err(|source| PresetLoadError::Invalid {
+                name: name.to_string(),
+                source,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Serialize;
+
+    #[derive(Serialize)]
+    struct Presets<'a> {
+        profiles: &'a BTreeMap<String, SecurityProfile>,
+    }
+
+    const MISSION_PLANNING: &str = r#"
+        [profiles.mission-planning]
+        domain = "defense"
+        quantum_resistant = true
+        security_strength = 256
+        compliance = "cnsa2"
+        classification = "secret"
+    "#;
+
+    fn temp_file(name: &str, contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("ucqcf-{}-{name}", std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn builtin_presets_round_trip_through_toml_and_json() {
+        let builtin = ProfileRegistry::builtin();
+        let presets = Presets {
+            profiles: &builtin.presets,
+        };
+
+        let mut from_toml = ProfileRegistry::new();
+        from_toml
+            .load_toml(&toml::to_string(&presets).unwrap())
+            .unwrap();
+        assert_eq!(from_toml, builtin);
+
+        let mut from_json = ProfileRegistry::new();
+        from_json
+            .load_json(&serde_json::to_string(&presets).unwrap())
+            .unwrap();
+        assert_eq!(from_json, builtin);
+    }
+
+    #[test]
+    fn files_are_loaded_by_extension() {
+        let mut registry = ProfileRegistry::new();
+        let toml_path = temp_file("presets.toml", MISSION_PLANNING);
+        registry.load_file(&toml_path).unwrap();
+        std::fs::remove_file(&toml_path).unwrap();
+        assert_eq!(
+            registry.get("mission-planning"),
+            Some(&SecurityProfile {
+                quantum_resistant: true,
+                security_strength: SecurityStrength::Bits256,
+                compliance: Some(ComplianceRegime::Cnsa2),
+                classification: DataClassification::Secret,
+                ..SecurityProfile::new(Domain::Defense)
+            })
+        );
+
+        let yaml_path = temp_file("presets.yaml", MISSION_PLANNING);
+        let result = ProfileRegistry::new().load_file(&yaml_path);
+        std::fs::remove_file(&yaml_path).unwrap();
+        assert!(matches!(
+            result,
+            Err(PresetLoadError::UnknownFormat(path)) if path == yaml_path
+        ));
+    }
+
+    #[test]
+    fn builtin_names_cannot_be_redefined() {
+        let mut registry = ProfileRegistry::builtin();
+        let result = registry.load_json(
+            r#"{"profiles": {
+                "analysis": {"domain": "defense"},
+                "defense-secret": {"domain": "defense"}
+            }}"#,
+        );
+        assert!(matches!(
+            result,
+            Err(PresetLoadError::Duplicate(name)) if name == "defense-secret"
+        ));
+        assert_eq!(registry, ProfileRegistry::builtin());
+    }
+
+    #[test]
+    fn a_name_defined_twice_in_one_file_is_rejected() {
+        let mut registry = ProfileRegistry::new();
+        let result = registry.load_json(
+            r#"{"profiles": {
+                "analysis": {"domain": "defense"},
+                "analysis": {"domain": "telecom"}
+            }}"#,
+        );
+        assert!(matches!(
+            result,
+            Err(PresetLoadError::Duplicate(name)) if name == "analysis"
+        ));
+        assert_eq!(registry, ProfileRegistry::new());
+    }
+
+    #[test]
+    fn invalid_presets_leave_the_registry_unchanged() {
+        let mut registry = ProfileRegistry::new();
+        let result = registry.load_toml(&format!(
+            "{MISSION_PLANNING}
+            [profiles.weak]
+            domain = \"defense\"
+            security_strength = 112
+            compliance = \"cnsa2\"
+            "
+        ));
+        assert!(matches!(
+            result,
+            Err(PresetLoadError::Invalid {
+                name,
+                source: ProfileError::StrengthBelowRegime { .. },
+            }) if name == "weak"
+        ));
+        assert_eq!(registry, ProfileRegistry::new());
+
+        assert!(matches!(
+            registry.load_json(r#"{"profiles": {"analysis": {"domain": "unknown"}}}"#),
+            Err(PresetLoadError::Json(_))
+        ));
+        assert_eq!(registry, ProfileRegistry::new());
+    }
+}
@@ -1,7 +1,18 @@
 // ucqcf_core/src/profile.rs
 
+use crate::algorithm::AlgorithmId;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use thiserror::Error;
+
+/// Protection lifetimes longer than this many years are assumed to outlive
+/// classical public-key cryptography, so data harvested today could be
+/// decrypted by a quantum adversary before it stops being sensitive.
+pub const QUANTUM_HORIZON_YEARS: u32 = 10;
+
 /// Represents the mission domain, influencing cryptographic choices.
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Domain {
     Defense,
     Telecom,
@@ -9,11 +20,586 @@ pub enum Domain {
     EndToEnd,
 }
 
+/// A security strength in bits, as defined by NIST SP 800-57 Part 1.
+/// Serialized as the number of bits.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(try_from = "u16", into = "u16")]
+pub enum SecurityStrength {
+    Bits112,
+    #[default]
+    Bits128,
+    Bits192,
+    Bits256,
+}
+
+impl SecurityStrength {
+    pub fn bits(&self) -> u16 {
+        match self {
+            SecurityStrength::Bits112 => 112,
+            SecurityStrength::Bits128 => 128,
+            SecurityStrength::Bits192 => 192,
+            SecurityStrength::Bits256 => 256,
+        }
+    }
+}
+
+impl TryFrom<u16> for SecurityStrength {
+    type Error = String;
+
+    fn try_from(bits: u16) -> Result<Self, Self::Error> {
+        match bits {
+            112 => Ok(SecurityStrength::Bits112),
+            128 => Ok(SecurityStrength::Bits128),
+            192 => Ok(SecurityStrength::Bits192),
+            256 => Ok(SecurityStrength::Bits256),
+            _ => Err(format!(
+                "unsupported security strength {bits}, expected 112, 128, 192 or 256"
+            )),
+        }
+    }
+}
+
+impl From<SecurityStrength> for u16 {
+    fn from(strength: SecurityStrength) -> Self {
+        strength.bits()
+    }
+}
+
+impl fmt::Display for SecurityStrength {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-bit", self.bits())
+    }
+}
+
+/// A named family of algorithms a profile may draw from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlgorithmSuite {
+    /// The NSA Commercial National Security Algorithm Suite 2.0, without the
+    /// transitional CNSA 1.0 algorithms.
+    Cnsa2,
+    /// The NIST-approved algorithms: AES, HMAC, HKDF, ECDSA over the NIST
+    /// curves, Ed25519, ML-KEM and ML-DSA.
+    Nist,
+    /// The IETF/CFRG algorithms common in end-to-end protocols.
+    Cfrg,
+    /// The algorithms used by blockchain ledgers.
+    Ledger,
+    /// The post-quantum public-key algorithms.
+    PostQuantum,
+}
+
+impl AlgorithmSuite {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AlgorithmSuite::Cnsa2 => "cnsa2",
+            AlgorithmSuite::Nist => "nist",
+            AlgorithmSuite::Cfrg => "cfrg",
+            AlgorithmSuite::Ledger => "ledger",
+            AlgorithmSuite::PostQuantum => "post_quantum",
+        }
+    }
+
+    pub fn algorithms(&self) -> &'static [AlgorithmId] {
+        match self {
+            AlgorithmSuite::Cnsa2 => &[
+                AlgorithmId::Aes256Gcm,
+                AlgorithmId::Aes256Kw,
+                AlgorithmId::HmacSha384,
+                AlgorithmId::HkdfSha384,
+                AlgorithmId::MlKem1024,
+                AlgorithmId::MlDsa87,
+            ],
+            AlgorithmSuite::Nist => &[
+                AlgorithmId::Aes128Gcm,
+                AlgorithmId::Aes256Gcm,
+                AlgorithmId::Aes256Kw,
+                AlgorithmId::HmacSha256,
+                AlgorithmId::HmacSha384,
+                AlgorithmId::HkdfSha256,
+                AlgorithmId::HkdfSha384,
+                AlgorithmId::EcdsaP256Sha256,
+                AlgorithmId::EcdsaP384Sha384,
+                AlgorithmId::Ed25519,
+                AlgorithmId::MlKem768,
+                AlgorithmId::MlKem1024,
+                AlgorithmId::MlDsa65,
+                AlgorithmId::MlDsa87,
+            ],
+            AlgorithmSuite::Cfrg => &[
+                AlgorithmId::ChaCha20Poly1305,
+                AlgorithmId::Aes256Gcm,
+                AlgorithmId::HmacSha256,
+                AlgorithmId::HkdfSha256,
+                AlgorithmId::X25519,
+                AlgorithmId::Ed25519,
+            ],
+            AlgorithmSuite::Ledger => &[
+                AlgorithmId::EcdsaSecp256k1Sha256,
+                AlgorithmId::Ed25519,
+                AlgorithmId::Aes256Gcm,
+                AlgorithmId::HmacSha256,
+                AlgorithmId::HkdfSha256,
+            ],
+            AlgorithmSuite::PostQuantum => &[
+                AlgorithmId::MlKem768,
+                AlgorithmId::MlKem1024,
+                AlgorithmId::MlDsa65,
+                AlgorithmId::MlDsa87,
+            ],
+        }
+    }
+
+    pub fn contains(&self, algorithm: AlgorithmId) -> bool {
+        self.algorithms().contains(&algorithm)
+    }
+}
+
+impl fmt::Display for AlgorithmSuite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A compliance regime the profile must conform to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ComplianceRegime {
+    /// FIPS 140-3: only NIST-approved algorithms, at 112 bits or more.
+    #[serde(rename = "fips140-3")]
+    Fips140_3,
+    /// CNSA 2.0: only the CNSA 2.0 suite, at 256 bits, quantum-resistant.
+    #[serde(rename = "cnsa2")]
+    Cnsa2,
+    /// BSI TR-02102-1: the algorithms recommended by the BSI, at 128 bits or more.
+    #[serde(rename = "bsi-tr-02102")]
+    BsiTr02102,
+}
+
+impl ComplianceRegime {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ComplianceRegime::Fips140_3 => "fips140-3",
+            ComplianceRegime::Cnsa2 => "cnsa2",
+            ComplianceRegime::BsiTr02102 => "bsi-tr-02102",
+        }
+    }
+
+    /// The lowest security strength the regime accepts.
+    pub fn minimum_strength(&self) -> SecurityStrength {
+        match self {
+            ComplianceRegime::Fips140_3 => SecurityStrength::Bits112,
+            ComplianceRegime::Cnsa2 => SecurityStrength::Bits256,
+            ComplianceRegime::BsiTr02102 => SecurityStrength::Bits128,
+        }
+    }
+
+    /// Returns `true` if the regime approves `algorithm`.
+    pub fn approves(&self, algorithm: AlgorithmId) -> bool {
+        match self {
+            ComplianceRegime::Fips140_3 => AlgorithmSuite::Nist.contains(algorithm),
+            ComplianceRegime::Cnsa2 => AlgorithmSuite::Cnsa2.contains(algorithm),
+            ComplianceRegime::BsiTr02102 => {
+                AlgorithmSuite::Nist.contains(algorithm) && algorithm != AlgorithmId::Ed25519
+            }
+        }
+    }
+}
+
+impl fmt::Display for ComplianceRegime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ComplianceRegime::Fips140_3 => "FIPS 140-3",
+            ComplianceRegime::Cnsa2 => "CNSA 2.0",
+            ComplianceRegime::BsiTr02102 => "BSI TR-02102",
+        })
+    }
+}
+
+/// The classification of the data a profile protects.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum DataClassification {
+    #[default]
+    Unclassified,
+    Confidential,
+    Secret,
+    TopSecret,
+}
+
+impl DataClassification {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DataClassification::Unclassified => "unclassified",
+            DataClassification::Confidential => "confidential",
+            DataClassification::Secret => "secret",
+            DataClassification::TopSecret => "top_secret",
+        }
+    }
+
+    /// The lowest security strength acceptable for data of this classification.
+    pub fn minimum_strength(&self) -> SecurityStrength {
+        match self {
+            DataClassification::Unclassified => SecurityStrength::Bits112,
+            DataClassification::Confidential | DataClassification::Secret => {
+                SecurityStrength::Bits128
+            }
+            DataClassification::TopSecret => SecurityStrength::Bits192,
+        }
+    }
+}
+
+impl fmt::Display for DataClassification {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 /// Defines the security intent for a cryptographic operation.
 /// This is the primary configuration object users interact with.
-#[derive(Debug, PartialEq, Eq)]
+///
+/// Start from `SecurityProfile::new` and override the fields that matter, and
+/// check the result with `validate`. When deserialized, only `domain` is
+/// required; the other fields default as in `new`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct SecurityProfile {
     pub domain: Domain,
+    #[serde(default)]
     pub quantum_resistant: bool,
+    #[serde(default)]
     pub require_atomic_time: bool,
+    /// The minimum security strength of every algorithm used.
+    #[serde(default)]
+    pub security_strength: SecurityStrength,
+    /// The suites algorithms may be drawn from. An empty list means any suite.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_suites: Vec<AlgorithmSuite>,
+    /// The compliance regime to conform to, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compliance: Option<ComplianceRegime>,
+    #[serde(default)]
+    pub classification: DataClassification,
+    /// How many years the protected data must stay confidential, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub protection_lifetime_years: Option<u32>,
+    /// The longest lifetime, in epochs (seconds), of a capability issued
+    /// under the profile. `None` leaves it to the engine and the policy.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_capability_lifetime: Option<u64>,
+    /// The cryptoperiod of keys generated under the profile: how many epochs
+    /// (seconds) after activation they may protect data. `None` leaves it unlimited.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_cryptoperiod: Option<u64>,
+    /// The most operations a key generated under the profile may perform.
+    /// `None` leaves it to the algorithm's safety bounds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_max_invocations: Option<u64>,
+    /// The most bytes a key generated under the profile may process in total.
+    /// `None` leaves it unlimited.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_max_bytes: Option<u64>,
+}
+
+impl SecurityProfile {
+    /// Creates a profile for `domain` with 128-bit strength, no compliance
+    /// regime, unclassified data and no other restrictions.
+    pub fn new(domain: Domain) -> Self {
+        Self {
+            domain,
+            quantum_resistant: false,
+            require_atomic_time: false,
+            security_strength: SecurityStrength::default(),
+            allowed_suites: Vec::new(),
+            compliance: None,
+            classification: DataClassification::default(),
+            protection_lifetime_years: None,
+            max_capability_lifetime: None,
+            key_cryptoperiod: None,
+            key_max_invocations: None,
+            key_max_bytes: None,
+        }
+    }
+
+    /// Returns `true` if `algorithm` may be used under the profile: it is strong
+    /// enough, quantum-resistant if required, in an allowed suite and approved
+    /// by the compliance regime.
+    pub fn permits(&self, algorithm: AlgorithmId) -> bool {
+        algorithm.security_strength() >= self.security_strength
+            && (!self.quantum_resistant || algorithm.quantum_resistant())
+            && (self.allowed_suites.is_empty()
+                || self.allowed_suites.iter().any(|s| s.contains(algorithm)))
+            && self.compliance.is_none_or(|c| c.approves(algorithm))
+    }
+
+    /// Checks that the profile is self-consistent.
+    pub fn validate(&self) -> Result<(), ProfileError> {
+        if let Some(regime) = self.compliance {
+            if self.security_strength < regime.minimum_strength() {
+                return Err(ProfileError::StrengthBelowRegime {
+                    regime,
+                    required: regime.minimum_strength(),
+                });
+            }
+            if regime == ComplianceRegime::Cnsa2 && !self.quantum_resistant {
+                return Err(ProfileError::RegimeRequiresQuantumResistance(regime));
+            }
+        }
+        if self.security_strength < self.classification.minimum_strength() {
+            return Err(ProfileError::StrengthBelowClassification {
+                classification: self.classification,
+                required: self.classification.minimum_strength(),
+            });
+        }
+        if let Some(years) = self.protection_lifetime_years
+            && years > QUANTUM_HORIZON_YEARS
+            && !self.quantum_resistant
+        {
+            return Err(ProfileError::ProtectionOutlivesClassicalCrypto { years });
+        }
+        if self.max_capability_lifetime == Some(0) {
+            return Err(ProfileError::ZeroCapabilityLifetime);
+        }
+        if self.key_cryptoperiod == Some(0) {
+            return Err(ProfileError::ZeroCryptoperiod);
+        }
+        if self.key_max_invocations == Some(0) || self.key_max_bytes == Some(0) {
+            return Err(ProfileError::ZeroKeyLimit);
+        }
+        if let Some(suite) = self
+            .allowed_suites
+            .iter()
+            .find(|s| !s.algorithms().iter().any(|a| self.permits(*a)))
+        {
+            return Err(ProfileError::SuiteExcluded(*suite));
+        }
+        if !AlgorithmId::ALL.into_iter().any(|a| self.permits(a)) {
+            return Err(ProfileError::NoPermittedAlgorithm);
+        }
+        Ok(())
+    }
+}
+
+/// Why a `SecurityProfile` is contradictory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum ProfileError {
+    #[error("{regime} requires a security strength of at least {required}")]
+    StrengthBelowRegime {
+        regime: ComplianceRegime,
+        required: SecurityStrength,
+    },
+    #[error("{classification} data requires a security strength of at least {required}")]
+    StrengthBelowClassification {
+        classification: DataClassification,
+        required: SecurityStrength,
+    },
+    #[error("{0} requires quantum resistance")]
+    RegimeRequiresQuantumResistance(ComplianceRegime),
+    #[error("data protected for {years} years requires quantum resistance")]
+    ProtectionOutlivesClassicalCrypto { years: u32 },
+    #[error("the maximum capability lifetime is zero")]
+    ZeroCapabilityLifetime,
+    #[error("the key cryptoperiod is zero")]
+    ZeroCryptoperiod,
+    #[error("a key usage limit is zero")]
+    ZeroKeyLimit,
+    #[error("no algorithm of the `{0}` suite is permitted by the rest of the profile")]
+    SuiteExcluded(AlgorithmSuite),
+    #[error("no algorithm is permitted by the profile")]
+    NoPermittedAlgorithm,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Edit = fn(&mut SecurityProfile);
+
+    fn profile(edit: impl FnOnce(&mut SecurityProfile)) -> SecurityProfile {
+        let mut profile = SecurityProfile::new(Domain::Defense);
+        edit(&mut profile);
+        profile
+    }
+
+    #[test]
+    fn regimes_require_their_strength() {
+        let weak = profile(|p| {
+            p.compliance = Some(ComplianceRegime::BsiTr02102);
+            p.security_strength = SecurityStrength::Bits112;
+        });
+        assert_eq!(
+            weak.validate(),
+            Err(ProfileError::StrengthBelowRegime {
+                regime: ComplianceRegime::BsiTr02102,
+                required: SecurityStrength::Bits128,
+            })
+        );
+        let fips = profile(|p| {
+            p.compliance = Some(ComplianceRegime::Fips140_3);
+            p.security_strength = SecurityStrength::Bits112;
+        });
+        assert_eq!(fips.validate(), Ok(()));
+    }
+
+    #[test]
+    fn cnsa2_requires_quantum_resistance() {
+        let classical = profile(|p| {
+            p.compliance = Some(ComplianceRegime::Cnsa2);
+            p.security_strength = SecurityStrength::Bits256;
+        });
+        assert_eq!(
+            classical.validate(),
+            Err(ProfileError::RegimeRequiresQuantumResistance(
+                ComplianceRegime::Cnsa2
+            ))
+        );
+        let resistant = SecurityProfile {
+            quantum_resistant: true,
+            ..classical
+        };
+        assert_eq!(resistant.validate(), Ok(()));
+    }
+
+    #[test]
+    fn classifications_require_their_strength() {
+        let weak = profile(|p| p.classification = DataClassification::TopSecret);
+        assert_eq!(
+            weak.validate(),
+            Err(ProfileError::StrengthBelowClassification {
+                classification: DataClassification::TopSecret,
+                required: SecurityStrength::Bits192,
+            })
+        );
+        let strong = profile(|p| {
+            p.classification = DataClassification::TopSecret;
+            p.security_strength = SecurityStrength::Bits192;
+        });
+        assert_eq!(strong.validate(), Ok(()));
+    }
+
+    #[test]
+    fn long_lived_data_requires_quantum_resistance() {
+        let horizon = profile(|p| p.protection_lifetime_years = Some(QUANTUM_HORIZON_YEARS));
+        assert_eq!(horizon.validate(), Ok(()));
+        let beyond = profile(|p| p.protection_lifetime_years = Some(QUANTUM_HORIZON_YEARS + 1));
+        assert_eq!(
+            beyond.validate(),
+            Err(ProfileError::ProtectionOutlivesClassicalCrypto {
+                years: QUANTUM_HORIZON_YEARS + 1
+            })
+        );
+        let resistant = profile(|p| {
+            p.quantum_resistant = true;
+            p.protection_lifetime_years = Some(QUANTUM_HORIZON_YEARS + 1);
+        });
+        assert_eq!(resistant.validate(), Ok(()));
+    }
+
+    #[test]
+    fn lifetimes_and_limits_may_not_be_zero() {
+        let cases: [(Edit, ProfileError); 4] = [
+            (
+                |p| p.max_capability_lifetime = Some(0),
+                ProfileError::ZeroCapabilityLifetime,
+            ),
+            (
+                |p| p.key_cryptoperiod = Some(0),
+                ProfileError::ZeroCryptoperiod,
+            ),
+            (
+                |p| p.key_max_invocations = Some(0),
+                ProfileError::ZeroKeyLimit,
+            ),
+            (|p| p.key_max_bytes = Some(0), ProfileError::ZeroKeyLimit),
+        ];
+        for (edit, error) in cases {
+            assert_eq!(profile(edit).validate(), Err(error));
+        }
+        let limited = profile(|p| {
+            p.max_capability_lifetime = Some(1);
+            p.key_cryptoperiod = Some(1);
+            p.key_max_invocations = Some(1);
+            p.key_max_bytes = Some(1);
+        });
+        assert_eq!(limited.validate(), Ok(()));
+    }
+
+    // Every suite holds a 256-bit quantum-resistant algorithm that every regime
+    // approves, so no consistent profile can exclude a suite or every algorithm
+    // with the current registry. Walk every combination to keep it that way.
+    #[test]
+    fn valid_profiles_keep_an_algorithm_from_every_suite() {
+        let strengths = [
+            SecurityStrength::Bits112,
+            SecurityStrength::Bits128,
+            SecurityStrength::Bits192,
+            SecurityStrength::Bits256,
+        ];
+        let regimes = [
+            None,
+            Some(ComplianceRegime::Fips140_3),
+            Some(ComplianceRegime::Cnsa2),
+            Some(ComplianceRegime::BsiTr02102),
+        ];
+        let suites = [
+            AlgorithmSuite::Cnsa2,
+            AlgorithmSuite::Nist,
+            AlgorithmSuite::Cfrg,
+            AlgorithmSuite::Ledger,
+            AlgorithmSuite::PostQuantum,
+        ];
+        let mut valid = 0;
+        for strength in strengths {
+            for quantum_resistant in [false, true] {
+                for regime in regimes {
+                    for mask in 0..1u32 << suites.len() {
+                        let candidate = profile(|p| {
+                            p.security_strength = strength;
+                            p.quantum_resistant = quantum_resistant;
+                            p.compliance = regime;
+                            p.allowed_suites = (0..suites.len())
+                                .filter(|i| mask & 1 << i != 0)
+                                .map(|i| suites[i])
+                                .collect();
+                        });
+                        let excluded = candidate
+                            .allowed_suites
+                            .iter()
+                            .find(|s| !s.algorithms().iter().any(|a| candidate.permits(*a)));
+                        let permits_any =
+                            AlgorithmId::ALL.into_iter().any(|a| candidate.permits(a));
+                        match candidate.validate() {
+                            Ok(()) => valid += 1,
+                            Err(ProfileError::SuiteExcluded(suite)) => {
+                                assert_eq!(excluded, Some(&suite), "{candidate:?}");
+                            }
+                            Err(ProfileError::NoPermittedAlgorithm) => {
+                                assert!(!permits_any, "{candidate:?}");
+                            }
+                            Err(_) => {}
+                        }
+                        // Whether or not `validate` got this far.
+                        assert_eq!(excluded, None, "{candidate:?}");
+                        assert!(permits_any, "{candidate:?}");
+                    }
+                }
+            }
+        }
+        assert!(valid > 0);
+    }
+
+    #[test]
+    fn suites_narrow_the_permitted_algorithms() {
+        let ledger = profile(|p| {
+            p.quantum_resistant = true;
+            p.allowed_suites = vec![AlgorithmSuite::Ledger];
+        });
+        assert_eq!(ledger.validate(), Ok(()));
+        assert!(ledger.permits(AlgorithmId::Aes256Gcm));
+        assert!(!ledger.permits(AlgorithmId::EcdsaSecp256k1Sha256));
+        assert!(!ledger.permits(AlgorithmId::MlKem1024));
+    }
 }
@@ -0,0 +1,256 @@
+// ucqcf_core/src/request.rs
+
+//! Defines the versioned request envelope accepted by the Core Engine.
+//!
+//! Requests travel as CBOR. The encoding produced by `RequestEnvelope::encode`
+//! is canonical, and `RequestEnvelope::decode` rejects anything that does not
+//! re-encode to exactly the same bytes.
+
+use crate::CryptoError;
+use crate::algorithm::AlgorithmId;
+use serde::{Deserialize, Serialize};
+
+/// The envelope version produced and accepted by this release.
+pub const REQUEST_VERSION: u16 = 1;
+
+/// The longest encoded request `RequestEnvelope::decode` accepts.
+pub const MAX_REQUEST_LEN: usize = 64 * 1024;
+
+/// A cryptographic operation a capability can authorize.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Operation {
+    Encrypt,
+    Decrypt,
+    Sign,
+    Verify,
+    Mac,
+    Derive,
+    Wrap,
+    Unwrap,
+    Encapsulate,
+    Decapsulate,
+}
+
+impl Operation {
+    /// Returns the canonical name of the operation.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Operation::Encrypt => "encrypt",
+            Operation::Decrypt => "decrypt",
+            Operation::Sign => "sign",
+            Operation::Verify => "verify",
+            Operation::Mac => "mac",
+            Operation::Derive => "derive",
+            Operation::Wrap => "wrap",
+            Operation::Unwrap => "unwrap",
+            Operation::Encapsulate => "encapsulate",
+            Operation::Decapsulate => "decapsulate",
+        }
+    }
+
+    /// Returns `true` if the operation only makes sense under a key that
+    /// already exists, e.g. decrypting data encrypted earlier.
+    pub fn requires_existing_key(&self) -> bool {
+        matches!(
+            self,
+            Operation::Decrypt | Operation::Verify | Operation::Unwrap | Operation::Decapsulate
+        )
+    }
+}
+
+/// Selects the key a capability will be bound to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeySelector {
+    /// Provision a fresh key that satisfies the request's `SecurityProfile`.
+    Generate,
+    /// Use a key previously provisioned through the engine, named by a key
+    /// handle the engine sealed for the request's caller.
+    Existing(#[serde(with = "serde_bytes")] Vec<u8>),
+}
+
+/// A request for a capability, as sent by a client.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RequestEnvelope {
+    /// The envelope format version; must be `REQUEST_VERSION`.
+    pub version: u16,
+    pub operation: Operation,
+    /// The requested algorithm, or `None` to let the engine choose.
+    pub algorithm: Option<AlgorithmId>,
+    pub key: KeySelector,
+    /// Associated data the capability is bound to.
+    #[serde(with = "serde_bytes")]
+    pub aad: Vec<u8>,
+    /// The identity of the principal making the request.
+    pub caller: String,
+    /// The requested capability lifetime in engine epochs, or `None` for the engine default.
+    pub lifetime: Option<u64>,
+}
+
+impl RequestEnvelope {
+    /// Creates a request for `operation` on a freshly generated key, with no AAD
+    /// and the engine's default algorithm and lifetime.
+    pub fn new(operation: Operation, caller: &str) -> Self {
+        Self {
+            version: REQUEST_VERSION,
+            operation,
+            algorithm: None,
+            key: KeySelector::Generate,
+            aad: Vec::new(),
+            caller: caller.to_string(),
+            lifetime: None,
+        }
+    }
+
+    /// Encodes the request in its canonical CBOR form.
+    pub fn encode(&self) -> Result<Vec<u8>, CryptoError> {
+        let mut bytes = Vec::new();
+        ciborium::into_writer(self, &mut bytes).map_err(|_| CryptoError::MalformedInput)?;
+        Ok(bytes)
+    }
+
+    /// Decodes a request, rejecting oversized input, unknown fields, trailing
+    /// bytes, unsupported versions and any encoding that is not canonical.
+    pub fn decode(bytes: &[u8]) -> Result<Self, CryptoError> {
+        if bytes.len() > MAX_REQUEST_LEN {
+            return Err(CryptoError::MalformedInput);
+        }
+        let request: Self =
+            ciborium::from_reader(bytes).map_err(|_| CryptoError::MalformedInput)?;
+        if request.version != REQUEST_VERSION {
+            return Err(CryptoError::MalformedInput);
+        }
+        // Re-encoding catches trailing data, reordered or missing fields and
+        // non-minimal integer encodings in one comparison.
+        if request.encode()? != bytes {
+            return Err(CryptoError::MalformedInput);
+        }
+        Ok(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ciborium::Value;
+
+    fn request() -> RequestEnvelope {
+        let mut request = RequestEnvelope::new(Operation::Decrypt, "alice");
+        request.algorithm = Some(AlgorithmId::Aes256Gcm);
+        request.key = KeySelector::Existing(vec![7; 80]);
+        request.aad = b"header".to_vec();
+        request.lifetime = Some(60);
+        request
+    }
+
+    /// Re-encodes `request` as a CBOR map whose entries `edit` may change.
+    fn encode_map(
+        request: &RequestEnvelope,
+        edit: impl FnOnce(&mut Vec<(Value, Value)>),
+    ) -> Vec<u8> {
+        let value = Value::serialized(request).unwrap();
+        let Value::Map(mut entries) = value else {
+            panic!("a request encodes as a map");
+        };
+        edit(&mut entries);
+        let mut bytes = Vec::new();
+        ciborium::into_writer(&Value::Map(entries), &mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn round_trips() {
+        for request in [RequestEnvelope::new(Operation::Encrypt, "bob"), request()] {
+            let bytes = request.encode().unwrap();
+            assert_eq!(RequestEnvelope::decode(&bytes), Ok(request));
+        }
+    }
+
+    #[test]
+    fn rejects_non_canonical_encodings() {
+        let bytes = request().encode().unwrap();
+
+        // The version, 1, as a one-byte integer argument instead of an immediate.
+        let version = bytes.windows(8).position(|w| w == b"\x67version").unwrap() + 8;
+        assert_eq!(bytes[version], 0x01);
+        let mut wide = bytes.clone();
+        wide.splice(version..=version, [0x18, 0x01]);
+        assert_eq!(
+            RequestEnvelope::decode(&wide),
+            Err(CryptoError::MalformedInput)
+        );
+
+        // The same fields in another order.
+        let reordered = encode_map(&request(), |entries| entries.reverse());
+        assert_ne!(reordered, bytes);
+        assert_eq!(
+            RequestEnvelope::decode(&reordered),
+            Err(CryptoError::MalformedInput)
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_and_missing_fields() {
+        let extra = encode_map(&request(), |entries| {
+            entries.push((Value::Text("admin".to_string()), Value::Bool(true)));
+        });
+        assert_eq!(
+            RequestEnvelope::decode(&extra),
+            Err(CryptoError::MalformedInput)
+        );
+
+        let missing = encode_map(&request(), |entries| {
+            entries.retain(|(name, _)| name != &Value::Text("lifetime".to_string()));
+        });
+        assert_eq!(
+            RequestEnvelope::decode(&missing),
+            Err(CryptoError::MalformedInput)
+        );
+    }
+
+    #[test]
+    fn rejects_trailing_bytes() {
+        let mut bytes = request().encode().unwrap();
+        bytes.push(0x00);
+        assert_eq!(
+            RequestEnvelope::decode(&bytes),
+            Err(CryptoError::MalformedInput)
+        );
+
+        let mut twice = request().encode().unwrap();
+        twice.extend(request().encode().unwrap());
+        assert_eq!(
+            RequestEnvelope::decode(&twice),
+            Err(CryptoError::MalformedInput)
+        );
+    }
+
+    #[test]
+    fn rejects_unsupported_versions() {
+        let mut request = request();
+        request.version = REQUEST_VERSION + 1;
+        let bytes = request.encode().unwrap();
+        assert_eq!(
+            RequestEnvelope::decode(&bytes),
+            Err(CryptoError::MalformedInput)
+        );
+    }
+
+    #[test]
+    fn rejects_oversized_input() {
+        let mut request = request();
+        request.aad = vec![0; MAX_REQUEST_LEN];
+        let bytes = request.encode().unwrap();
+        assert!(bytes.len() > MAX_REQUEST_LEN);
+        assert_eq!(
+            RequestEnvelope::decode(&bytes),
+            Err(CryptoError::MalformedInput)
+        );
+
+        request.aad = vec![0; MAX_REQUEST_LEN / 2];
+        let bytes = request.encode().unwrap();
+        assert_eq!(RequestEnvelope::decode(&bytes), Ok(request));
+    }
+}
@@ -0,0 +1,215 @@
+// ucqcf_core/src/suite.rs
+
+//! Domain-driven cipher suite resolution: maps a `SecurityProfile` to the
+//! concrete algorithm to use for each cryptographic role.
+//!
+//! Each domain ranks the algorithms it favours for every role:
+//!
+//! - `Defense` follows CNSA 2.0: AES-256, SHA-384, ML-KEM-1024 and ML-DSA-87.
+//! - `Telecom` follows 3GPP: AES-128 (NEA2/NIA2) with HMAC-SHA256 key
+//!   derivation (TS 33.220), X25519 for SUCI (ECIES profile A) and ECDSA P-256.
+//! - `Blockchain` uses secp256k1 and Ed25519 signatures.
+//! - `EndToEnd` uses X25519 key agreement with an AEAD and Ed25519.
+//!
+//! The first algorithm of a role the profile permits wins. When the profile is
+//! quantum-resistant, classical public-key algorithms are no longer permitted,
+//! and the domain's classical choice is kept alongside the post-quantum one as
+//! the classical half of a hybrid, if the profile's suites and compliance
+//! regime allow it.
+
+use crate::algorithm::AlgorithmId;
+use crate::profile::{Domain, SecurityProfile, SecurityStrength};
+use crate::request::Operation;
+
+/// The algorithm chosen for each role under a profile. A role is `None` if
+/// the profile permits none of the domain's algorithms for it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CipherSuite {
+    pub aead: Option<AlgorithmId>,
+    pub mac: Option<AlgorithmId>,
+    pub kdf: Option<AlgorithmId>,
+    pub key_wrap: Option<AlgorithmId>,
+    pub signature: Option<AlgorithmId>,
+    /// The key agreement scheme. In a hybrid suite, the classical half
+    /// combined with `kem`.
+    pub key_agreement: Option<AlgorithmId>,
+    pub kem: Option<AlgorithmId>,
+    /// In a hybrid suite, the classical signature combined with `signature`.
+    pub hybrid_signature: Option<AlgorithmId>,
+}
+
+impl CipherSuite {
+    /// Resolves the cipher suite for `profile`.
+    pub fn resolve(profile: &SecurityProfile) -> Self {
+        let domain = profile.domain;
+        let pick = |role: Role| {
+            preferences(domain, role, profile.quantum_resistant)
+                .iter()
+                .copied()
+                .find(|a| profile.permits(*a))
+        };
+        let mut suite = CipherSuite {
+            aead: pick(Role::Aead),
+            mac: pick(Role::Mac),
+            kdf: pick(Role::Kdf),
+            key_wrap: pick(Role::KeyWrap),
+            signature: pick(Role::Signature),
+            key_agreement: pick(Role::KeyAgreement),
+            kem: pick(Role::Kem),
+            hybrid_signature: None,
+        };
+
+        if profile.quantum_resistant {
+            // The classical half of a hybrid adds defence in depth, so it only
+            // has to be acceptable to the suites and the compliance regime.
+            let classical = SecurityProfile {
+                quantum_resistant: false,
+                security_strength: SecurityStrength::Bits112,
+                ..profile.clone()
+            };
+            let partner = |role: Role| {
+                preferences(domain, role, false)
+                    .iter()
+                    .copied()
+                    .find(|a| !a.quantum_resistant() && classical.permits(*a))
+            };
+            if suite.signature.is_some() {
+                suite.hybrid_signature = partner(Role::Signature);
+            }
+            if suite.kem.is_some() && suite.key_agreement.is_none() {
+                suite.key_agreement = partner(Role::KeyAgreement);
+            }
+        }
+        suite
+    }
+
+    /// Returns `true` if the suite combines classical and post-quantum algorithms.
+    pub fn is_hybrid(&self) -> bool {
+        self.hybrid_signature.is_some() || (self.kem.is_some() && self.key_agreement.is_some())
+    }
+
+    /// Returns every algorithm the profile's domain ranks for `operation`
+    /// that the profile permits, most preferred first. `resolve` picks the
+    /// first of them; a module that does not support it can fall back on the
+    /// next. `key` is the algorithm of the key the operation uses, if known.
+    pub fn candidates(
+        profile: &SecurityProfile,
+        operation: Operation,
+        key: Option<AlgorithmId>,
+    ) -> Vec<AlgorithmId> {
+        preferences(
+            profile.domain,
+            Role::for_operation(operation, key),
+            profile.quantum_resistant,
+        )
+        .iter()
+        .copied()
+        .filter(|a| profile.permits(*a))
+        .collect()
+    }
+
+    /// Returns the suite's algorithm for `operation`, if it has one. `key` is
+    /// the algorithm of the key the operation uses, if known; it tells a MAC
+    /// `Verify` from a signature one.
+    pub fn algorithm_for(
+        &self,
+        operation: Operation,
+        key: Option<AlgorithmId>,
+    ) -> Option<AlgorithmId> {
+        match Role::for_operation(operation, key) {
+            Role::Aead => self.aead,
+            Role::Mac => self.mac,
+            Role::Kdf => self.kdf,
+            Role::KeyWrap => self.key_wrap,
+            Role::Signature => self.signature,
+            Role::KeyAgreement => self.key_agreement,
+            Role::Kem => self.kem,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Role {
+    Aead,
+    Mac,
+    Kdf,
+    KeyWrap,
+    Signature,
+    KeyAgreement,
+    Kem,
+}
+
+impl Role {
+    /// The role whose algorithm performs `operation` with a key of algorithm
+    /// `key`. `Verify` checks a signature unless the key is a MAC key.
+    fn for_operation(operation: Operation, key: Option<AlgorithmId>) -> Self {
+        match operation {
+            Operation::Encrypt | Operation::Decrypt => Role::Aead,
+            Operation::Verify if key.is_some_and(|key| key.supports(Operation::Mac)) => Role::Mac,
+            Operation::Sign | Operation::Verify => Role::Signature,
+            Operation::Mac => Role::Mac,
+            Operation::Derive => Role::Kdf,
+            Operation::Wrap | Operation::Unwrap => Role::KeyWrap,
+            Operation::Encapsulate | Operation::Decapsulate => Role::Kem,
+        }
+    }
+}
+
+/// The algorithms `domain` favours for `role`, most preferred first. Outside
+/// `Defense`, KEMs are only used when the profile asks for quantum resistance.
+fn preferences(domain: Domain, role: Role, quantum_resistant: bool) -> &'static [AlgorithmId] {
+    use AlgorithmId::*;
+
+    match (domain, role) {
+        (Domain::Defense, Role::Aead) => &[Aes256Gcm],
+        (Domain::Defense, Role::Mac) => &[HmacSha384],
+        (Domain::Defense, Role::Kdf) => &[HkdfSha384],
+        (Domain::Defense, Role::KeyWrap) => &[Aes256Kw],
+        (Domain::Defense, Role::Signature) => &[MlDsa87, EcdsaP384Sha384],
+        (Domain::Defense, Role::KeyAgreement) => &[],
+        (Domain::Defense, Role::Kem) => &[MlKem1024],
+
+        (Domain::Telecom, Role::Aead) => &[Aes128Gcm, Aes256Gcm],
+        (Domain::Telecom, Role::Signature) => &[EcdsaP256Sha256, EcdsaP384Sha384, MlDsa65, MlDsa87],
+
+        (Domain::Blockchain, Role::Aead) => &[ChaCha20Poly1305, Aes256Gcm],
+        (Domain::Blockchain, Role::Signature) => &[EcdsaSecp256k1Sha256, Ed25519, MlDsa65, MlDsa87],
+
+        (Domain::EndToEnd, Role::Aead) => &[ChaCha20Poly1305, Aes256Gcm],
+        (Domain::EndToEnd, Role::Signature) => &[Ed25519, MlDsa65, MlDsa87],
+
+        (_, Role::Mac) => &[HmacSha256, HmacSha384],
+        (_, Role::Kdf) => &[HkdfSha256, HkdfSha384],
+        (_, Role::KeyWrap) => &[Aes256Kw],
+        (_, Role::KeyAgreement) => &[X25519],
+        (_, Role::Kem) if quantum_resistant => &[MlKem768, MlKem1024],
+        (_, Role::Kem) => &[],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verify_follows_the_key() {
+        let suite = CipherSuite::resolve(&SecurityProfile::new(Domain::Defense));
+        assert_eq!(
+            suite.algorithm_for(Operation::Verify, None),
+            suite.signature
+        );
+        assert_eq!(
+            suite.algorithm_for(Operation::Verify, Some(AlgorithmId::MlDsa87)),
+            suite.signature
+        );
+        assert_eq!(
+            suite.algorithm_for(Operation::Verify, Some(AlgorithmId::HmacSha384)),
+            Some(AlgorithmId::HmacSha384)
+        );
+        let profile = SecurityProfile::new(Domain::Telecom);
+        assert_eq!(
+            CipherSuite::candidates(&profile, Operation::Verify, Some(AlgorithmId::HmacSha256)),
+            CipherSuite::candidates(&profile, Operation::Mac, None)
+        );
+    }
+}
@@ -0,0 +1,372 @@
+// ucqcf_engine/src/audit.rs
+
+//! Tamper-evident audit trail. Every record commits to its predecessor's hash,
+//! so deleting, reordering or editing a record breaks the chain.
+//!
+//! The chain is not keyed: whoever can rewrite the log can also recompute
+//! every hash after the change. An `AuditAnchor` taken from the engine and
+//! kept outside it closes that gap, since a rewritten or truncated log no
+//! longer contains the anchored head (see `verify_anchored_chain`).
+
+use crate::clock::EngineClock;
+use ring::digest;
+use std::cell::RefCell;
+use std::rc::Rc;
+use ucqcf_core::handles::KeyHandle;
+use ucqcf_core::interfaces::ModuleEventSink;
+use uuid::Uuid;
+
+/// The length of a record hash (SHA-256).
+pub const AUDIT_HASH_LEN: usize = 32;
+
+/// The `previous_hash` of the first record in a chain.
+pub const GENESIS_HASH: [u8; AUDIT_HASH_LEN] = [0; AUDIT_HASH_LEN];
+
+/// A security-relevant event observed by the Core Engine.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuditEvent {
+    /// A request entered the engine.
+    RequestReceived { size: usize },
+    /// A request could not be interpreted.
+    RequestRejected { reason: String },
+    /// The policy module decided on a request.
+    PolicyDecision {
+        caller: String,
+        operation: String,
+        allow: bool,
+        reason: Option<String>,
+    },
+    /// A provider failed and the router failed over to the next candidate.
+    ProviderFailover { provider: String, reason: String },
+    /// A capability was issued.
+    CapabilityIssued {
+        capability: Uuid,
+        key: Uuid,
+        operation: String,
+    },
+    /// A capability was presented for use.
+    CapabilityUsed {
+        capability: Uuid,
+        success: bool,
+        /// The size of the input, recorded when the capability requires detailed auditing.
+        input_size: Option<u64>,
+    },
+    /// A capability was revoked.
+    CapabilityRevoked { capability: Uuid },
+    /// A key's FSM changed state, as reported by the owning module.
+    FsmTransition {
+        key: Option<Uuid>,
+        from: String,
+        to: String,
+    },
+    /// A tamper event was signalled by the hardware.
+    TamperEvent { source: String },
+    /// A key was imported, rotated, relabelled, exported or destroyed.
+    KeyLifecycle {
+        key: Uuid,
+        action: String,
+        /// The other key involved, e.g. the predecessor of a rotated key or
+        /// the wrapping key of an export.
+        related: Option<Uuid>,
+    },
+}
+
+impl AuditEvent {
+    /// Appends a canonical, unambiguous encoding of the event to `out`.
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            AuditEvent::RequestReceived { size } => {
+                out.push(0x01);
+                out.extend_from_slice(&(*size as u64).to_be_bytes());
+            }
+            AuditEvent::RequestRejected { reason } => {
+                out.push(0x02);
+                encode_str(out, reason);
+            }
+            AuditEvent::PolicyDecision {
+                caller,
+                operation,
+                allow,
+                reason,
+            } => {
+                out.push(0x03);
+                encode_str(out, caller);
+                encode_str(out, operation);
+                out.push(*allow as u8);
+                encode_opt_str(out, reason.as_deref());
+            }
+            AuditEvent::CapabilityIssued {
+                capability,
+                key,
+                operation,
+            } => {
+                out.push(0x04);
+                out.extend_from_slice(capability.as_bytes());
+                out.extend_from_slice(key.as_bytes());
+                encode_str(out, operation);
+            }
+            AuditEvent::CapabilityUsed {
+                capability,
+                success,
+                input_size,
+            } => {
+                out.push(0x05);
+                out.extend_from_slice(capability.as_bytes());
+                out.push(*success as u8);
+                match input_size {
+                    Some(size) => {
+                        out.push(1);
+                        out.extend_from_slice(&size.to_be_bytes());
+                    }
+                    None => out.push(0),
+                }
+            }
+            AuditEvent::CapabilityRevoked { capability } => {
+                out.push(0x06);
+                out.extend_from_slice(capability.as_bytes());
+            }
+            AuditEvent::FsmTransition { key, from, to } => {
+                out.push(0x07);
+                match key {
+                    Some(key) => {
+                        out.push(1);
+                        out.extend_from_slice(key.as_bytes());
+                    }
+                    None => out.push(0),
+                }
+                encode_str(out, from);
+                encode_str(out, to);
+            }
+            AuditEvent::TamperEvent { source } => {
+                out.push(0x08);
+                encode_str(out, source);
+            }
+            AuditEvent::ProviderFailover { provider, reason } => {
+                out.push(0x09);
+                encode_str(out, provider);
+                encode_str(out, reason);
+            }
+            AuditEvent::KeyLifecycle {
+                key,
+                action,
+                related,
+            } => {
+                out.push(0x0a);
+                out.extend_from_slice(key.as_bytes());
+                encode_str(out, action);
+                match related {
+                    Some(related) => {
+                        out.push(1);
+                        out.extend_from_slice(related.as_bytes());
+                    }
+                    None => out.push(0),
+                }
+            }
+        }
+    }
+}
+
+fn encode_str(out: &mut Vec<u8>, value: &str) {
+    out.extend_from_slice(&(value.len() as u64).to_be_bytes());
+    out.extend_from_slice(value.as_bytes());
+}
+
+fn encode_opt_str(out: &mut Vec<u8>, value: Option<&str>) {
+    match value {
+        Some(value) => {
+            out.push(1);
+            encode_str(out, value);
+        }
+        None => out.push(0),
+    }
+}
+
+/// A single, hash-chained entry of the audit log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditRecord {
+    /// The position of the record in the log, starting at zero.
+    pub sequence: u64,
+    /// The engine epoch at which the event was recorded.
+    pub epoch: u64,
+    pub event: AuditEvent,
+    /// The hash of the preceding record, or `GENESIS_HASH` for the first record.
+    pub previous_hash: [u8; AUDIT_HASH_LEN],
+    /// The hash over this record's contents and `previous_hash`.
+    pub hash: [u8; AUDIT_HASH_LEN],
+}
+
+impl AuditRecord {
+    /// Computes the hash this record should carry.
+    pub fn compute_hash(&self) -> [u8; AUDIT_HASH_LEN] {
+        let mut encoded = Vec::new();
+        encoded.extend_from_slice(&self.previous_hash);
+        encoded.extend_from_slice(&self.sequence.to_be_bytes());
+        encoded.extend_from_slice(&self.epoch.to_be_bytes());
+        self.event.encode(&mut encoded);
+
+        let mut hash = [0u8; AUDIT_HASH_LEN];
+        hash.copy_from_slice(digest::digest(&digest::SHA256, &encoded).as_ref());
+        hash
+    }
+}
+
+/// Why an audit chain failed verification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditFault {
+    /// The record's sequence number is not its position in the log (deletion or reordering).
+    SequenceMismatch,
+    /// The record does not point at its predecessor's hash.
+    BrokenLink,
+    /// The record's contents do not match its hash (modification).
+    HashMismatch,
+    /// The log does not end the anchored prefix with the anchored head, so
+    /// it was rewritten since the anchor was taken.
+    AnchorMismatch,
+    /// The log is shorter than the anchored prefix, so records were removed
+    /// from its end since the anchor was taken. Reported at the last record
+    /// left, or at index 0 if none are.
+    Truncated,
+}
+
+/// The first broken link found while verifying an audit chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AuditVerificationError {
+    /// The index of the first record that failed verification.
+    pub index: usize,
+    pub fault: AuditFault,
+}
+
+/// Walks `records` from the start and reports the first broken link, if any.
+pub fn verify_chain(records: &[AuditRecord]) -> Result<(), AuditVerificationError> {
+    let mut previous_hash = GENESIS_HASH;
+    for (index, record) in records.iter().enumerate() {
+        let fault = if record.sequence != index as u64 {
+            Some(AuditFault::SequenceMismatch)
+        } else if record.previous_hash != previous_hash {
+            Some(AuditFault::BrokenLink)
+        } else if record.compute_hash() != record.hash {
+            Some(AuditFault::HashMismatch)
+        } else {
+            None
+        };
+        if let Some(fault) = fault {
+            return Err(AuditVerificationError { index, fault });
+        }
+        previous_hash = record.hash;
+    }
+    Ok(())
+}
+
+/// A commitment to the first `length` records of an audit log: the hash of
+/// the last of them, or `GENESIS_HASH` for an empty log. Kept outside the
+/// engine, e.g. on write-once media or in a transparency log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AuditAnchor {
+    /// The number of records the anchor covers.
+    pub length: u64,
+    /// The hash of the last record covered.
+    pub head: [u8; AUDIT_HASH_LEN],
+}
+
+/// Verifies `records` like `verify_chain`, and checks that they extend the
+/// log `anchor` was taken from.
+pub fn verify_anchored_chain(
+    records: &[AuditRecord],
+    anchor: &AuditAnchor,
+) -> Result<(), AuditVerificationError> {
+    verify_chain(records)?;
+    let length = usize::try_from(anchor.length).unwrap_or(usize::MAX);
+    if length > records.len() {
+        return Err(AuditVerificationError {
+            index: records.len().saturating_sub(1),
+            fault: AuditFault::Truncated,
+        });
+    }
+    let head = match length {
+        0 => GENESIS_HASH,
+        _ => records[length - 1].hash,
+    };
+    if head != anchor.head {
+        return Err(AuditVerificationError {
+            index: length.saturating_sub(1),
+            fault: AuditFault::AnchorMismatch,
+        });
+    }
+    Ok(())
+}
+
+/// Appends events to the engine's hash-chained audit log.
+pub(crate) struct AuditManager {
+    records: RefCell<Vec<AuditRecord>>,
+}
+
+impl AuditManager {
+    pub(crate) fn new() -> Self {
+        Self {
+            records: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Appends `event`, observed at engine epoch `epoch`, to the log.
+    pub(crate) fn record(&self, epoch: u64, event: AuditEvent) {
+        let mut records = self.records.borrow_mut();
+        let previous_hash = records.last().map_or(GENESIS_HASH, |last| last.hash);
+        let mut record = AuditRecord {
+            sequence: records.len() as u64,
+            epoch,
+            event,
+            previous_hash,
+            hash: [0; AUDIT_HASH_LEN],
+        };
+        record.hash = record.compute_hash();
+        records.push(record);
+    }
+
+    /// Returns an anchor for the log as it stands.
+    pub(crate) fn anchor(&self) -> AuditAnchor {
+        let records = self.records.borrow();
+        AuditAnchor {
+            length: records.len() as u64,
+            head: records.last().map_or(GENESIS_HASH, |last| last.hash),
+        }
+    }
+
+    /// Returns a copy of the full log.
+    pub(crate) fn records(&self) -> Vec<AuditRecord> {
+        self.records.borrow().clone()
+    }
+
+    /// Verifies the in-memory log.
+    pub(crate) fn verify(&self) -> Result<(), AuditVerificationError> {
+        verify_chain(&self.records.borrow())
+    }
+}
+
+/// Records the events a module reports in the engine's audit log, at the
+/// engine's epoch.
+pub(crate) struct AuditSink<'a> {
+    pub(crate) audit_manager: Rc<AuditManager>,
+    pub(crate) clock: Rc<EngineClock<'a>>,
+}
+
+impl ModuleEventSink for AuditSink<'_> {
+    fn fsm_transition(&self, key_handle: Option<&KeyHandle>, from: &str, to: &str) {
+        self.audit_manager.record(
+            self.clock.epoch(),
+            AuditEvent::FsmTransition {
+                key: key_handle.map(KeyHandle::id),
+                from: from.to_string(),
+                to: to.to_string(),
+            },
+        );
+    }
+
+    fn tamper_event(&self, source: &str) {
+        self.audit_manager.record(
+            self.clock.epoch(),
+            AuditEvent::TamperEvent {
+                source: source.to_string(),
+            },
+        );
+    }
+}
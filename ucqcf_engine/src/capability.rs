@@ -0,0 +1,156 @@
+// ucqcf_engine/src/capability.rs
+
+//! Capability issuance: binds an authorized operation and key to an opaque handle.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use ucqcf_core::CryptoError;
+use ucqcf_core::algorithm::AlgorithmId;
+use ucqcf_core::handles::{CapabilityHandle, KeyHandle};
+use ucqcf_core::interfaces::Obligations;
+use ucqcf_core::request::Operation;
+
+/// The number of engine epochs a capability stays valid for unless constrained further.
+pub const DEFAULT_CAPABILITY_LIFETIME: u64 = 1000;
+
+/// The authorization recorded behind a `CapabilityHandle`.
+#[derive(Debug, Clone)]
+pub(crate) struct IssuedCapability {
+    pub(crate) operation: Operation,
+    pub(crate) algorithm: AlgorithmId,
+    pub(crate) aad: Vec<u8>,
+    pub(crate) caller: String,
+    pub(crate) provider: String,
+    pub(crate) key_handle: KeyHandle,
+    pub(crate) issued_at: u64,
+    pub(crate) expires_at: u64,
+    /// `None` means the capability may be used until it expires.
+    pub(crate) remaining_uses: Option<u32>,
+    /// The obligations attached by the policy decision that authorized the capability.
+    pub(crate) obligations: Obligations,
+}
+
+/// A non-sensitive view of a live capability, as returned by `CoreEngine::list_capabilities`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapabilityInfo {
+    pub handle: CapabilityHandle,
+    pub operation: Operation,
+    pub algorithm: AlgorithmId,
+    /// The associated data the capability is bound to.
+    pub aad: Vec<u8>,
+    /// The principal the capability was issued to.
+    pub caller: String,
+    /// The name of the provider that owns the key.
+    pub provider: String,
+    pub key_handle: KeyHandle,
+    pub issued_at: u64,
+    pub expires_at: u64,
+    pub remaining_uses: Option<u32>,
+    pub obligations: Obligations,
+}
+
+/// The registry of issued capabilities, keyed by handle.
+pub(crate) struct CapabilityManager {
+    issued: RefCell<HashMap<CapabilityHandle, IssuedCapability>>,
+}
+
+impl CapabilityManager {
+    pub(crate) fn new() -> Self {
+        Self {
+            issued: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Issues a new capability for `record`.
+    pub(crate) fn issue(&self, record: IssuedCapability) -> CapabilityHandle {
+        let handle = CapabilityHandle::new();
+        self.issued.borrow_mut().insert(handle.clone(), record);
+        handle
+    }
+
+    /// Resolves a handle for one use at epoch `now`, consuming a use if the capability is limited.
+    /// Expired and exhausted capabilities are removed from the registry. A use is only consumed
+    /// once `check` has accepted the call.
+    pub(crate) fn consume(
+        &self,
+        handle: &CapabilityHandle,
+        now: u64,
+        check: impl FnOnce(&IssuedCapability) -> Result<(), CryptoError>,
+    ) -> Result<IssuedCapability, CryptoError> {
+        let mut issued = self.issued.borrow_mut();
+        let record = issued
+            .get_mut(handle)
+            .ok_or(CryptoError::AuthorizationFailed)?;
+
+        if now >= record.expires_at {
+            issued.remove(handle);
+            return Err(CryptoError::CapabilityExpired);
+        }
+
+        check(record)?;
+        match record.remaining_uses {
+            Some(0) => {
+                issued.remove(handle);
+                Err(CryptoError::FsmUsageExceeded)
+            }
+            Some(uses) => {
+                record.remaining_uses = Some(uses - 1);
+                let resolved = record.clone();
+                if uses == 1 {
+                    issued.remove(handle);
+                }
+                Ok(resolved)
+            }
+            None => Ok(record.clone()),
+        }
+    }
+
+    /// Lists the capabilities that are still live at epoch `now`, pruning expired ones.
+    pub(crate) fn list(&self, now: u64) -> Vec<CapabilityInfo> {
+        let mut issued = self.issued.borrow_mut();
+        issued.retain(|_, record| now < record.expires_at);
+        issued
+            .iter()
+            .map(|(handle, record)| CapabilityInfo {
+                handle: handle.clone(),
+                operation: record.operation,
+                algorithm: record.algorithm,
+                aad: record.aad.clone(),
+                caller: record.caller.clone(),
+                provider: record.provider.clone(),
+                key_handle: record.key_handle.clone(),
+                issued_at: record.issued_at,
+                expires_at: record.expires_at,
+                remaining_uses: record.remaining_uses,
+                obligations: record.obligations.clone(),
+            })
+            .collect()
+    }
+
+    /// Returns the epoch at which a live capability expires.
+    pub(crate) fn expires_at(&self, handle: &CapabilityHandle) -> Option<u64> {
+        self.issued
+            .borrow()
+            .get(handle)
+            .map(|record| record.expires_at)
+    }
+
+    /// Revokes a single capability. Returns `false` if the handle was not live.
+    pub(crate) fn revoke(&self, handle: &CapabilityHandle) -> bool {
+        self.issued.borrow_mut().remove(handle).is_some()
+    }
+
+    /// Revokes every capability bound to `key_handle`, returning the revoked handles.
+    pub(crate) fn revoke_for_key(&self, key_handle: &KeyHandle) -> Vec<CapabilityHandle> {
+        let mut issued = self.issued.borrow_mut();
+        let revoked: Vec<CapabilityHandle> = issued
+            .iter()
+            .filter(|(_, record)| &record.key_handle == key_handle)
+            .map(|(handle, _)| handle.clone())
+            .collect();
+        for handle in &revoked {
+            issued.remove(handle);
+        }
+        revoked
+    }
+}
@@ -0,0 +1,35 @@
+// ucqcf_engine/src/clock.rs
+
+//! The engine's view of time: epochs read from a secure clock source.
+
+use std::cell::{Cell, RefCell};
+use ucqcf_core::clock::ClockSource;
+
+/// Reads epochs from a clock source, so that they never run backwards.
+pub(crate) struct EngineClock<'a> {
+    source: RefCell<Box<dyn ClockSource + 'a>>,
+    /// The latest epoch returned.
+    latest: Cell<u64>,
+}
+
+impl<'a> EngineClock<'a> {
+    pub(crate) fn new(source: Box<dyn ClockSource + 'a>) -> Self {
+        Self {
+            source: RefCell::new(source),
+            latest: Cell::new(0),
+        }
+    }
+
+    /// Replaces the clock source. Epochs stay where they are until the new
+    /// source catches up.
+    pub(crate) fn set_source(&self, source: Box<dyn ClockSource + 'a>) {
+        self.source.replace(source);
+    }
+
+    /// Returns the current epoch, never earlier than one already returned.
+    pub(crate) fn epoch(&self) -> u64 {
+        let now = self.source.borrow().epoch().max(self.latest.get());
+        self.latest.set(now);
+        now
+    }
+}
@@ -2,66 +2,793 @@
 
 //! The Core Engine (UCE) of the Universal Classical-Quantum Cryptography Framework.
 
-use ucqcf_core::handles::CapabilityHandle;
-use ucqcf_core::interfaces::{CryptoProvider, KeyManager, PolicyInterface};
-use ucqcf_core::profile::SecurityProfile;
+mod audit;
+mod capability;
+mod clock;
+mod request;
+mod router;
+#[cfg(test)]
+mod tests;
+mod token;
+
+use crate::audit::{AuditManager, AuditSink};
+use crate::capability::{CapabilityManager, DEFAULT_CAPABILITY_LIFETIME, IssuedCapability};
+use crate::clock::EngineClock;
+use crate::request::RequestManager;
+use crate::router::ModuleRouter;
+use crate::token::{HandleKind, HandleSealer};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
 use ucqcf_core::CryptoError;
+use ucqcf_core::algorithm::{AlgorithmId, CryptoOperation};
+use ucqcf_core::clock::{ClockSource, SystemClock};
+use ucqcf_core::handles::{CapabilityHandle, KeyHandle};
+use ucqcf_core::interfaces::{
+    AuditLevel, CryptoProvider, KeyManager, ModuleEventSink, Obligations, PolicyContext,
+    PolicyEvaluation, PolicyInterface,
+};
+use ucqcf_core::key::{DestructionProof, KeyFormat, KeyMetadata};
+use ucqcf_core::profile::SecurityProfile;
+use ucqcf_core::request::{KeySelector, Operation, RequestEnvelope};
+use ucqcf_core::suite::CipherSuite;
+use uuid::Uuid;
+
+pub use crate::audit::{
+    AUDIT_HASH_LEN, AuditAnchor, AuditEvent, AuditFault, AuditRecord, AuditVerificationError,
+    GENESIS_HASH, verify_anchored_chain, verify_chain,
+};
+pub use crate::capability::CapabilityInfo;
+pub use crate::router::{AssuranceLevel, ProviderDescriptor};
 
-// Placeholders for the internal components of the Core Engine.
-struct RequestManager {}
-struct ModuleRouter {}
-struct CapabilityManager {}
-struct AuditManager {}
+/// The outcome of `CoreEngine::dry_run`: what `execute_request` would decide, and why.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DryRunReport {
+    /// The context the policy was evaluated against.
+    pub context: PolicyContext,
+    /// The policy decision, with the trace explaining it.
+    pub evaluation: PolicyEvaluation,
+    /// The providers that would be asked for a key, in order. For an existing
+    /// key, only the provider that owns it.
+    pub providers: Vec<String>,
+    /// The lifetime, in epochs, the capability would be issued with.
+    pub lifetime: u64,
+    /// Why the request would be refused, if it would be.
+    pub refusal: Option<String>,
+}
+
+impl DryRunReport {
+    /// Returns `true` if the request would be granted a capability, barring
+    /// failures of the providers themselves.
+    pub fn would_issue(&self) -> bool {
+        self.refusal.is_none()
+    }
+}
 
 /// The Universal Cryptographic Engine (UCE).
 pub struct CoreEngine<'a> {
     // Internal components.
     request_manager: RequestManager,
-    module_router: ModuleRouter,
+    module_router: ModuleRouter<'a>,
     capability_manager: CapabilityManager,
-    audit_manager: AuditManager,
+    audit_manager: Rc<AuditManager>,
+    handle_sealer: HandleSealer,
 
     // Pluggable modules.
     policy_interface: Box<dyn PolicyInterface + 'a>,
-    crypto_provider: Box<dyn CryptoProvider + 'a>,
-    key_manager: Box<dyn KeyManager + 'a>,
+
+    /// The secure clock the engine's epochs are read from.
+    clock: Rc<EngineClock<'a>>,
+    /// Environment attributes exposed to the policy module.
+    environment: RefCell<BTreeMap<String, String>>,
 }
 
 impl<'a> CoreEngine<'a> {
-    /// Creates a new `CoreEngine` with the given pluggable modules.
-    pub fn new(
-        policy_interface: Box<dyn PolicyInterface + 'a>,
-        crypto_provider: Box<dyn CryptoProvider + 'a>,
-        key_manager: Box<dyn KeyManager + 'a>,
-    ) -> Self {
+    /// Creates a new `CoreEngine` enforcing the given policy.
+    /// Providers are added with `register_provider`.
+    pub fn new(policy_interface: Box<dyn PolicyInterface + 'a>) -> Self {
         Self {
-            request_manager: RequestManager {},
-            module_router: ModuleRouter {},
-            capability_manager: CapabilityManager {},
-            audit_manager: AuditManager {},
+            request_manager: RequestManager::new(),
+            module_router: ModuleRouter::new(),
+            capability_manager: CapabilityManager::new(),
+            audit_manager: Rc::new(AuditManager::new()),
+            handle_sealer: HandleSealer::new(),
             policy_interface,
-            crypto_provider,
-            key_manager,
+            clock: Rc::new(EngineClock::new(Box::new(SystemClock))),
+            environment: RefCell::new(BTreeMap::new()),
         }
     }
 
+    /// Registers a crypto provider together with the key manager that owns its keys.
+    /// Fails with `InvalidState` if a provider with the same name is already registered.
+    pub fn register_provider(
+        &mut self,
+        descriptor: ProviderDescriptor,
+        crypto_provider: Box<dyn CryptoProvider + 'a>,
+        key_manager: Box<dyn KeyManager + 'a>,
+    ) -> Result<(), CryptoError> {
+        self.module_router
+            .register(descriptor, crypto_provider, key_manager)
+    }
+
+    /// Replaces the engine's random identity with a persistent one, so sealed
+    /// handles survive restarts and can be opened by every engine sharing
+    /// `secret`. Handles sealed under the previous identity stop opening.
+    /// Fails with `MalformedInput` if `secret` is shorter than 32 bytes.
+    pub fn set_handle_sealing_key(
+        &mut self,
+        engine_id: Uuid,
+        secret: &[u8],
+    ) -> Result<(), CryptoError> {
+        self.handle_sealer = HandleSealer::from_secret(engine_id, secret)?;
+        Ok(())
+    }
+
+    /// Replaces the clock the engine reads its epochs from (by default the
+    /// operating system's). Epochs never run backwards, even if the new clock
+    /// is behind the old one.
+    pub fn set_clock_source(&mut self, clock: Box<dyn ClockSource + 'a>) {
+        self.clock.set_source(clock);
+    }
+
+    /// Returns the id embedded in every handle this engine seals.
+    pub fn engine_id(&self) -> Uuid {
+        self.handle_sealer.engine_id()
+    }
+
+    /// Returns the descriptors of all registered providers.
+    pub fn providers(&self) -> Vec<ProviderDescriptor> {
+        self.module_router.descriptors()
+    }
+
+    /// Sets an environment attribute (e.g. `site` or `threat_level`) seen by the policy module.
+    pub fn set_environment_attribute(&self, name: &str, value: &str) {
+        self.environment
+            .borrow_mut()
+            .insert(name.to_string(), value.to_string());
+    }
+
+    /// Removes an environment attribute.
+    pub fn remove_environment_attribute(&self, name: &str) {
+        self.environment.borrow_mut().remove(name);
+    }
+
+    /// Returns the current engine epoch: the secure clock's time in seconds
+    /// since the UNIX epoch. Capability lifetimes and the expiry of sealed
+    /// handles are measured against it, so they hold across restarts.
+    pub fn epoch(&self) -> u64 {
+        self.clock.epoch()
+    }
+
     /// The primary, high-level, handle-based API for the Core Engine.
+    ///
+    /// `request_data` is a CBOR-encoded `RequestEnvelope` (see `RequestEnvelope::encode`).
+    /// An existing key is named by a key handle sealed for the request's caller.
+    /// The capability is returned sealed for the caller, for use with
+    /// `execute_capability`. Key limits the policy obliges are passed to the
+    /// key's key manager first, and the request fails if it cannot apply them.
     pub fn execute_request(
         &self,
         profile: &SecurityProfile,
-        // A placeholder for the actual request data.
-        _request_data: &[u8],
-    ) -> Result<CapabilityHandle, CryptoError> {
-        // This is a simplified sketch of the Core Engine's logic.
-        // A real implementation would involve a more complex sequence of calls.
+        request_data: &[u8],
+    ) -> Result<Vec<u8>, CryptoError> {
+        let now = self.epoch();
+        self.audit_manager.record(
+            now,
+            AuditEvent::RequestReceived {
+                size: request_data.len(),
+            },
+        );
 
         // 1. Requirement Interpretation (RequestManager).
+        let request = self
+            .request_manager
+            .interpret(profile, request_data)
+            .inspect_err(|err| {
+                self.audit_manager.record(
+                    now,
+                    AuditEvent::RequestRejected {
+                        reason: err.to_string(),
+                    },
+                );
+            })?;
+        let key = match &request.key {
+            KeySelector::Generate => None,
+            KeySelector::Existing(token) => Some(
+                self.open_handle(HandleKind::Key, token, &request.caller, now)
+                    .map(KeyHandle::from_id)?,
+            ),
+        };
+
         // 2. Policy Enforcement (PolicyInterface).
+        let algorithm = self.requested_algorithm(profile, &request);
+        let context = self.policy_context(&request, key.as_ref(), algorithm, profile, now);
+        let decision = self
+            .policy_interface
+            .evaluate(&context)
+            .inspect_err(|err| {
+                self.audit_manager.record(
+                    now,
+                    AuditEvent::RequestRejected {
+                        reason: format!("policy evaluation failed: {err}"),
+                    },
+                );
+            })?;
+        self.audit_manager.record(
+            now,
+            AuditEvent::PolicyDecision {
+                caller: request.caller.clone(),
+                operation: request.operation.as_str().to_string(),
+                allow: decision.allow,
+                reason: decision.reason.clone(),
+            },
+        );
+        if !decision.allow {
+            return Err(CryptoError::AuthorizationFailed);
+        }
+        let obligations = decision.obligations;
+        if !aad_satisfied(&obligations, &request) {
+            self.audit_manager.record(
+                now,
+                AuditEvent::RequestRejected {
+                    reason: "request does not carry the AAD required by policy".to_string(),
+                },
+            );
+            return Err(CryptoError::AuthorizationFailed);
+        }
+        if obligations.max_uses == Some(0) {
+            self.audit_manager.record(
+                now,
+                AuditEvent::RequestRejected {
+                    reason: "policy allows no uses of the capability".to_string(),
+                },
+            );
+            return Err(CryptoError::AuthorizationFailed);
+        }
+
         // 3. Module Orchestration (ModuleRouter).
+        let provisioned = match &key {
+            None => self.module_router.provision_key(
+                profile,
+                algorithm,
+                request.operation,
+                |provider, err| {
+                    self.audit_manager.record(
+                        now,
+                        AuditEvent::ProviderFailover {
+                            provider: provider.to_string(),
+                            reason: err.to_string(),
+                        },
+                    );
+                },
+            )?,
+            Some(key_handle) => {
+                self.module_router
+                    .locate_key(key_handle, profile, algorithm, request.operation)?
+            }
+        };
+        if obligations.key_max_invocations.is_some() || obligations.key_max_bytes.is_some() {
+            self.module_router
+                .key_manager(&provisioned.key_handle)?
+                .restrict_key_usage(
+                    &provisioned.key_handle,
+                    obligations.key_max_invocations,
+                    obligations.key_max_bytes,
+                )
+                .inspect_err(|err| {
+                    self.audit_manager.record(
+                        now,
+                        AuditEvent::RequestRejected {
+                            reason: format!("key limits required by policy not applied: {err}"),
+                        },
+                    );
+                })?;
+        }
+
         // 4. Capability Issuance (CapabilityManager).
+        let key_id = provisioned.key_handle.id();
+        let lifetime = capability_lifetime(profile, &obligations, &request);
+        let expires_at = now.saturating_add(lifetime);
+        let handle = self.capability_manager.issue(IssuedCapability {
+            operation: request.operation,
+            algorithm: provisioned.algorithm,
+            aad: request.aad,
+            caller: request.caller.clone(),
+            provider: provisioned.provider,
+            key_handle: provisioned.key_handle,
+            issued_at: now,
+            expires_at,
+            remaining_uses: obligations.max_uses,
+            obligations,
+        });
+        self.audit_manager.record(
+            now,
+            AuditEvent::CapabilityIssued {
+                capability: handle.id(),
+                key: key_id,
+                operation: request.operation.as_str().to_string(),
+            },
+        );
+        self.handle_sealer.seal(
+            HandleKind::Capability,
+            handle.id(),
+            &request.caller,
+            expires_at,
+        )
+    }
+
+    /// Evaluates a request the way `execute_request` would and reports the full
+    /// policy trace. No key is generated, no capability is issued and nothing
+    /// is audited.
+    ///
+    /// Malformed requests, key tokens that do not open and policy module
+    /// errors are returned as errors, as `execute_request` would return them,
+    /// and the policy is not consulted; everything else, including a denial,
+    /// is described by the report.
+    pub fn dry_run(
+        &self,
+        profile: &SecurityProfile,
+        request_data: &[u8],
+    ) -> Result<DryRunReport, CryptoError> {
+        let now = self.epoch();
+        let request = self.request_manager.interpret(profile, request_data)?;
+        let key = match &request.key {
+            KeySelector::Generate => None,
+            KeySelector::Existing(token) => Some(
+                self.handle_sealer
+                    .open(HandleKind::Key, token, &request.caller, now)
+                    .map(KeyHandle::from_id)
+                    .map_err(|fault| fault.error())?,
+            ),
+        };
+        let algorithm = self.requested_algorithm(profile, &request);
+        let context = self.policy_context(&request, key.as_ref(), algorithm, profile, now);
+        let evaluation = self.policy_interface.explain(&context)?;
+        let decision = &evaluation.decision;
+
+        let providers = match &key {
+            None => self
+                .module_router
+                .candidate_names(profile, algorithm, request.operation),
+            Some(key_handle) => self
+                .module_router
+                .locate_key(key_handle, profile, algorithm, request.operation)
+                .map(|provisioned| vec![provisioned.provider])
+                .unwrap_or_default(),
+        };
+
+        let refusal = if !decision.allow {
+            Some(
+                decision
+                    .reason
+                    .clone()
+                    .unwrap_or_else(|| "denied by policy".to_string()),
+            )
+        } else if !aad_satisfied(&decision.obligations, &request) {
+            Some("request does not carry the AAD required by policy".to_string())
+        } else if decision.obligations.max_uses == Some(0) {
+            Some("policy allows no uses of the capability".to_string())
+        } else if providers.is_empty() {
+            Some(
+                match request.key {
+                    KeySelector::Generate => "no registered provider can serve the request",
+                    KeySelector::Existing(_) => {
+                        "the key is unknown or its provider cannot serve the request"
+                    }
+                }
+                .to_string(),
+            )
+        } else {
+            None
+        };
+
+        Ok(DryRunReport {
+            lifetime: capability_lifetime(profile, &decision.obligations, &request),
+            context,
+            evaluation,
+            providers,
+            refusal,
+        })
+    }
+
+    /// The algorithm to ask the providers for: the one the request names, or
+    /// for a fresh key the most preferred of the profile's `CipherSuite`
+    /// candidates for the operation that a provider offers, as the CIEM picks
+    /// its AEAD. `None` leaves the choice to the providers.
+    fn requested_algorithm(
+        &self,
+        profile: &SecurityProfile,
+        request: &RequestEnvelope,
+    ) -> Option<AlgorithmId> {
+        if request.algorithm.is_some() || request.key != KeySelector::Generate {
+            return request.algorithm;
+        }
+        CipherSuite::candidates(profile, request.operation, None)
+            .into_iter()
+            .find(|algorithm| {
+                !self
+                    .module_router
+                    .candidate_names(profile, Some(*algorithm), request.operation)
+                    .is_empty()
+            })
+    }
+
+    /// Builds the context the policy module evaluates a request against.
+    /// `key` is the opened key handle the request names, if any.
+    fn policy_context(
+        &self,
+        request: &RequestEnvelope,
+        key: Option<&KeyHandle>,
+        algorithm: Option<AlgorithmId>,
+        profile: &SecurityProfile,
+        now: u64,
+    ) -> PolicyContext {
+        let key_attributes = match key {
+            None => BTreeMap::from([("origin".to_string(), "generated".to_string())]),
+            Some(key_handle) => self.module_router.key_attributes(key_handle),
+        };
+        PolicyContext {
+            principal: request.caller.clone(),
+            operation: request.operation,
+            algorithm,
+            key_attributes,
+            profile: profile.clone(),
+            epoch: now,
+            environment: self.environment.borrow().clone(),
+        }
+    }
+
+    /// Executes the operation authorized by the sealed `capability` token,
+    /// presented by `caller`, on `data`. Tokens are opened like
+    /// `open_capability_handle` does; unknown or revoked capabilities are
+    /// rejected with `AuthorizationFailed`.
+    ///
+    /// Encryption and decryption authenticate the AAD the capability was issued
+    /// for, and derivation binds the derived key to it. Verification needs a
+    /// signature; use `verify_with_capability` instead.
+    pub fn execute_capability(
+        &self,
+        capability: &[u8],
+        caller: &str,
+        data: &[u8],
+    ) -> Result<Vec<u8>, CryptoError> {
+        self.invoke(capability, caller, data, None)
+    }
+
+    /// Checks `signature` (or a MAC tag) over `message` with a sealed verify
+    /// capability token presented by `caller`. Fails with `VerificationFailed`
+    /// if it does not verify.
+    pub fn verify_with_capability(
+        &self,
+        capability: &[u8],
+        caller: &str,
+        message: &[u8],
+        signature: &[u8],
+    ) -> Result<(), CryptoError> {
+        self.invoke(capability, caller, message, Some(signature))
+            .map(|_| ())
+    }
+
+    /// Opens a capability token, consumes one use of the capability and
+    /// executes its operation, auditing the outcome. A call that does not fit
+    /// the capability's operation is refused without consuming a use.
+    fn invoke(
+        &self,
+        token: &[u8],
+        caller: &str,
+        data: &[u8],
+        signature: Option<&[u8]>,
+    ) -> Result<Vec<u8>, CryptoError> {
+        let now = self.epoch();
+        let capability = CapabilityHandle::from_id(self.open_handle(
+            HandleKind::Capability,
+            token,
+            caller,
+            now,
+        )?);
+        let issued = self.capability_manager.consume(&capability, now, |issued| {
+            crypto_operation(issued.operation, &issued.aad, signature).map(|_| ())
+        });
+        let audit_level = issued.as_ref().map_or(AuditLevel::Standard, |issued| {
+            issued.obligations.audit_level
+        });
+        let result = issued.and_then(|issued| {
+            let operation = crypto_operation(issued.operation, &issued.aad, signature)?;
+            self.module_router.execute(
+                &issued.provider,
+                &issued.key_handle,
+                issued.algorithm,
+                operation,
+                data,
+            )
+        });
+        self.audit_manager.record(
+            now,
+            AuditEvent::CapabilityUsed {
+                capability: capability.id(),
+                success: result.is_ok(),
+                input_size: (audit_level == AuditLevel::Detailed).then_some(data.len() as u64),
+            },
+        );
+        result
+    }
 
-        // For now, we'll just return a placeholder.
-        let _ = profile;
-        Err(CryptoError::InvalidState)
+    /// Lists all capabilities that are still live.
+    pub fn list_capabilities(&self) -> Vec<CapabilityInfo> {
+        self.capability_manager.list(self.epoch())
     }
+
+    /// Revokes a capability. Returns `false` if it was unknown, expired or already revoked.
+    pub fn revoke_capability(&self, capability: &CapabilityHandle) -> bool {
+        let revoked = self.capability_manager.revoke(capability);
+        if revoked {
+            self.audit_manager.record(
+                self.epoch(),
+                AuditEvent::CapabilityRevoked {
+                    capability: capability.id(),
+                },
+            );
+        }
+        revoked
+    }
+
+    /// Revokes every capability bound to `key_handle`, returning how many were revoked.
+    pub fn revoke_capabilities_for_key(&self, key_handle: &KeyHandle) -> usize {
+        let revoked = self.capability_manager.revoke_for_key(key_handle);
+        for capability in &revoked {
+            self.audit_manager.record(
+                self.epoch(),
+                AuditEvent::CapabilityRevoked {
+                    capability: capability.id(),
+                },
+            );
+        }
+        revoked.len()
+    }
+
+    /// Imports key material into the named provider and makes it available to
+    /// requests naming it with `KeySelector::Existing`.
+    ///
+    /// Key management operations are administrative: they are audited, but not
+    /// subject to the policy module.
+    pub fn import_key(
+        &self,
+        provider: &str,
+        profile: &SecurityProfile,
+        algorithm: AlgorithmId,
+        format: KeyFormat,
+        material: &[u8],
+    ) -> Result<KeyHandle, CryptoError> {
+        let key_handle = self
+            .module_router
+            .import_key(provider, profile, algorithm, format, material)?;
+        self.record_key_event(&key_handle, "imported", None);
+        Ok(key_handle)
+    }
+
+    /// Rotates a key, returning its successor. Capabilities bound to the old key
+    /// stay valid, and the old key can still be named in requests, e.g. to
+    /// decrypt data protected under it.
+    pub fn rotate_key(&self, key_handle: &KeyHandle) -> Result<KeyHandle, CryptoError> {
+        let successor = self.module_router.rotate_key(key_handle)?;
+        self.record_key_event(&successor, "rotated", Some(key_handle));
+        Ok(successor)
+    }
+
+    /// Exports a key wrapped under `wrapping_key`, which must belong to the same provider.
+    pub fn export_wrapped_key(
+        &self,
+        key_handle: &KeyHandle,
+        wrapping_key: &KeyHandle,
+    ) -> Result<Vec<u8>, CryptoError> {
+        let wrapped = self
+            .module_router
+            .export_wrapped(key_handle, wrapping_key)?;
+        self.record_key_event(key_handle, "exported", Some(wrapping_key));
+        Ok(wrapped)
+    }
+
+    /// Replaces the labels of a key. Policies see the new labels from the
+    /// next request on.
+    pub fn set_key_labels(
+        &self,
+        key_handle: &KeyHandle,
+        labels: BTreeMap<String, String>,
+    ) -> Result<(), CryptoError> {
+        self.module_router.set_labels(key_handle, labels)?;
+        self.record_key_event(key_handle, "labels_updated", None);
+        Ok(())
+    }
+
+    /// Returns the metadata of a key provisioned through the engine.
+    pub fn key_metadata(&self, key_handle: &KeyHandle) -> Result<KeyMetadata, CryptoError> {
+        self.module_router
+            .key_manager(key_handle)?
+            .key_metadata(key_handle)
+    }
+
+    /// Lists the keys of every provider whose key manager can enumerate them.
+    pub fn list_keys(&self) -> Result<Vec<KeyMetadata>, CryptoError> {
+        self.module_router.list_keys()
+    }
+
+    /// Destroys a key, then revokes every capability bound to it, and returns
+    /// the key manager's proof of destruction. If the key manager cannot
+    /// destroy the key, its capabilities are left alone.
+    pub fn destroy_key(&self, key_handle: &KeyHandle) -> Result<DestructionProof, CryptoError> {
+        let proof = self.module_router.destroy_key(key_handle)?;
+        self.record_key_event(key_handle, "destroyed", None);
+        self.revoke_capabilities_for_key(key_handle);
+        Ok(proof)
+    }
+
+    fn record_key_event(&self, key_handle: &KeyHandle, action: &str, related: Option<&KeyHandle>) {
+        self.audit_manager.record(
+            self.epoch(),
+            AuditEvent::KeyLifecycle {
+                key: key_handle.id(),
+                action: action.to_string(),
+                related: related.map(KeyHandle::id),
+            },
+        );
+    }
+
+    /// Seals a key handle for `tenant`, valid for `lifetime` epochs, so it can
+    /// be handed to a client outside the process. Only keys provisioned
+    /// through the engine can be sealed.
+    pub fn seal_key_handle(
+        &self,
+        key_handle: &KeyHandle,
+        tenant: &str,
+        lifetime: u64,
+    ) -> Result<Vec<u8>, CryptoError> {
+        if !self.module_router.knows_key(key_handle) {
+            return Err(CryptoError::KeyNotFound);
+        }
+        let expires_at = self.epoch().saturating_add(lifetime);
+        self.handle_sealer
+            .seal(HandleKind::Key, key_handle.id(), tenant, expires_at)
+    }
+
+    /// Opens a sealed key handle presented by `tenant`. Forged, tampered,
+    /// foreign or other tenants' tokens fail with `AuthorizationFailed`, and
+    /// expired ones with `CapabilityExpired`; every rejection is audited.
+    pub fn open_key_handle(&self, token: &[u8], tenant: &str) -> Result<KeyHandle, CryptoError> {
+        self.open_handle(HandleKind::Key, token, tenant, self.epoch())
+            .map(KeyHandle::from_id)
+    }
+
+    /// Seals a live capability handle for `tenant`. The token expires with the capability.
+    pub fn seal_capability_handle(
+        &self,
+        capability: &CapabilityHandle,
+        tenant: &str,
+    ) -> Result<Vec<u8>, CryptoError> {
+        let expires_at = self
+            .capability_manager
+            .expires_at(capability)
+            .ok_or(CryptoError::AuthorizationFailed)?;
+        self.handle_sealer
+            .seal(HandleKind::Capability, capability.id(), tenant, expires_at)
+    }
+
+    /// Opens a sealed capability handle presented by `tenant`, rejecting it
+    /// like `open_key_handle` does.
+    pub fn open_capability_handle(
+        &self,
+        token: &[u8],
+        tenant: &str,
+    ) -> Result<CapabilityHandle, CryptoError> {
+        self.open_handle(HandleKind::Capability, token, tenant, self.epoch())
+            .map(CapabilityHandle::from_id)
+    }
+
+    fn open_handle(
+        &self,
+        kind: HandleKind,
+        token: &[u8],
+        tenant: &str,
+        now: u64,
+    ) -> Result<Uuid, CryptoError> {
+        self.handle_sealer
+            .open(kind, token, tenant, now)
+            .map_err(|fault| {
+                self.audit_manager.record(
+                    now,
+                    AuditEvent::RequestRejected {
+                        reason: fault.describe().to_string(),
+                    },
+                );
+                fault.error()
+            })
+    }
+
+    /// Returns a sink that records the events a module reports (e.g. the
+    /// CIEM's key state changes and tamper alarms) in this engine's audit
+    /// log. The sink may outlive the engine.
+    pub fn audit_sink(&self) -> Box<dyn ModuleEventSink + 'a> {
+        Box::new(self.sink())
+    }
+
+    fn sink(&self) -> AuditSink<'a> {
+        AuditSink {
+            audit_manager: Rc::clone(&self.audit_manager),
+            clock: Rc::clone(&self.clock),
+        }
+    }
+
+    /// Records a key state transition reported by a module that owns an FSM (e.g. the CIEM).
+    pub fn report_fsm_transition(&self, key_handle: Option<&KeyHandle>, from: &str, to: &str) {
+        self.sink().fsm_transition(key_handle, from, to);
+    }
+
+    /// Records a tamper event signalled by `source`.
+    pub fn report_tamper_event(&self, source: &str) {
+        self.sink().tamper_event(source);
+    }
+
+    /// Returns a copy of the hash-chained audit log.
+    pub fn audit_log(&self) -> Vec<AuditRecord> {
+        self.audit_manager.records()
+    }
+
+    /// Walks the audit log and reports the first broken link, if any.
+    pub fn verify_audit_log(&self) -> Result<(), AuditVerificationError> {
+        self.audit_manager.verify()
+    }
+
+    /// Returns an anchor for the audit log as it stands. Stored outside the
+    /// engine, it lets `verify_anchored_chain` detect a log that was later
+    /// rewritten or truncated, hashes and all.
+    pub fn audit_anchor(&self) -> AuditAnchor {
+        self.audit_manager.anchor()
+    }
+}
+
+/// The provider operation a capability call performs. Only verify calls carry
+/// a signature, and they must.
+fn crypto_operation<'d>(
+    operation: Operation,
+    aad: &'d [u8],
+    signature: Option<&'d [u8]>,
+) -> Result<CryptoOperation<'d>, CryptoError> {
+    Ok(match (operation, signature) {
+        (Operation::Verify, Some(signature)) => CryptoOperation::Verify { signature },
+        (Operation::Verify, None) => return Err(CryptoError::MalformedInput),
+        (_, Some(_)) => return Err(CryptoError::AuthorizationFailed),
+        (Operation::Encrypt, None) => CryptoOperation::Encrypt { aad },
+        (Operation::Decrypt, None) => CryptoOperation::Decrypt { aad },
+        (Operation::Sign, None) => CryptoOperation::Sign,
+        (Operation::Mac, None) => CryptoOperation::Mac,
+        (Operation::Derive, None) => CryptoOperation::Derive { info: aad },
+        (Operation::Wrap, None) => CryptoOperation::Wrap,
+        (Operation::Unwrap, None) => CryptoOperation::Unwrap,
+        (Operation::Encapsulate, None) => CryptoOperation::Encapsulate,
+        (Operation::Decapsulate, None) => CryptoOperation::Decapsulate,
+    })
+}
+
+/// Returns `true` if the request carries the AAD the obligations require, if any.
+fn aad_satisfied(obligations: &Obligations, request: &RequestEnvelope) -> bool {
+    obligations
+        .required_aad
+        .as_ref()
+        .is_none_or(|aad| aad == &request.aad)
+}
+
+/// The lifetime of a capability: the shortest of the default, the requested
+/// lifetime, the policy's expiry obligation and the profile's maximum.
+fn capability_lifetime(
+    profile: &SecurityProfile,
+    obligations: &Obligations,
+    request: &RequestEnvelope,
+) -> u64 {
+    [
+        request.lifetime,
+        obligations.expires_in,
+        profile.max_capability_lifetime,
+    ]
+    .into_iter()
+    .flatten()
+    .fold(DEFAULT_CAPABILITY_LIFETIME, u64::min)
 }
@@ -0,0 +1,53 @@
+// ucqcf_engine/src/request.rs
+
+//! Requirement interpretation: turns raw request bytes into a validated request.
+
+use ucqcf_core::CryptoError;
+use ucqcf_core::profile::SecurityProfile;
+use ucqcf_core::request::{KeySelector, RequestEnvelope};
+
+/// Interprets incoming requests against the caller's `SecurityProfile`.
+pub(crate) struct RequestManager {}
+
+impl RequestManager {
+    pub(crate) fn new() -> Self {
+        Self {}
+    }
+
+    /// Strictly parses a CBOR-encoded `RequestEnvelope` and rejects requests
+    /// that are well-formed but meaningless, made under a contradictory
+    /// profile, or naming an algorithm the profile does not permit.
+    pub(crate) fn interpret(
+        &self,
+        profile: &SecurityProfile,
+        request_data: &[u8],
+    ) -> Result<RequestEnvelope, CryptoError> {
+        profile.validate()?;
+        let request = RequestEnvelope::decode(request_data)?;
+
+        if request.caller.is_empty() {
+            return Err(CryptoError::MalformedInput);
+        }
+        if request
+            .algorithm
+            .is_some_and(|algorithm| !algorithm.supports(request.operation))
+        {
+            return Err(CryptoError::UnsupportedAlgorithm);
+        }
+        if request
+            .algorithm
+            .is_some_and(|algorithm| !profile.permits(algorithm))
+        {
+            return Err(CryptoError::AuthorizationFailed);
+        }
+        if request.lifetime == Some(0) {
+            return Err(CryptoError::MalformedInput);
+        }
+        // Data can only be decrypted or verified under a key that already exists.
+        if request.operation.requires_existing_key() && request.key == KeySelector::Generate {
+            return Err(CryptoError::MalformedInput);
+        }
+
+        Ok(request)
+    }
+}
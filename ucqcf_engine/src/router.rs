@@ -0,0 +1,457 @@
+// ucqcf_engine/src/router.rs
+
+//! Module orchestration: selects among the registered providers and routes work to them.
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use ucqcf_core::CryptoError;
+use ucqcf_core::algorithm::{AlgorithmId, CryptoOperation};
+use ucqcf_core::handles::KeyHandle;
+use ucqcf_core::interfaces::{CryptoProvider, KeyManager};
+use ucqcf_core::key::{DestructionProof, KeyFormat, KeyMetadata};
+use ucqcf_core::profile::{Domain, SecurityProfile};
+use ucqcf_core::request::Operation;
+
+/// How much assurance a provider gives about the protection of its keys.
+/// Higher levels are preferred when several providers can serve a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum AssuranceLevel {
+    /// Keys live in ordinary process memory.
+    Software,
+    /// Keys live in a hardware module such as the CIEM.
+    Hardware,
+    /// Keys live in a certified module such as an HSM.
+    Certified,
+}
+
+/// The capabilities a provider declares when it is registered with the engine.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProviderDescriptor {
+    /// A unique name identifying the provider.
+    pub name: String,
+    /// The algorithms the engine may use the provider for, most preferred first.
+    /// Must be a subset of what the provider implements.
+    pub algorithms: Vec<AlgorithmId>,
+    pub assurance: AssuranceLevel,
+    /// Whether the provider's algorithms resist quantum attacks.
+    pub quantum_resistant: bool,
+    /// The domains the provider may serve. An empty list means any domain.
+    pub domains: Vec<Domain>,
+}
+
+impl ProviderDescriptor {
+    /// Returns `true` if the provider may serve requests made under `profile`.
+    pub fn satisfies(&self, profile: &SecurityProfile) -> bool {
+        if profile.quantum_resistant && !self.quantum_resistant {
+            return false;
+        }
+        self.domains.is_empty() || self.domains.contains(&profile.domain)
+    }
+
+    /// Picks the algorithm the provider would use for `operation` under
+    /// `profile`: `requested` if the provider offers it, or else its most
+    /// preferred algorithm able to perform the operation. Only algorithms the
+    /// profile permits are considered. Returns `None` if the provider cannot serve it.
+    pub fn resolve_algorithm(
+        &self,
+        profile: &SecurityProfile,
+        requested: Option<AlgorithmId>,
+        operation: Operation,
+    ) -> Option<AlgorithmId> {
+        self.algorithms
+            .iter()
+            .copied()
+            .filter(|a| a.supports(operation) && profile.permits(*a))
+            .find(|a| requested.is_none_or(|requested| requested == *a))
+    }
+}
+
+/// A provider together with the key manager that owns its keys.
+struct RegisteredModule<'a> {
+    descriptor: ProviderDescriptor,
+    crypto_provider: Box<dyn CryptoProvider + 'a>,
+    key_manager: Box<dyn KeyManager + 'a>,
+}
+
+/// A key provisioned by the router, the provider that owns it and its algorithm.
+pub(crate) struct ProvisionedKey {
+    pub(crate) provider: String,
+    pub(crate) key_handle: KeyHandle,
+    pub(crate) algorithm: AlgorithmId,
+}
+
+/// What the router remembers about a key it provisioned.
+struct KeyOwner {
+    provider: String,
+    algorithm: AlgorithmId,
+    /// The handle as issued by the key manager, with any attributes attached.
+    handle: KeyHandle,
+}
+
+/// Dispatches key management and cryptographic operations to the registered modules.
+pub(crate) struct ModuleRouter<'a> {
+    modules: Vec<RegisteredModule<'a>>,
+    /// The owner and algorithm of each key provisioned through the router.
+    key_owners: RefCell<HashMap<KeyHandle, KeyOwner>>,
+}
+
+impl<'a> ModuleRouter<'a> {
+    pub(crate) fn new() -> Self {
+        Self {
+            modules: Vec::new(),
+            key_owners: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Registers a provider and its key manager. Provider names must be unique,
+    /// and the descriptor may only list algorithms the provider implements.
+    pub(crate) fn register(
+        &mut self,
+        descriptor: ProviderDescriptor,
+        crypto_provider: Box<dyn CryptoProvider + 'a>,
+        key_manager: Box<dyn KeyManager + 'a>,
+    ) -> Result<(), CryptoError> {
+        if self.module(&descriptor.name).is_some() {
+            return Err(CryptoError::InvalidState);
+        }
+        if !descriptor
+            .algorithms
+            .iter()
+            .all(|a| crypto_provider.algorithms().contains(a))
+        {
+            return Err(CryptoError::UnsupportedAlgorithm);
+        }
+        self.modules.push(RegisteredModule {
+            descriptor,
+            crypto_provider,
+            key_manager,
+        });
+        Ok(())
+    }
+
+    /// Returns the descriptors of all registered providers, in registration order.
+    pub(crate) fn descriptors(&self) -> Vec<ProviderDescriptor> {
+        self.modules.iter().map(|m| m.descriptor.clone()).collect()
+    }
+
+    /// Returns the modules able to serve `profile` and `operation`, most assured
+    /// first, with the algorithm each would use. Modules with equal assurance
+    /// keep their registration order.
+    fn candidates(
+        &self,
+        profile: &SecurityProfile,
+        algorithm: Option<AlgorithmId>,
+        operation: Operation,
+    ) -> Vec<(&RegisteredModule<'a>, AlgorithmId)> {
+        let mut candidates: Vec<_> = self
+            .modules
+            .iter()
+            .filter(|m| m.descriptor.satisfies(profile))
+            .filter_map(|m| {
+                let resolved = m
+                    .descriptor
+                    .resolve_algorithm(profile, algorithm, operation)?;
+                Some((m, resolved))
+            })
+            .collect();
+        candidates.sort_by_key(|(m, _)| std::cmp::Reverse(m.descriptor.assurance));
+        candidates
+    }
+
+    /// Returns the names of the providers `provision_key` would try, in order.
+    pub(crate) fn candidate_names(
+        &self,
+        profile: &SecurityProfile,
+        algorithm: Option<AlgorithmId>,
+        operation: Operation,
+    ) -> Vec<String> {
+        self.candidates(profile, algorithm, operation)
+            .into_iter()
+            .map(|(m, _)| m.descriptor.name.clone())
+            .collect()
+    }
+
+    fn module(&self, name: &str) -> Option<&RegisteredModule<'a>> {
+        self.modules.iter().find(|m| m.descriptor.name == name)
+    }
+
+    /// Provisions a key satisfying the profile, failing over to the next
+    /// candidate whenever a key manager reports an error. Each failure is
+    /// passed to `on_failure` before the next candidate is tried.
+    ///
+    /// Fails with `ProviderUnavailable` if no provider can serve the request,
+    /// or with the last candidate's error, unchanged, if all of them failed.
+    pub(crate) fn provision_key(
+        &self,
+        profile: &SecurityProfile,
+        algorithm: Option<AlgorithmId>,
+        operation: Operation,
+        mut on_failure: impl FnMut(&str, &CryptoError),
+    ) -> Result<ProvisionedKey, CryptoError> {
+        let mut last_failure = None;
+        for (module, algorithm) in self.candidates(profile, algorithm, operation) {
+            match module.key_manager.generate_key(profile, algorithm) {
+                Ok(key_handle) => {
+                    self.adopt(&module.descriptor.name, &key_handle, algorithm);
+                    return Ok(ProvisionedKey {
+                        provider: module.descriptor.name.clone(),
+                        key_handle,
+                        algorithm,
+                    });
+                }
+                Err(err) => {
+                    on_failure(&module.descriptor.name, &err);
+                    last_failure = Some(err);
+                }
+            }
+        }
+        Err(last_failure.unwrap_or(CryptoError::ProviderUnavailable))
+    }
+
+    /// Records that `provider` owns `key_handle`, so requests can name it.
+    fn adopt(&self, provider: &str, key_handle: &KeyHandle, algorithm: AlgorithmId) {
+        self.key_owners.borrow_mut().insert(
+            key_handle.clone(),
+            KeyOwner {
+                provider: provider.to_string(),
+                algorithm,
+                handle: key_handle.clone(),
+            },
+        );
+    }
+
+    /// Finds the provider owning a key provisioned earlier, and checks that it
+    /// can still serve `profile`, and that the key's algorithm is `algorithm`
+    /// (if given), is permitted by `profile` and can perform `operation`. Keys
+    /// the router never provisioned are not usable through the engine
+    /// (`KeyNotFound`), and keys whose attributes restrict their operations
+    /// cannot be used for others. A key's cryptoperiod is not checked here:
+    /// its `expires_at` is on the key manager's clock, not the engine's, so
+    /// the provider refuses to protect data with an expired key.
+    ///
+    /// The returned handle is the one issued by the key manager, with its
+    /// attributes, even if `key_handle` came from a client without them.
+    pub(crate) fn locate_key(
+        &self,
+        key_handle: &KeyHandle,
+        profile: &SecurityProfile,
+        algorithm: Option<AlgorithmId>,
+        operation: Operation,
+    ) -> Result<ProvisionedKey, CryptoError> {
+        let key_owners = self.key_owners.borrow();
+        let owner = key_owners.get(key_handle).ok_or(CryptoError::KeyNotFound)?;
+        if algorithm.is_some_and(|algorithm| algorithm != owner.algorithm)
+            || !owner.algorithm.supports(operation)
+        {
+            return Err(CryptoError::UnsupportedAlgorithm);
+        }
+        if !profile.permits(owner.algorithm)
+            || owner
+                .handle
+                .attributes()
+                .is_some_and(|attributes| !attributes.allows(operation))
+        {
+            return Err(CryptoError::AuthorizationFailed);
+        }
+        let module = self
+            .module(&owner.provider)
+            .ok_or(CryptoError::ProviderUnavailable)?;
+        if !module.descriptor.satisfies(profile)
+            || !module.descriptor.algorithms.contains(&owner.algorithm)
+        {
+            return Err(CryptoError::ProviderUnavailable);
+        }
+        Ok(ProvisionedKey {
+            provider: owner.provider.clone(),
+            key_handle: owner.handle.clone(),
+            algorithm: owner.algorithm,
+        })
+    }
+
+    /// Returns the non-sensitive attributes the router knows about a key, for
+    /// policy evaluation. Attributes attached to the key's handle by its key
+    /// manager are included; labels appear as `label.<name>`.
+    pub(crate) fn key_attributes(&self, key_handle: &KeyHandle) -> BTreeMap<String, String> {
+        let mut attributes = BTreeMap::from([("origin".to_string(), "existing".to_string())]);
+        let key_owners = self.key_owners.borrow();
+        let Some(owner) = key_owners.get(key_handle) else {
+            return attributes;
+        };
+        attributes.insert("provider".to_string(), owner.provider.clone());
+        attributes.insert("algorithm".to_string(), owner.algorithm.name().to_string());
+        if let Some(module) = self.module(&owner.provider) {
+            attributes.insert(
+                "assurance".to_string(),
+                format!("{:?}", module.descriptor.assurance).to_lowercase(),
+            );
+        }
+        if let Some(key) = owner.handle.attributes() {
+            attributes.insert("created_at".to_string(), key.created_at.to_string());
+            if let Some(expires_at) = key.expires_at {
+                attributes.insert("expires_at".to_string(), expires_at.to_string());
+            }
+            if let Some(key_owner) = &key.owner {
+                attributes.insert("owner".to_string(), key_owner.clone());
+            }
+            if let Some(hardware) = &key.origin_hardware {
+                attributes.insert("origin_hardware".to_string(), hardware.clone());
+            }
+            for (name, value) in &key.labels {
+                attributes.insert(format!("label.{}", name), value.clone());
+            }
+        }
+        attributes
+    }
+
+    /// Returns the module owning a key provisioned through the router, and the key's algorithm.
+    fn owner(
+        &self,
+        key_handle: &KeyHandle,
+    ) -> Result<(&RegisteredModule<'a>, AlgorithmId), CryptoError> {
+        let key_owners = self.key_owners.borrow();
+        let owner = key_owners.get(key_handle).ok_or(CryptoError::KeyNotFound)?;
+        let module = self
+            .module(&owner.provider)
+            .ok_or(CryptoError::ProviderUnavailable)?;
+        Ok((module, owner.algorithm))
+    }
+
+    /// Returns `true` if the key was provisioned through the router and is still routable.
+    pub(crate) fn knows_key(&self, key_handle: &KeyHandle) -> bool {
+        self.key_owners.borrow().contains_key(key_handle)
+    }
+
+    /// Returns the key manager owning a key provisioned through the router.
+    pub(crate) fn key_manager(
+        &self,
+        key_handle: &KeyHandle,
+    ) -> Result<&dyn KeyManager, CryptoError> {
+        self.owner(key_handle)
+            .map(|(module, _)| module.key_manager.as_ref())
+    }
+
+    /// Imports key material into the named provider, which must be allowed
+    /// to serve `profile` and `algorithm`.
+    pub(crate) fn import_key(
+        &self,
+        provider: &str,
+        profile: &SecurityProfile,
+        algorithm: AlgorithmId,
+        format: KeyFormat,
+        material: &[u8],
+    ) -> Result<KeyHandle, CryptoError> {
+        let module = self
+            .module(provider)
+            .ok_or(CryptoError::ProviderUnavailable)?;
+        if !module.descriptor.satisfies(profile) {
+            return Err(CryptoError::ProviderUnavailable);
+        }
+        if !module.descriptor.algorithms.contains(&algorithm) {
+            return Err(CryptoError::UnsupportedAlgorithm);
+        }
+        let key_handle = module
+            .key_manager
+            .import_key(profile, algorithm, format, material)?;
+        self.adopt(provider, &key_handle, algorithm);
+        Ok(key_handle)
+    }
+
+    /// Rotates a key within its provider. Both keys stay routable.
+    pub(crate) fn rotate_key(&self, key_handle: &KeyHandle) -> Result<KeyHandle, CryptoError> {
+        let (module, algorithm) = self.owner(key_handle)?;
+        let successor = module.key_manager.rotate_key(key_handle)?;
+        self.adopt(&module.descriptor.name, &successor, algorithm);
+        Ok(successor)
+    }
+
+    /// Replaces the labels of a key in its key manager, and in the handle the
+    /// router keeps for it. The key manager's metadata is taken as the new
+    /// attributes when it discloses them.
+    pub(crate) fn set_labels(
+        &self,
+        key_handle: &KeyHandle,
+        labels: BTreeMap<String, String>,
+    ) -> Result<(), CryptoError> {
+        let (module, _) = self.owner(key_handle)?;
+        module.key_manager.set_labels(key_handle, labels.clone())?;
+        let metadata = module.key_manager.key_metadata(key_handle).ok();
+
+        let mut key_owners = self.key_owners.borrow_mut();
+        let owner = key_owners
+            .get_mut(key_handle)
+            .ok_or(CryptoError::KeyNotFound)?;
+        let attributes = match metadata {
+            Some(metadata) => Some(metadata.attributes),
+            None => owner.handle.attributes().cloned().map(|mut attributes| {
+                attributes.labels = labels;
+                attributes
+            }),
+        };
+        if let Some(attributes) = attributes {
+            owner.handle = owner.handle.clone().with_attributes(attributes);
+        }
+        Ok(())
+    }
+
+    /// Exports a key wrapped under another key of the same provider.
+    pub(crate) fn export_wrapped(
+        &self,
+        key_handle: &KeyHandle,
+        wrapping_key: &KeyHandle,
+    ) -> Result<Vec<u8>, CryptoError> {
+        let (module, _) = self.owner(key_handle)?;
+        let (wrapping_module, wrapping_algorithm) = self.owner(wrapping_key)?;
+        if module.descriptor.name != wrapping_module.descriptor.name {
+            return Err(CryptoError::ProviderUnavailable);
+        }
+        if !wrapping_algorithm.supports(Operation::Wrap) {
+            return Err(CryptoError::UnsupportedAlgorithm);
+        }
+        module.key_manager.export_wrapped(key_handle, wrapping_key)
+    }
+
+    /// Destroys a key in its provider and stops routing to it.
+    pub(crate) fn destroy_key(
+        &self,
+        key_handle: &KeyHandle,
+    ) -> Result<DestructionProof, CryptoError> {
+        let (module, _) = self.owner(key_handle)?;
+        let proof = module.key_manager.destroy_key(key_handle)?;
+        self.key_owners.borrow_mut().remove(key_handle);
+        Ok(proof)
+    }
+
+    /// Collects the keys of every provider whose key manager can enumerate them.
+    pub(crate) fn list_keys(&self) -> Result<Vec<KeyMetadata>, CryptoError> {
+        let mut keys = Vec::new();
+        for module in &self.modules {
+            match module.key_manager.list_keys() {
+                Ok(listed) => keys.extend(listed),
+                Err(CryptoError::NotSupported) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(keys)
+    }
+
+    /// Executes an operation on the provider that owns `key_handle`.
+    /// There is no failover here: the key only exists inside that provider.
+    pub(crate) fn execute(
+        &self,
+        provider: &str,
+        key_handle: &KeyHandle,
+        algorithm: AlgorithmId,
+        operation: CryptoOperation<'_>,
+        data: &[u8],
+    ) -> Result<Vec<u8>, CryptoError> {
+        let module = self
+            .module(provider)
+            .ok_or(CryptoError::ProviderUnavailable)?;
+        if !module.crypto_provider.supports(algorithm, operation.kind()) {
+            return Err(CryptoError::UnsupportedAlgorithm);
+        }
+        module
+            .crypto_provider
+            .execute_crypto(key_handle, algorithm, operation, data)
+    }
+}
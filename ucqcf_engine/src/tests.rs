@@ -0,0 +1,1358 @@
+// ucqcf_engine/src/tests.rs
+
+//! Engine tests, run against an in-memory provider and key manager.
+
+use super::*;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use ucqcf_core::clock::NANOS_PER_EPOCH;
+use ucqcf_core::interfaces::PolicyDecision;
+use ucqcf_core::key::{KeyAttributes, KeyOrigin};
+use ucqcf_core::profile::Domain;
+
+/// The keys of a `MockKeyManager`, shared with the test.
+#[derive(Clone, Default)]
+struct Keys(Rc<RefCell<HashMap<KeyHandle, KeyAttributes>>>);
+
+/// A clock the test sets by hand, in epochs.
+#[derive(Clone, Default)]
+struct TestClock(Rc<Cell<u64>>);
+
+impl TestClock {
+    fn advance(&self, epochs: u64) {
+        self.0.set(self.0.get() + epochs);
+    }
+}
+
+impl ClockSource for TestClock {
+    fn now(&self) -> u64 {
+        self.0.get() * NANOS_PER_EPOCH
+    }
+
+    fn name(&self) -> &'static str {
+        "TestClock"
+    }
+}
+
+/// A provider whose "encryption" returns its input unchanged, after the
+/// associated data it was given. Like a hardware module, it refuses to
+/// encrypt under a key of `keys` whose cryptoperiod has ended on `clock`.
+#[derive(Default)]
+struct MockProvider {
+    keys: Keys,
+    clock: TestClock,
+}
+
+impl CryptoProvider for MockProvider {
+    fn algorithms(&self) -> &[AlgorithmId] {
+        &[
+            AlgorithmId::Aes256Gcm,
+            AlgorithmId::MlDsa65,
+            AlgorithmId::MlDsa87,
+        ]
+    }
+
+    fn execute_crypto(
+        &self,
+        key_handle: &KeyHandle,
+        _algorithm: AlgorithmId,
+        operation: CryptoOperation<'_>,
+        data: &[u8],
+    ) -> Result<Vec<u8>, CryptoError> {
+        let expired = self
+            .keys
+            .0
+            .borrow()
+            .get(key_handle)
+            .and_then(|attributes| attributes.expires_at)
+            .is_some_and(|expires_at| expires_at <= self.clock.0.get());
+        if expired && matches!(operation, CryptoOperation::Encrypt { .. }) {
+            return Err(CryptoError::OutsideCryptoperiod);
+        }
+        let mut output = match operation {
+            CryptoOperation::Encrypt { aad } => aad.to_vec(),
+            _ => Vec::new(),
+        };
+        output.extend_from_slice(data);
+        Ok(output)
+    }
+}
+
+struct MockKeyManager {
+    keys: Keys,
+    /// The clock keys are created and expire on.
+    clock: TestClock,
+    /// Whether `destroy_key` is supported.
+    destroys: bool,
+}
+
+impl KeyManager for MockKeyManager {
+    fn generate_key(
+        &self,
+        profile: &SecurityProfile,
+        algorithm: AlgorithmId,
+    ) -> Result<KeyHandle, CryptoError> {
+        if profile.require_atomic_time {
+            return Err(CryptoError::ProfileUnsatisfied(
+                "no atomic clock".to_string(),
+            ));
+        }
+        let now = self.clock.0.get();
+        let mut attributes = KeyAttributes::new(algorithm, now);
+        attributes.expires_at = profile
+            .key_cryptoperiod
+            .map(|cryptoperiod| now + cryptoperiod);
+        let handle = KeyHandle::new().with_attributes(attributes.clone());
+        self.keys.0.borrow_mut().insert(handle.clone(), attributes);
+        Ok(handle)
+    }
+
+    fn delete_key(&self, key_handle: &KeyHandle) -> Result<(), CryptoError> {
+        self.keys.0.borrow_mut().remove(key_handle);
+        Ok(())
+    }
+
+    fn key_metadata(&self, key_handle: &KeyHandle) -> Result<KeyMetadata, CryptoError> {
+        let keys = self.keys.0.borrow();
+        let attributes = keys.get(key_handle).ok_or(CryptoError::KeyNotFound)?;
+        Ok(KeyMetadata {
+            handle: key_handle.clone(),
+            attributes: attributes.clone(),
+            origin: KeyOrigin::Generated,
+            predecessor: None,
+            exportable: false,
+        })
+    }
+
+    fn set_labels(
+        &self,
+        key_handle: &KeyHandle,
+        labels: BTreeMap<String, String>,
+    ) -> Result<(), CryptoError> {
+        let mut keys = self.keys.0.borrow_mut();
+        keys.get_mut(key_handle)
+            .ok_or(CryptoError::KeyNotFound)?
+            .labels = labels;
+        Ok(())
+    }
+
+    fn destroy_key(&self, key_handle: &KeyHandle) -> Result<DestructionProof, CryptoError> {
+        if !self.destroys {
+            return Err(CryptoError::NotSupported);
+        }
+        self.delete_key(key_handle)?;
+        Ok(DestructionProof {
+            key: key_handle.clone(),
+            destroyed_at: 0,
+            attestation: Vec::new(),
+        })
+    }
+}
+
+/// Permits fresh keys, and existing keys labelled `mission = alpha`. Fails
+/// to evaluate requests from `broken`, limits the capabilities of `limited`
+/// to two uses, those of `exhausted` to none and those of `brief` to five
+/// epochs, and requires the AAD `mission` from `bound`.
+struct MissionPolicy;
+
+impl PolicyInterface for MissionPolicy {
+    fn evaluate(&self, context: &PolicyContext) -> Result<PolicyDecision, CryptoError> {
+        if context.principal == "broken" {
+            return Err(CryptoError::InvalidState);
+        }
+        let attribute = |name: &str| context.key_attributes.get(name).map(String::as_str);
+        if attribute("origin") == Some("generated") || attribute("label.mission") == Some("alpha") {
+            Ok(PolicyDecision::permit().with_obligations(Obligations {
+                max_uses: match context.principal.as_str() {
+                    "limited" => Some(2),
+                    "exhausted" => Some(0),
+                    _ => None,
+                },
+                expires_in: (context.principal == "brief").then_some(5),
+                required_aad: (context.principal == "bound").then(|| b"mission".to_vec()),
+                ..Obligations::default()
+            }))
+        } else {
+            Ok(PolicyDecision::not_applicable("not a mission key"))
+        }
+    }
+}
+
+fn engine(destroys: bool) -> CoreEngine<'static> {
+    engine_with_clock(destroys, TestClock::default())
+}
+
+fn engine_with_clock(destroys: bool, clock: TestClock) -> CoreEngine<'static> {
+    engine_with_clocks(destroys, clock.clone(), clock)
+}
+
+/// Returns an engine reading `engine_clock`, whose key manager and provider
+/// read `key_clock`.
+fn engine_with_clocks(
+    destroys: bool,
+    engine_clock: TestClock,
+    key_clock: TestClock,
+) -> CoreEngine<'static> {
+    let mut engine = CoreEngine::new(Box::new(MissionPolicy));
+    engine.set_clock_source(Box::new(engine_clock));
+    let keys = Keys::default();
+    engine
+        .register_provider(
+            descriptor("mock", AssuranceLevel::Software),
+            Box::new(MockProvider {
+                keys: keys.clone(),
+                clock: key_clock.clone(),
+            }),
+            Box::new(MockKeyManager {
+                keys,
+                clock: key_clock,
+                destroys,
+            }),
+        )
+        .unwrap();
+    engine
+}
+
+/// Describes a classical AES-256-GCM provider serving every domain.
+fn descriptor(name: &str, assurance: AssuranceLevel) -> ProviderDescriptor {
+    ProviderDescriptor {
+        name: name.to_string(),
+        algorithms: vec![AlgorithmId::Aes256Gcm],
+        assurance,
+        quantum_resistant: false,
+        domains: Vec::new(),
+    }
+}
+
+/// Registers a `MockProvider` under `descriptor`, with a key manager of its own.
+fn register(engine: &mut CoreEngine<'static>, descriptor: ProviderDescriptor) {
+    engine
+        .register_provider(
+            descriptor,
+            Box::new(MockProvider::default()),
+            Box::new(MockKeyManager {
+                keys: Keys::default(),
+                clock: TestClock::default(),
+                destroys: true,
+            }),
+        )
+        .unwrap();
+}
+
+/// A key manager whose hardware is gone: it cannot generate keys.
+struct UnavailableKeyManager;
+
+impl KeyManager for UnavailableKeyManager {
+    fn generate_key(
+        &self,
+        _profile: &SecurityProfile,
+        _algorithm: AlgorithmId,
+    ) -> Result<KeyHandle, CryptoError> {
+        Err(CryptoError::ProviderUnavailable)
+    }
+
+    fn delete_key(&self, _key_handle: &KeyHandle) -> Result<(), CryptoError> {
+        Err(CryptoError::KeyNotFound)
+    }
+}
+
+fn profile() -> SecurityProfile {
+    SecurityProfile::new(Domain::EndToEnd)
+}
+
+/// Requests an encrypt capability on a fresh key for `alice`, returning the
+/// sealed capability and the key.
+fn generate(engine: &CoreEngine<'_>) -> (Vec<u8>, KeyHandle) {
+    let request = RequestEnvelope::new(Operation::Encrypt, "alice");
+    let capability = engine
+        .execute_request(&profile(), &request.encode().unwrap())
+        .unwrap();
+    let handle = engine.open_capability_handle(&capability, "alice").unwrap();
+    let key = engine
+        .list_capabilities()
+        .into_iter()
+        .find(|info| info.handle == handle)
+        .unwrap()
+        .key_handle;
+    (capability, key)
+}
+
+/// Encodes a request from `alice` to encrypt under the sealed key `token`.
+fn existing_key_request(token: Vec<u8>) -> Vec<u8> {
+    let mut request = RequestEnvelope::new(Operation::Encrypt, "alice");
+    request.key = KeySelector::Existing(token);
+    request.encode().unwrap()
+}
+
+/// Flips one bit of a sealed handle's handle id.
+fn tamper(mut token: Vec<u8>) -> Vec<u8> {
+    token[20] ^= 1;
+    token
+}
+
+#[test]
+fn malformed_requests_never_reach_the_policy() {
+    let engine = engine(true);
+    let request = RequestEnvelope::new(Operation::Encrypt, "alice");
+    let mut trailing = request.encode().unwrap();
+    trailing.push(0);
+    let malformed = [
+        b"encrypt".to_vec(),
+        Vec::new(),
+        trailing,
+        RequestEnvelope::new(Operation::Encrypt, "")
+            .encode()
+            .unwrap(),
+        // Decryption needs an existing key.
+        RequestEnvelope::new(Operation::Decrypt, "alice")
+            .encode()
+            .unwrap(),
+    ];
+    for request in &malformed {
+        assert_eq!(
+            engine.execute_request(&profile(), request),
+            Err(CryptoError::MalformedInput)
+        );
+    }
+    assert!(
+        !engine
+            .audit_log()
+            .iter()
+            .any(|record| matches!(record.event, AuditEvent::PolicyDecision { .. }))
+    );
+}
+
+#[test]
+fn relabelling_a_key_changes_policy_decisions() {
+    let engine = engine(true);
+    let (_, key) = generate(&engine);
+    let request = existing_key_request(engine.seal_key_handle(&key, "alice", 100).unwrap());
+    assert_eq!(
+        engine.execute_request(&profile(), &request),
+        Err(CryptoError::AuthorizationFailed)
+    );
+
+    let labels = BTreeMap::from([("mission".to_string(), "alpha".to_string())]);
+    engine.set_key_labels(&key, labels.clone()).unwrap();
+    assert!(engine.execute_request(&profile(), &request).is_ok());
+    assert_eq!(engine.key_metadata(&key).unwrap().attributes.labels, labels);
+
+    engine.set_key_labels(&key, BTreeMap::new()).unwrap();
+    assert_eq!(
+        engine.execute_request(&profile(), &request),
+        Err(CryptoError::AuthorizationFailed)
+    );
+}
+
+#[test]
+fn policies_see_the_request_attributes() {
+    let engine = engine(true);
+    engine.set_environment_attribute("site", "lab");
+    let (_, key) = generate(&engine);
+    let labels = BTreeMap::from([("mission".to_string(), "alpha".to_string())]);
+    engine.set_key_labels(&key, labels).unwrap();
+
+    let mut request = RequestEnvelope::new(Operation::Sign, "alice");
+    request.algorithm = Some(AlgorithmId::MlDsa65);
+    let context = engine
+        .dry_run(&profile(), &request.encode().unwrap())
+        .unwrap()
+        .context;
+    assert_eq!(context.principal, "alice");
+    assert_eq!(context.operation, Operation::Sign);
+    assert_eq!(context.algorithm, Some(AlgorithmId::MlDsa65));
+    assert_eq!(context.key_attributes["origin"], "generated");
+    assert_eq!(context.profile, profile());
+    assert_eq!(context.environment["site"], "lab");
+
+    engine.remove_environment_attribute("site");
+    let request = existing_key_request(engine.seal_key_handle(&key, "alice", 100).unwrap());
+    let context = engine.dry_run(&profile(), &request).unwrap().context;
+    assert!(context.environment.is_empty());
+    assert_eq!(context.key_attributes["origin"], "existing");
+    assert_eq!(context.key_attributes["provider"], "mock");
+    assert_eq!(context.key_attributes["algorithm"], "AES-256-GCM");
+    assert_eq!(context.key_attributes["label.mission"], "alpha");
+}
+
+#[test]
+fn destroying_a_key_revokes_its_capabilities() {
+    let engine = engine(true);
+    let (capability, key) = generate(&engine);
+    let proof = engine.destroy_key(&key).unwrap();
+    assert_eq!(proof.key, key);
+    assert!(engine.list_capabilities().is_empty());
+    assert_eq!(
+        engine.execute_capability(&capability, "alice", b"data"),
+        Err(CryptoError::AuthorizationFailed)
+    );
+}
+
+#[test]
+fn failing_to_destroy_a_key_keeps_its_capabilities() {
+    let engine = engine(false);
+    let (capability, key) = generate(&engine);
+    assert_eq!(engine.destroy_key(&key), Err(CryptoError::NotSupported));
+    assert_eq!(engine.list_capabilities().len(), 1);
+    assert_eq!(
+        engine.execute_capability(&capability, "alice", b"data"),
+        Ok(b"data".to_vec())
+    );
+    assert!(
+        !engine
+            .audit_log()
+            .iter()
+            .any(|record| matches!(record.event, AuditEvent::CapabilityRevoked { .. }))
+    );
+}
+
+#[test]
+fn tampered_tokens_are_rejected() {
+    let engine = engine(true);
+    let (capability, key) = generate(&engine);
+    let key_token = engine.seal_key_handle(&key, "alice", 100).unwrap();
+    assert_eq!(
+        engine.execute_request(&profile(), &existing_key_request(tamper(key_token))),
+        Err(CryptoError::AuthorizationFailed)
+    );
+    assert_eq!(
+        engine.execute_capability(&tamper(capability.clone()), "alice", b"data"),
+        Err(CryptoError::AuthorizationFailed)
+    );
+    // Raw handle ids are not tokens.
+    let raw = engine.list_capabilities()[0].handle.id();
+    assert_eq!(
+        engine.execute_capability(raw.as_bytes(), "alice", b"data"),
+        Err(CryptoError::MalformedInput)
+    );
+    assert_eq!(
+        engine.execute_capability(&capability, "mallory", b"data"),
+        Err(CryptoError::AuthorizationFailed)
+    );
+    assert_eq!(
+        engine.execute_capability(&capability, "alice", b"data"),
+        Ok(b"data".to_vec())
+    );
+}
+
+#[test]
+fn expired_tokens_are_rejected() {
+    let clock = TestClock::default();
+    clock.advance(1_000);
+    let engine = engine_with_clock(true, clock.clone());
+    let (capability, key) = generate(&engine);
+    let key_token = engine.seal_key_handle(&key, "alice", 10).unwrap();
+    engine
+        .set_key_labels(
+            &key,
+            BTreeMap::from([("mission".to_string(), "alpha".to_string())]),
+        )
+        .unwrap();
+    let request = existing_key_request(key_token);
+
+    clock.advance(9);
+    assert!(engine.execute_request(&profile(), &request).is_ok());
+    clock.advance(1);
+    assert_eq!(
+        engine.execute_request(&profile(), &request),
+        Err(CryptoError::CapabilityExpired)
+    );
+
+    clock.advance(DEFAULT_CAPABILITY_LIFETIME);
+    assert_eq!(
+        engine.execute_capability(&capability, "alice", b"data"),
+        Err(CryptoError::CapabilityExpired)
+    );
+}
+
+#[test]
+fn tokens_from_another_engine_are_rejected() {
+    let issuer = engine(true);
+    let other = engine(true);
+    let (capability, key) = generate(&issuer);
+    let key_token = issuer.seal_key_handle(&key, "alice", 100).unwrap();
+    assert_eq!(
+        other.execute_request(&profile(), &existing_key_request(key_token)),
+        Err(CryptoError::AuthorizationFailed)
+    );
+    assert_eq!(
+        other.execute_capability(&capability, "alice", b"data"),
+        Err(CryptoError::AuthorizationFailed)
+    );
+
+    // Engines sharing a sealing secret still tell their tokens apart by id.
+    let secret = [7; 32];
+    let mut first = engine(true);
+    let mut second = engine(true);
+    first
+        .set_handle_sealing_key(Uuid::new_v4(), &secret)
+        .unwrap();
+    second
+        .set_handle_sealing_key(Uuid::new_v4(), &secret)
+        .unwrap();
+    let (capability, _) = generate(&first);
+    assert_eq!(
+        second.execute_capability(&capability, "alice", b"data"),
+        Err(CryptoError::AuthorizationFailed)
+    );
+    assert!(second.audit_log().iter().any(|record| record.event
+        == AuditEvent::RequestRejected {
+            reason: "sealed handle was issued by another engine".to_string()
+        }));
+}
+
+#[test]
+fn short_sealing_secrets_are_rejected() {
+    let mut engine = engine(true);
+    assert_eq!(
+        engine.set_handle_sealing_key(Uuid::new_v4(), &[7; 31]),
+        Err(CryptoError::MalformedInput)
+    );
+    assert_eq!(
+        engine.set_handle_sealing_key(Uuid::new_v4(), b""),
+        Err(CryptoError::MalformedInput)
+    );
+}
+
+#[test]
+fn policies_see_the_secure_clock_time() {
+    let clock = TestClock::default();
+    clock.advance(1_700_000_000);
+    let engine = engine_with_clock(true, clock.clone());
+    let request = RequestEnvelope::new(Operation::Encrypt, "alice")
+        .encode()
+        .unwrap();
+    assert_eq!(
+        engine.dry_run(&profile(), &request).unwrap().context.epoch,
+        1_700_000_000
+    );
+    clock.advance(5);
+    assert_eq!(
+        engine.dry_run(&profile(), &request).unwrap().context.epoch,
+        1_700_000_005
+    );
+}
+
+#[test]
+fn policy_errors_are_audited() {
+    let engine = engine(true);
+    let request = RequestEnvelope::new(Operation::Encrypt, "broken")
+        .encode()
+        .unwrap();
+    assert_eq!(
+        engine.execute_request(&profile(), &request),
+        Err(CryptoError::InvalidState)
+    );
+    let log = engine.audit_log();
+    assert!(matches!(
+        &log.last().unwrap().event,
+        AuditEvent::RequestRejected { reason } if reason.starts_with("policy evaluation failed")
+    ));
+}
+
+#[test]
+fn anchored_verification_detects_rewritten_logs() {
+    let engine = engine(true);
+    assert_eq!(
+        engine.audit_anchor(),
+        AuditAnchor {
+            length: 0,
+            head: GENESIS_HASH
+        }
+    );
+    generate(&engine);
+    let anchor = engine.audit_anchor();
+    generate(&engine);
+    let log = engine.audit_log();
+    assert_eq!(verify_anchored_chain(&log, &anchor), Ok(()));
+
+    // Rewriting a record and rehashing everything after it passes the chain
+    // check, but not the anchor.
+    let mut rewritten = log.clone();
+    rewritten[0].event = AuditEvent::RequestReceived { size: 1 };
+    let mut previous_hash = GENESIS_HASH;
+    for record in &mut rewritten {
+        record.previous_hash = previous_hash;
+        record.hash = record.compute_hash();
+        previous_hash = record.hash;
+    }
+    assert_eq!(verify_chain(&rewritten), Ok(()));
+    assert_eq!(
+        verify_anchored_chain(&rewritten, &anchor),
+        Err(AuditVerificationError {
+            index: anchor.length as usize - 1,
+            fault: AuditFault::AnchorMismatch,
+        })
+    );
+
+    // Truncation is reported at the last record left.
+    let truncated = &log[..anchor.length as usize - 1];
+    assert_eq!(
+        verify_anchored_chain(truncated, &anchor),
+        Err(AuditVerificationError {
+            index: truncated.len() - 1,
+            fault: AuditFault::Truncated,
+        })
+    );
+    assert_eq!(
+        verify_anchored_chain(&[], &anchor),
+        Err(AuditVerificationError {
+            index: 0,
+            fault: AuditFault::Truncated,
+        })
+    );
+}
+
+#[test]
+fn verification_reports_the_first_broken_link() {
+    use ucqcf_policy::RulePolicy;
+
+    let mut engine = CoreEngine::new(Box::new(RulePolicy::parse("deny \"all\";").unwrap()));
+    register(&mut engine, descriptor("mock", AssuranceLevel::Software));
+    let requests = [
+        RequestEnvelope::new(Operation::Encrypt, "alice")
+            .encode()
+            .unwrap(),
+        RequestEnvelope::new(Operation::Sign, "alice")
+            .encode()
+            .unwrap(),
+        b"launch".to_vec(),
+    ];
+    for request in &requests {
+        let _ = engine.execute_request(&profile(), request);
+    }
+    let log = engine.audit_log();
+    assert_eq!(log.len(), 6);
+    assert_eq!(verify_chain(&log), Ok(()));
+
+    let mut edited = log.clone();
+    edited[2].epoch += 1;
+    assert_eq!(
+        verify_chain(&edited),
+        Err(AuditVerificationError {
+            index: 2,
+            fault: AuditFault::HashMismatch,
+        })
+    );
+
+    let mut deleted = log.clone();
+    deleted.remove(3);
+    assert_eq!(
+        verify_chain(&deleted),
+        Err(AuditVerificationError {
+            index: 3,
+            fault: AuditFault::SequenceMismatch,
+        })
+    );
+
+    let mut reordered = log.clone();
+    reordered.swap(1, 4);
+    (reordered[1].sequence, reordered[4].sequence) = (1, 4);
+    assert_eq!(
+        verify_chain(&reordered),
+        Err(AuditVerificationError {
+            index: 1,
+            fault: AuditFault::BrokenLink,
+        })
+    );
+}
+
+#[test]
+fn ciem_state_changes_and_tamper_are_audited() {
+    use ring::hmac;
+    use ucqcf_ciem::CIEM;
+    use ucqcf_ciem::entropy::EntropyAggregator;
+    use ucqcf_mock_hw::clock::MockAtomicClock;
+    use ucqcf_mock_hw::rng::{MockQRNG, MockTRNG};
+
+    let engine = engine(true);
+    let aggregator = EntropyAggregator::new(
+        Box::new(MockTRNG),
+        vec![Box::new(MockQRNG)],
+        hmac::Key::new(hmac::HMAC_SHA256, &[7; 32]),
+    );
+    let mut ciem = CIEM::new(aggregator, Box::new(MockAtomicClock)).unwrap();
+    ciem.set_event_sink(engine.audit_sink());
+
+    let key = ciem
+        .generate_key(AlgorithmId::Aes256Gcm, &profile())
+        .unwrap();
+    ciem.suspend_key(&key).unwrap();
+    assert!(ciem.resume_key(&key).is_ok());
+    ciem.inject_tamper();
+
+    let events: Vec<_> = engine
+        .audit_log()
+        .into_iter()
+        .map(|record| record.event)
+        .collect();
+    let transition = |from: &str, to: &str| AuditEvent::FsmTransition {
+        key: Some(key.id()),
+        from: from.to_string(),
+        to: to.to_string(),
+    };
+    assert_eq!(
+        events,
+        vec![
+            transition("Empty", "Bound"),
+            transition("Bound", "Suspended"),
+            transition("Suspended", "Bound"),
+            AuditEvent::TamperEvent {
+                source: "ciem".to_string()
+            },
+            transition("Bound", "Zeroized"),
+        ]
+    );
+    assert_eq!(engine.verify_audit_log(), Ok(()));
+}
+
+#[test]
+fn key_manager_errors_pass_through_unchanged() {
+    let engine = engine(true);
+    let mut profile = profile();
+    profile.require_atomic_time = true;
+    let request = RequestEnvelope::new(Operation::Encrypt, "alice")
+        .encode()
+        .unwrap();
+    assert_eq!(
+        engine.execute_request(&profile, &request),
+        Err(CryptoError::ProfileUnsatisfied(
+            "no atomic clock".to_string()
+        ))
+    );
+    assert!(engine.audit_log().iter().any(|record| matches!(
+        &record.event,
+        AuditEvent::ProviderFailover { provider, .. } if provider == "mock"
+    )));
+}
+
+#[test]
+fn malformed_calls_consume_no_uses() {
+    let engine = engine(true);
+    let request = RequestEnvelope::new(Operation::Encrypt, "limited");
+    let capability = engine
+        .execute_request(&profile(), &request.encode().unwrap())
+        .unwrap();
+    let remaining_uses = || engine.list_capabilities()[0].remaining_uses;
+    assert_eq!(remaining_uses(), Some(2));
+
+    for _ in 0..3 {
+        assert_eq!(
+            engine.verify_with_capability(&capability, "limited", b"message", b"signature"),
+            Err(CryptoError::AuthorizationFailed)
+        );
+    }
+    assert_eq!(remaining_uses(), Some(2));
+
+    assert!(
+        engine
+            .execute_capability(&capability, "limited", b"message")
+            .is_ok()
+    );
+    assert_eq!(remaining_uses(), Some(1));
+}
+
+#[test]
+fn capabilities_without_uses_are_not_issued() {
+    let engine = engine(true);
+    let request = RequestEnvelope::new(Operation::Encrypt, "exhausted")
+        .encode()
+        .unwrap();
+    let report = engine.dry_run(&profile(), &request).unwrap();
+    assert!(report.evaluation.decision.allow);
+    assert_eq!(
+        report.refusal.as_deref(),
+        Some("policy allows no uses of the capability")
+    );
+    assert_eq!(
+        engine.execute_request(&profile(), &request),
+        Err(CryptoError::AuthorizationFailed)
+    );
+    assert!(engine.list_capabilities().is_empty());
+}
+
+#[test]
+fn policy_key_limits_are_passed_to_the_key_manager() {
+    use ring::hmac;
+    use ucqcf_ciem::CIEM;
+    use ucqcf_ciem::entropy::EntropyAggregator;
+    use ucqcf_mock_hw::clock::MockAtomicClock;
+    use ucqcf_mock_hw::rng::{MockQRNG, MockTRNG};
+    use ucqcf_policy::RulePolicy;
+
+    /// Lends the test's CIEM to the engine, to inspect its keys afterwards.
+    struct LentCiem<'c, 'a>(&'c CIEM<'a>);
+
+    impl KeyManager for LentCiem<'_, '_> {
+        fn generate_key(
+            &self,
+            profile: &SecurityProfile,
+            algorithm: AlgorithmId,
+        ) -> Result<KeyHandle, CryptoError> {
+            KeyManager::generate_key(self.0, profile, algorithm)
+        }
+
+        fn delete_key(&self, key_handle: &KeyHandle) -> Result<(), CryptoError> {
+            self.0.delete_key(key_handle)
+        }
+
+        fn restrict_key_usage(
+            &self,
+            key_handle: &KeyHandle,
+            max_invocations: Option<u64>,
+            max_bytes: Option<u64>,
+        ) -> Result<(), CryptoError> {
+            self.0
+                .restrict_key_usage(key_handle, max_invocations, max_bytes)
+        }
+    }
+
+    let aggregator = EntropyAggregator::new(
+        Box::new(MockTRNG),
+        vec![Box::new(MockQRNG)],
+        hmac::Key::new(hmac::HMAC_SHA256, &[7; 32]),
+    );
+    let ciem = CIEM::new(aggregator, Box::new(MockAtomicClock)).unwrap();
+    let rules = r#"
+        permit "rationed" when key.origin == generated
+            with key_max_invocations = 100, key_max_bytes = 4096;
+    "#;
+    let request = RequestEnvelope::new(Operation::Encrypt, "alice")
+        .encode()
+        .unwrap();
+
+    let mut engine = CoreEngine::new(Box::new(RulePolicy::parse(rules).unwrap()));
+    engine
+        .register_provider(
+            descriptor("ciem", AssuranceLevel::Software),
+            Box::new(MockProvider::default()),
+            Box::new(LentCiem(&ciem)),
+        )
+        .unwrap();
+    engine.execute_request(&profile(), &request).unwrap();
+    let limits = ciem
+        .key_limits(&engine.list_capabilities()[0].key_handle)
+        .unwrap();
+    assert_eq!(
+        (limits.max_invocations, limits.max_bytes),
+        (100, Some(4096))
+    );
+
+    // A key manager that cannot apply the limits fails the request.
+    let mut engine = CoreEngine::new(Box::new(RulePolicy::parse(rules).unwrap()));
+    register(&mut engine, descriptor("mock", AssuranceLevel::Software));
+    assert_eq!(
+        engine.execute_request(&profile(), &request),
+        Err(CryptoError::NotSupported)
+    );
+    assert!(engine.list_capabilities().is_empty());
+}
+
+#[test]
+fn cryptoperiods_are_measured_on_the_key_managers_clock() {
+    // The engine's clock is far past the key's `expires_at`, but the key
+    // stays usable until the key manager's clock reaches it.
+    let engine_clock = TestClock::default();
+    engine_clock.advance(1_000_000);
+    let key_clock = TestClock::default();
+    key_clock.advance(1_000);
+    let engine = engine_with_clocks(true, engine_clock, key_clock.clone());
+    let profile = SecurityProfile {
+        key_cryptoperiod: Some(10),
+        ..profile()
+    };
+    let request = RequestEnvelope::new(Operation::Encrypt, "alice");
+    engine
+        .execute_request(&profile, &request.encode().unwrap())
+        .unwrap();
+    let key = engine.list_capabilities()[0].key_handle.clone();
+    assert_eq!(key.attributes().unwrap().expires_at, Some(1_010));
+    let labels = BTreeMap::from([("mission".to_string(), "alpha".to_string())]);
+    engine.set_key_labels(&key, labels).unwrap();
+    let token = engine.seal_key_handle(&key, "alice", 100).unwrap();
+    let encrypt = existing_key_request(token.clone());
+    let mut decrypt = RequestEnvelope::new(Operation::Decrypt, "alice");
+    decrypt.key = KeySelector::Existing(token);
+    let decrypt = decrypt.encode().unwrap();
+
+    key_clock.advance(9);
+    let capability = engine.execute_request(&profile, &encrypt).unwrap();
+    assert_eq!(
+        engine.execute_capability(&capability, "alice", b"data"),
+        Ok(b"data".to_vec())
+    );
+
+    key_clock.advance(1);
+    let capability = engine.execute_request(&profile, &encrypt).unwrap();
+    assert_eq!(
+        engine.execute_capability(&capability, "alice", b"data"),
+        Err(CryptoError::OutsideCryptoperiod)
+    );
+    let capability = engine.execute_request(&profile, &decrypt).unwrap();
+    assert_eq!(
+        engine.execute_capability(&capability, "alice", b"data"),
+        Ok(b"data".to_vec())
+    );
+}
+
+/// The providers a fresh-key request under `profile` would be routed to, in order.
+fn routed_providers(engine: &CoreEngine<'_>, profile: &SecurityProfile) -> Vec<String> {
+    let request = RequestEnvelope::new(Operation::Encrypt, "alice");
+    engine
+        .dry_run(profile, &request.encode().unwrap())
+        .unwrap()
+        .providers
+}
+
+#[test]
+fn the_most_assured_provider_is_chosen() {
+    let mut engine = engine(true);
+    register(&mut engine, descriptor("hsm", AssuranceLevel::Certified));
+    register(&mut engine, descriptor("ciem", AssuranceLevel::Hardware));
+    register(&mut engine, descriptor("soft", AssuranceLevel::Software));
+    assert_eq!(
+        routed_providers(&engine, &profile()),
+        ["hsm", "ciem", "mock", "soft"]
+    );
+    generate(&engine);
+    assert_eq!(engine.list_capabilities()[0].provider, "hsm");
+}
+
+#[test]
+fn quantum_resistant_profiles_skip_classical_providers() {
+    let mut engine = engine(true);
+    register(
+        &mut engine,
+        ProviderDescriptor {
+            quantum_resistant: true,
+            ..descriptor("pq", AssuranceLevel::Software)
+        },
+    );
+    let mut quantum_resistant = profile();
+    quantum_resistant.quantum_resistant = true;
+    assert_eq!(routed_providers(&engine, &quantum_resistant), ["pq"]);
+    assert_eq!(routed_providers(&engine, &profile()), ["mock", "pq"]);
+
+    let request = RequestEnvelope::new(Operation::Encrypt, "alice");
+    engine
+        .execute_request(&quantum_resistant, &request.encode().unwrap())
+        .unwrap();
+    assert_eq!(engine.list_capabilities()[0].provider, "pq");
+}
+
+#[test]
+fn providers_only_serve_their_domains() {
+    let mut engine = engine(true);
+    register(
+        &mut engine,
+        ProviderDescriptor {
+            domains: vec![Domain::Defense],
+            ..descriptor("defense-hsm", AssuranceLevel::Certified)
+        },
+    );
+    assert_eq!(routed_providers(&engine, &profile()), ["mock"]);
+    let defense = SecurityProfile::new(Domain::Defense);
+    assert_eq!(routed_providers(&engine, &defense), ["defense-hsm", "mock"]);
+}
+
+#[test]
+fn provider_names_are_unique() {
+    let mut engine = engine(true);
+    assert_eq!(
+        engine.register_provider(
+            descriptor("mock", AssuranceLevel::Certified),
+            Box::new(MockProvider::default()),
+            Box::new(UnavailableKeyManager),
+        ),
+        Err(CryptoError::InvalidState)
+    );
+    assert_eq!(
+        engine.providers(),
+        [descriptor("mock", AssuranceLevel::Software)]
+    );
+
+    let empty = CoreEngine::new(Box::new(MissionPolicy));
+    let request = RequestEnvelope::new(Operation::Encrypt, "alice");
+    assert_eq!(
+        empty.execute_request(&profile(), &request.encode().unwrap()),
+        Err(CryptoError::ProviderUnavailable)
+    );
+}
+
+#[test]
+fn keys_are_only_imported_into_providers_that_can_hold_them() {
+    let engine = engine(true);
+    let import = |provider: &str, algorithm| {
+        engine.import_key(provider, &profile(), algorithm, KeyFormat::Raw, &[7; 32])
+    };
+    assert_eq!(
+        import("hsm", AlgorithmId::Aes256Gcm),
+        Err(CryptoError::ProviderUnavailable)
+    );
+    assert_eq!(
+        import("mock", AlgorithmId::ChaCha20Poly1305),
+        Err(CryptoError::UnsupportedAlgorithm)
+    );
+    // The key manager does not implement imports.
+    assert_eq!(
+        import("mock", AlgorithmId::Aes256Gcm),
+        Err(CryptoError::NotSupported)
+    );
+    assert!(
+        !engine
+            .audit_log()
+            .iter()
+            .any(|record| matches!(record.event, AuditEvent::KeyLifecycle { .. }))
+    );
+    // Nor can it enumerate its keys, so there are none to list.
+    assert_eq!(engine.list_keys(), Ok(Vec::new()));
+}
+
+#[test]
+fn fresh_keys_use_the_most_preferred_suite_algorithm_a_provider_offers() {
+    let mut engine = engine(true);
+    // No provider offers Ed25519, the suite's first choice for signatures,
+    // and the signer's own first choice is its last in the suite.
+    register(
+        &mut engine,
+        ProviderDescriptor {
+            algorithms: vec![AlgorithmId::MlDsa87, AlgorithmId::MlDsa65],
+            ..descriptor("signer", AssuranceLevel::Software)
+        },
+    );
+    assert_eq!(
+        CipherSuite::candidates(&profile(), Operation::Sign, None),
+        [
+            AlgorithmId::Ed25519,
+            AlgorithmId::MlDsa65,
+            AlgorithmId::MlDsa87
+        ]
+    );
+    let request = RequestEnvelope::new(Operation::Sign, "alice")
+        .encode()
+        .unwrap();
+    let report = engine.dry_run(&profile(), &request).unwrap();
+    assert_eq!(report.context.algorithm, Some(AlgorithmId::MlDsa65));
+    engine.execute_request(&profile(), &request).unwrap();
+    let capability = &engine.list_capabilities()[0];
+    assert_eq!(
+        (capability.provider.as_str(), capability.algorithm),
+        ("signer", AlgorithmId::MlDsa65)
+    );
+}
+
+#[test]
+fn key_generation_fails_over_to_the_next_provider() {
+    let mut engine = engine(true);
+    engine
+        .register_provider(
+            descriptor("broken", AssuranceLevel::Certified),
+            Box::new(MockProvider::default()),
+            Box::new(UnavailableKeyManager),
+        )
+        .unwrap();
+    let (capability, _) = generate(&engine);
+    assert_eq!(engine.list_capabilities()[0].provider, "mock");
+    assert_eq!(
+        engine.execute_capability(&capability, "alice", b"data"),
+        Ok(b"data".to_vec())
+    );
+    assert!(engine.audit_log().iter().any(|record| record.event
+        == AuditEvent::ProviderFailover {
+            provider: "broken".to_string(),
+            reason: CryptoError::ProviderUnavailable.to_string(),
+        }));
+}
+
+#[test]
+fn dry_runs_report_key_tokens_that_do_not_open() {
+    let clock = TestClock::default();
+    let engine = engine_with_clock(true, clock.clone());
+    let (_, key) = generate(&engine);
+    let token = engine.seal_key_handle(&key, "alice", 10).unwrap();
+    assert_eq!(
+        engine.dry_run(&profile(), &existing_key_request(tamper(token.clone()))),
+        Err(CryptoError::AuthorizationFailed)
+    );
+    clock.advance(10);
+    assert_eq!(
+        engine.dry_run(&profile(), &existing_key_request(token)),
+        Err(CryptoError::CapabilityExpired)
+    );
+}
+
+#[test]
+fn dry_runs_trace_every_rule() {
+    use ucqcf_core::interfaces::TraceStep;
+    use ucqcf_policy::RulePolicy;
+
+    let rules = r#"
+        deny "lockdown" when env.threat_level == high with reason = "site in lockdown";
+        permit "fresh-keys" when key.origin == generated;
+        permit "mission-keys"
+            when principal == alice
+            and key.label.mission == alpha
+            with max_uses = 5;
+    "#;
+    let mut engine = CoreEngine::new(Box::new(RulePolicy::parse(rules).unwrap()));
+    register(&mut engine, descriptor("mock", AssuranceLevel::Software));
+    engine.set_environment_attribute("threat_level", "low");
+    let (_, key) = generate(&engine);
+    let labels = BTreeMap::from([("mission".to_string(), "alpha".to_string())]);
+    engine.set_key_labels(&key, labels).unwrap();
+    let request = existing_key_request(engine.seal_key_handle(&key, "alice", 100).unwrap());
+
+    let step =
+        |source: &str, applicable, allow, consulted: &[(&str, &str)], obligations| TraceStep {
+            depth: 0,
+            source: format!("rule \"{source}\""),
+            applicable,
+            allow,
+            consulted: consulted
+                .iter()
+                .map(|(name, value)| (name.to_string(), Some(value.to_string())))
+                .collect(),
+            obligations,
+            reason: (source == "lockdown").then(|| "site in lockdown".to_string()),
+        };
+    let limited = Obligations {
+        max_uses: Some(5),
+        ..Obligations::default()
+    };
+
+    let audited = engine.audit_log().len();
+    let report = engine.dry_run(&profile(), &request).unwrap();
+    assert_eq!(
+        report.evaluation.trace,
+        [
+            step(
+                "lockdown",
+                false,
+                false,
+                &[("env.threat_level", "low")],
+                Obligations::default()
+            ),
+            step(
+                "fresh-keys",
+                false,
+                true,
+                &[("key.origin", "existing")],
+                Obligations::default()
+            ),
+            step(
+                "mission-keys",
+                true,
+                true,
+                &[("principal", "alice"), ("key.label.mission", "alpha")],
+                limited.clone(),
+            ),
+        ]
+    );
+    assert!(report.evaluation.decision.allow);
+    assert_eq!(report.evaluation.decision.obligations, limited);
+    assert_eq!(report.providers, ["mock"]);
+    assert!(report.would_issue());
+
+    engine.set_environment_attribute("threat_level", "high");
+    let report = engine.dry_run(&profile(), &request).unwrap();
+    assert_eq!(
+        report.evaluation.trace[0],
+        step(
+            "lockdown",
+            true,
+            false,
+            &[("env.threat_level", "high")],
+            Obligations::default()
+        )
+    );
+    assert!(!report.evaluation.decision.allow);
+    assert_eq!(report.refusal.as_deref(), Some("site in lockdown"));
+    // Nothing was issued or audited by the dry runs.
+    assert_eq!(engine.list_capabilities().len(), 1);
+    assert_eq!(engine.audit_log().len(), audited);
+}
+
+#[test]
+fn dry_runs_report_refusals_after_the_policy() {
+    let mut engine = CoreEngine::new(Box::new(MissionPolicy));
+    let mut request = RequestEnvelope::new(Operation::Encrypt, "bound");
+    let report = engine
+        .dry_run(&profile(), &request.encode().unwrap())
+        .unwrap();
+    assert!(report.evaluation.decision.allow);
+    assert_eq!(
+        report.refusal.as_deref(),
+        Some("request does not carry the AAD required by policy")
+    );
+
+    request.aad = b"mission".to_vec();
+    let request = request.encode().unwrap();
+    let report = engine.dry_run(&profile(), &request).unwrap();
+    assert!(report.providers.is_empty());
+    assert_eq!(
+        report.refusal.as_deref(),
+        Some("no registered provider can serve the request")
+    );
+
+    register(&mut engine, descriptor("mock", AssuranceLevel::Software));
+    assert!(engine.dry_run(&profile(), &request).unwrap().would_issue());
+    assert_eq!(
+        engine.dry_run(&profile(), b"encrypt"),
+        Err(CryptoError::MalformedInput)
+    );
+}
+
+/// Returns the handle of the live capability bound to `key`, of which there
+/// must be exactly one.
+fn capability_for(engine: &CoreEngine<'_>, key: &KeyHandle) -> CapabilityHandle {
+    let listed: Vec<_> = engine
+        .list_capabilities()
+        .into_iter()
+        .filter(|info| &info.key_handle == key)
+        .collect();
+    assert_eq!(listed.len(), 1);
+    listed[0].handle.clone()
+}
+
+#[test]
+fn unknown_capabilities_are_refused() {
+    let engine = engine(true);
+    let unknown = CapabilityHandle::new();
+    assert_eq!(
+        engine.seal_capability_handle(&unknown, "alice"),
+        Err(CryptoError::AuthorizationFailed)
+    );
+    assert!(!engine.revoke_capability(&unknown));
+}
+
+#[test]
+fn revoked_capabilities_cannot_be_used() {
+    let engine = engine(true);
+    let (capability, key) = generate(&engine);
+    let handle = capability_for(&engine, &key);
+    assert!(engine.revoke_capability(&handle));
+    assert!(!engine.revoke_capability(&handle));
+    assert!(engine.list_capabilities().is_empty());
+    assert_eq!(
+        engine.execute_capability(&capability, "alice", b"data"),
+        Err(CryptoError::AuthorizationFailed)
+    );
+    assert!(engine.audit_log().iter().any(|record| record.event
+        == AuditEvent::CapabilityRevoked {
+            capability: handle.id()
+        }));
+}
+
+#[test]
+fn revoking_by_key_spares_other_keys() {
+    let engine = engine(true);
+    let (first, key) = generate(&engine);
+    let (other, other_key) = generate(&engine);
+    let labels = BTreeMap::from([("mission".to_string(), "alpha".to_string())]);
+    engine.set_key_labels(&key, labels).unwrap();
+    let request = existing_key_request(engine.seal_key_handle(&key, "alice", 100).unwrap());
+    let second = engine.execute_request(&profile(), &request).unwrap();
+
+    assert_eq!(engine.revoke_capabilities_for_key(&key), 2);
+    for capability in [first, second] {
+        assert_eq!(
+            engine.execute_capability(&capability, "alice", b"data"),
+            Err(CryptoError::AuthorizationFailed)
+        );
+    }
+    capability_for(&engine, &other_key);
+    assert_eq!(
+        engine.execute_capability(&other, "alice", b"data"),
+        Ok(b"data".to_vec())
+    );
+    assert_eq!(engine.revoke_capabilities_for_key(&key), 0);
+}
+
+#[test]
+fn listings_reflect_expiry_and_remaining_uses() {
+    let clock = TestClock::default();
+    let engine = engine_with_clock(true, clock.clone());
+    let mut request = RequestEnvelope::new(Operation::Encrypt, "limited");
+    let limited = engine
+        .execute_request(&profile(), &request.encode().unwrap())
+        .unwrap();
+    request.caller = "alice".to_string();
+    request.lifetime = Some(10);
+    engine
+        .execute_request(&profile(), &request.encode().unwrap())
+        .unwrap();
+    let listed = |caller: &str| {
+        engine
+            .list_capabilities()
+            .into_iter()
+            .find(|info| info.caller == caller)
+    };
+    assert_eq!(listed("limited").unwrap().remaining_uses, Some(2));
+    assert_eq!(listed("alice").unwrap().remaining_uses, None);
+    assert_eq!(listed("alice").unwrap().expires_at, 10);
+
+    engine
+        .execute_capability(&limited, "limited", b"data")
+        .unwrap();
+    assert_eq!(listed("limited").unwrap().remaining_uses, Some(1));
+    engine
+        .execute_capability(&limited, "limited", b"data")
+        .unwrap();
+    assert_eq!(listed("limited"), None);
+
+    clock.advance(9);
+    assert!(listed("alice").is_some());
+    clock.advance(1);
+    assert_eq!(listed("alice"), None);
+    assert!(engine.list_capabilities().is_empty());
+}
+
+#[test]
+fn capabilities_carry_the_required_aad() {
+    let engine = engine(true);
+    let mut request = RequestEnvelope::new(Operation::Encrypt, "bound");
+    for aad in [&b""[..], b"other"] {
+        request.aad = aad.to_vec();
+        assert_eq!(
+            engine.execute_request(&profile(), &request.encode().unwrap()),
+            Err(CryptoError::AuthorizationFailed)
+        );
+    }
+    assert!(engine.list_capabilities().is_empty());
+
+    request.aad = b"mission".to_vec();
+    let capability = engine
+        .execute_request(&profile(), &request.encode().unwrap())
+        .unwrap();
+    assert_eq!(engine.list_capabilities()[0].aad, b"mission");
+    assert_eq!(
+        engine.execute_capability(&capability, "bound", b"data"),
+        Ok(b"missiondata".to_vec())
+    );
+}
+
+#[test]
+fn expiry_obligations_expire_capabilities_on_the_secure_clock() {
+    let clock = TestClock::default();
+    clock.advance(1_000);
+    let engine = engine_with_clock(true, clock.clone());
+    let request = RequestEnvelope::new(Operation::Encrypt, "brief");
+    let capability = engine
+        .execute_request(&profile(), &request.encode().unwrap())
+        .unwrap();
+    assert_eq!(engine.list_capabilities()[0].expires_at, 1_005);
+
+    clock.advance(4);
+    assert!(
+        engine
+            .execute_capability(&capability, "brief", b"data")
+            .is_ok()
+    );
+    clock.advance(1);
+    assert_eq!(
+        engine.execute_capability(&capability, "brief", b"data"),
+        Err(CryptoError::CapabilityExpired)
+    );
+    assert!(engine.list_capabilities().is_empty());
+}
@@ -0,0 +1,165 @@
+// ucqcf_engine/src/token.rs
+
+//! Sealed handles: encodings of `KeyHandle`s and `CapabilityHandle`s that can
+//! cross process boundaries. Each token names the engine that issued it, the
+//! tenant it was issued to and the epoch it expires at, and is authenticated
+//! with HMAC-SHA256 under a key only the engine holds.
+//!
+//! Layout (integers big-endian):
+//!
+//! ```text
+//! version (1) | kind (1) | engine id (16) | handle id (16) | expires_at (8)
+//!   | tenant length (2) | tenant (UTF-8) | HMAC-SHA256 tag (32)
+//! ```
+
+use ring::hmac;
+use ring::rand::SystemRandom;
+use ucqcf_core::CryptoError;
+use uuid::Uuid;
+
+/// The token format version produced and accepted by this release.
+const TOKEN_VERSION: u8 = 1;
+
+/// The length of the authentication tag (HMAC-SHA256).
+const TAG_LEN: usize = 32;
+
+/// The minimum length of a persistent sealing secret.
+const MIN_SECRET_LEN: usize = 32;
+
+/// The length of the fixed-size fields preceding the tenant.
+const HEADER_LEN: usize = 1 + 1 + 16 + 16 + 8 + 2;
+
+/// What a sealed token refers to. Part of the authenticated data, so a key
+/// token can never be presented as a capability token or vice versa.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum HandleKind {
+    Key = 1,
+    Capability = 2,
+}
+
+/// Why a token was rejected. Kept distinct for the audit log; callers only
+/// see the corresponding `CryptoError`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TokenFault {
+    Malformed,
+    Forged,
+    WrongEngine,
+    WrongKind,
+    WrongTenant,
+    Expired,
+}
+
+impl TokenFault {
+    pub(crate) fn describe(&self) -> &'static str {
+        match self {
+            TokenFault::Malformed => "sealed handle is malformed",
+            TokenFault::Forged => "sealed handle failed authentication",
+            TokenFault::WrongEngine => "sealed handle was issued by another engine",
+            TokenFault::WrongKind => "sealed handle is of the wrong kind",
+            TokenFault::WrongTenant => "sealed handle was issued to another tenant",
+            TokenFault::Expired => "sealed handle has expired",
+        }
+    }
+
+    pub(crate) fn error(&self) -> CryptoError {
+        match self {
+            TokenFault::Malformed => CryptoError::MalformedInput,
+            TokenFault::Expired => CryptoError::CapabilityExpired,
+            _ => CryptoError::AuthorizationFailed,
+        }
+    }
+}
+
+/// Seals and opens handle tokens for one engine.
+pub(crate) struct HandleSealer {
+    engine_id: Uuid,
+    key: hmac::Key,
+}
+
+impl HandleSealer {
+    /// Creates a sealer with a fresh engine id and a random MAC key, so tokens
+    /// are only valid for the lifetime of this engine instance.
+    pub(crate) fn new() -> Self {
+        let key = hmac::Key::generate(hmac::HMAC_SHA256, &SystemRandom::new())
+            .expect("the system random number generator is unavailable");
+        Self {
+            engine_id: Uuid::new_v4(),
+            key,
+        }
+    }
+
+    /// Creates a sealer from a persistent identity, so tokens survive restarts
+    /// or can be shared between engine instances holding the same secret.
+    /// Secrets shorter than 32 bytes are rejected with `MalformedInput`.
+    pub(crate) fn from_secret(engine_id: Uuid, secret: &[u8]) -> Result<Self, CryptoError> {
+        if secret.len() < MIN_SECRET_LEN {
+            return Err(CryptoError::MalformedInput);
+        }
+        Ok(Self {
+            engine_id,
+            key: hmac::Key::new(hmac::HMAC_SHA256, secret),
+        })
+    }
+
+    pub(crate) fn engine_id(&self) -> Uuid {
+        self.engine_id
+    }
+
+    /// Seals a handle id for `tenant`, valid until epoch `expires_at`.
+    pub(crate) fn seal(
+        &self,
+        kind: HandleKind,
+        id: Uuid,
+        tenant: &str,
+        expires_at: u64,
+    ) -> Result<Vec<u8>, CryptoError> {
+        let tenant_len = u16::try_from(tenant.len()).map_err(|_| CryptoError::MalformedInput)?;
+        let mut token = Vec::with_capacity(HEADER_LEN + tenant.len() + TAG_LEN);
+        token.push(TOKEN_VERSION);
+        token.push(kind as u8);
+        token.extend_from_slice(self.engine_id.as_bytes());
+        token.extend_from_slice(id.as_bytes());
+        token.extend_from_slice(&expires_at.to_be_bytes());
+        token.extend_from_slice(&tenant_len.to_be_bytes());
+        token.extend_from_slice(tenant.as_bytes());
+        let tag = hmac::sign(&self.key, &token);
+        token.extend_from_slice(tag.as_ref());
+        Ok(token)
+    }
+
+    /// Authenticates a token and returns the handle id it carries. The tag is
+    /// checked before any field is trusted.
+    pub(crate) fn open(
+        &self,
+        kind: HandleKind,
+        token: &[u8],
+        tenant: &str,
+        now: u64,
+    ) -> Result<Uuid, TokenFault> {
+        if token.len() < HEADER_LEN + TAG_LEN {
+            return Err(TokenFault::Malformed);
+        }
+        let (body, tag) = token.split_at(token.len() - TAG_LEN);
+        let tenant_len = u16::from_be_bytes([body[HEADER_LEN - 2], body[HEADER_LEN - 1]]) as usize;
+        if body[0] != TOKEN_VERSION || body.len() != HEADER_LEN + tenant_len {
+            return Err(TokenFault::Malformed);
+        }
+        hmac::verify(&self.key, body, tag).map_err(|_| TokenFault::Forged)?;
+
+        if Uuid::from_slice(&body[2..18]).ok() != Some(self.engine_id) {
+            return Err(TokenFault::WrongEngine);
+        }
+        if body[1] != kind as u8 {
+            return Err(TokenFault::WrongKind);
+        }
+        if &body[HEADER_LEN..] != tenant.as_bytes() {
+            return Err(TokenFault::WrongTenant);
+        }
+        let mut expires_at = [0; 8];
+        expires_at.copy_from_slice(&body[34..42]);
+        if now >= u64::from_be_bytes(expires_at) {
+            return Err(TokenFault::Expired);
+        }
+        Uuid::from_slice(&body[18..34]).map_err(|_| TokenFault::Malformed)
+    }
+}
@@ -1,14 +1,6 @@
 // ucqcf_mock_hw/src/clock.rs
 use std::time::{SystemTime, UNIX_EPOCH};
-
-/// A trait for any hardware source of time.
-pub trait ClockSource {
-    /// Returns a timestamp from the source. The unit is arbitrary for simulation
-    /// (e.g., nanoseconds since an epoch).
-    fn now(&self) -> u64;
-    /// Returns the name of the source.
-    fn name(&self) -> &'static str;
-}
+pub use ucqcf_core::clock::{ClockClass, ClockSource, NANOS_PER_EPOCH};
 
 /// A mock classical oscillator, which might have jitter or lower precision.
 pub struct MockClassicalOscillator;
@@ -41,6 +33,9 @@ impl ClockSource for MockAtomicClock {
     fn name(&self) -> &'static str {
         "MockAtomicClock"
     }
+    fn class(&self) -> ClockClass {
+        ClockClass::Atomic
+    }
 }
 
 /// A mock quantum clock. In a real system, this might measure quantum phenomena
@@ -59,4 +54,7 @@ impl ClockSource for MockQuantumClock {
     fn name(&self) -> &'static str {
         "MockQuantumClock"
     }
+    fn class(&self) -> ClockClass {
+        ClockClass::Quantum
+    }
 }